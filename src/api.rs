@@ -1,10 +1,12 @@
 use crate::app_context::AppContext;
-use crate::query::data::{AlbumData, PlaylistData, TrackData};
+use crate::play_log::Play;
+use crate::query::data::{AlbumData, ArtistData, PlaylistData, TimeRange, TrackData};
 use crate::query::deserialise::result_parser;
 use crate::utils::logger::fatal;
 use crate::utils::url::build_url;
 use regex::Regex;
-use reqwest::Response;
+use reqwest::{Method, Response};
+use serde_json::json;
 use std::cmp::PartialEq;
 use std::sync::mpsc::{Sender, channel};
 use std::thread;
@@ -21,26 +23,42 @@ pub struct APIQuery {
 #[derive(Debug, PartialEq)]
 pub enum QueryType {
     UserPlaylist,
+    Playlist,
     UserPlaylistTracks,
     UserFollowing,
     UserSavedAlbums,
+    UserSavedTracks,
+    UserTopTracks,
+    UserTopArtists,
+    UserRecentlyPlayed,
+    Artists,
     AlbumTracks,
+    CurrentUser,
+    UserCreatePlaylist,
 }
 
 impl QueryType {
     fn make_endpoint(&self, start: &str, id: Option<String>) -> String {
         let mut url = match self {
             QueryType::UserPlaylist => start.to_string() + "/me/playlists",
+            QueryType::Playlist => start.to_string() + "/playlists/{id}",
             QueryType::UserPlaylistTracks => start.to_string() + "/playlists/{id}/tracks",
             QueryType::UserFollowing => start.to_string() + "/me/following",
             QueryType::UserSavedAlbums => start.to_string() + "/me/albums",
+            QueryType::UserSavedTracks => start.to_string() + "/me/tracks",
+            QueryType::UserTopTracks => start.to_string() + "/me/top/tracks",
+            QueryType::UserTopArtists => start.to_string() + "/me/top/artists",
+            QueryType::UserRecentlyPlayed => start.to_string() + "/me/player/recently-played",
+            QueryType::Artists => start.to_string() + "/artists",
             QueryType::AlbumTracks => start.to_string() + "/albums/{id}/tracks",
+            QueryType::CurrentUser => start.to_string() + "/me",
+            QueryType::UserCreatePlaylist => start.to_string() + "/users/{id}/playlists",
         };
 
         if id.is_some() {
             // verify the url can accept an id
             let id_url =
-                Regex::new(r"[/\w]+\/\{id\}\/[/\w]+").expect("id_url Regex failed to init.");
+                Regex::new(r"[/\w]+\/\{id\}(\/[/\w]+)?$").expect("id_url Regex failed to init.");
 
             if id_url.is_match(url.as_str()) {
                 url = url.replace("{id}", id.unwrap().as_str());
@@ -53,13 +71,15 @@ impl QueryType {
     }
 }
 
-static API_ENDPOINT: &str = "https://api.spotify.com/v1";
+pub static API_ENDPOINT: &str = "https://api.spotify.com/v1";
 static MAX_RESPONSE_ITEMS: usize = 50;
+/// The most tracks that can be added to or removed from a playlist in one request
+static MAX_MODIFY_ITEMS: usize = 100;
 
 impl APIQuery {
     /// Get all of a users playlists
     pub fn get_playlists(cx: &AppContext) -> Result<Vec<PlaylistData>, String> {
-        let url = QueryType::UserPlaylist.make_endpoint(API_ENDPOINT, None);
+        let url = QueryType::UserPlaylist.make_endpoint(&cx.api_endpoint, None);
 
         let mut playlists: Vec<PlaylistData> = Vec::new();
 
@@ -107,7 +127,7 @@ impl APIQuery {
     }
 
     pub fn get_saved_albums(cx: &AppContext) -> Result<Vec<AlbumData>, String> {
-        let url = QueryType::UserSavedAlbums.make_endpoint(API_ENDPOINT, None);
+        let url = QueryType::UserSavedAlbums.make_endpoint(&cx.api_endpoint, None);
 
         let mut albums: Vec<AlbumData> = Vec::new();
 
@@ -149,12 +169,53 @@ impl APIQuery {
         Ok(albums)
     }
 
+    /// Get all the artists the user follows, which are paged with a cursor rather than an offset
+    pub fn get_followed_artists(cx: &AppContext) -> Result<Vec<ArtistData>, String> {
+        let url = QueryType::UserFollowing.make_endpoint(&cx.api_endpoint, None);
+
+        let mut artists: Vec<ArtistData> = Vec::new();
+        let mut after: Option<String> = None;
+
+        let mut count: usize = 0;
+
+        loop {
+            if count == 49 {
+                break;
+            }
+
+            let mut params: Vec<(&str, String)> = vec![
+                ("type", "artist".to_string()),
+                ("limit", MAX_RESPONSE_ITEMS.to_string()),
+            ];
+            if let Some(res) = after {
+                params.push(("after", res));
+            }
+
+            let raw_data =
+                Self::send_request(cx, Method::GET, build_url(url.clone(), params), None)?;
+
+            let (mut temp_artists, cursor) = result_parser::parse_followed_artists(raw_data)?;
+
+            artists.append(&mut temp_artists);
+
+            // the cursor is null on the last page
+            match cursor {
+                Some(res) => after = Some(res),
+                None => break,
+            }
+
+            count += 1;
+        }
+
+        Ok(artists)
+    }
+
     pub fn get_playlist_tracks(
         cx: &AppContext,
         playlist_id: String,
     ) -> Result<Vec<TrackData>, String> {
-        let url =
-            QueryType::UserPlaylistTracks.make_endpoint(API_ENDPOINT, Some(playlist_id.clone()));
+        let url = QueryType::UserPlaylistTracks
+            .make_endpoint(&cx.api_endpoint, Some(playlist_id.clone()));
 
         let mut tracks: Vec<TrackData> = Vec::new();
         let mut count: usize = 0;
@@ -191,11 +252,107 @@ impl APIQuery {
         Ok(tracks)
     }
 
+    /// Get the user's Liked Songs, which come in the same form as playlist tracks
+    pub fn get_liked_tracks(cx: &AppContext) -> Result<Vec<TrackData>, String> {
+        let url = QueryType::UserSavedTracks.make_endpoint(&cx.api_endpoint, None);
+
+        let mut tracks: Vec<TrackData> = Vec::new();
+        let mut count: usize = 0;
+
+        loop {
+            if count == 399 {
+                break;
+            } // Liked Songs can be much longer than a playlist, allows for 400*MAX_RESPONSE_ITEMS to be fetched
+
+            let query = APIQuery {
+                url: url.clone(),
+                limit: MAX_RESPONSE_ITEMS,
+                offset: MAX_RESPONSE_ITEMS * count,
+                fields: None,
+            };
+
+            let raw_data = query.send(cx)?;
+
+            let mut temp_tracks = result_parser::parse_tracks(raw_data, &"liked".to_string())?;
+
+            let loaded_tracks = temp_tracks.len();
+
+            tracks.append(&mut temp_tracks);
+
+            if loaded_tracks < MAX_RESPONSE_ITEMS {
+                break;
+            }
+
+            count += 1;
+        }
+
+        Ok(tracks)
+    }
+
+    /// Get the user's most recent plays, newest first, the API only keeps the last 50
+    pub fn get_recently_played(cx: &AppContext) -> Result<Vec<Play>, String> {
+        let url = QueryType::UserRecentlyPlayed.make_endpoint(&cx.api_endpoint, None);
+
+        let params: Vec<(&str, String)> = vec![("limit", MAX_RESPONSE_ITEMS.to_string())];
+
+        let raw_data = Self::send_request(cx, Method::GET, build_url(url, params), None)?;
+
+        result_parser::parse_recently_played(raw_data)
+    }
+
+    /// Get the full details of the artists, MAX_RESPONSE_ITEMS at a time
+    pub fn get_artists(cx: &AppContext, ids: &[String]) -> Result<Vec<ArtistData>, String> {
+        let url = QueryType::Artists.make_endpoint(&cx.api_endpoint, None);
+
+        let mut artists: Vec<ArtistData> = Vec::new();
+
+        for chunk in ids.chunks(MAX_RESPONSE_ITEMS) {
+            let params: Vec<(&str, String)> = vec![("ids", chunk.join(","))];
+
+            let raw_data =
+                Self::send_request(cx, Method::GET, build_url(url.clone(), params), None)?;
+
+            artists.append(&mut result_parser::parse_artists(raw_data)?);
+        }
+
+        Ok(artists)
+    }
+
+    /// Get the user's top tracks over the time range, most listened to first
+    pub fn get_top_tracks(cx: &AppContext, range: &TimeRange) -> Result<Vec<TrackData>, String> {
+        let url = QueryType::UserTopTracks.make_endpoint(&cx.api_endpoint, None);
+
+        // the top tracks are a single page of MAX_RESPONSE_ITEMS, the most a request can return
+        let params: Vec<(&str, String)> = vec![
+            ("time_range", range.api_value().to_string()),
+            ("limit", MAX_RESPONSE_ITEMS.to_string()),
+        ];
+
+        let raw_data = Self::send_request(cx, Method::GET, build_url(url, params), None)?;
+
+        result_parser::parse_top_tracks(raw_data)
+    }
+
+    /// Get the user's top artists over the time range, most listened to first
+    pub fn get_top_artists(cx: &AppContext, range: &TimeRange) -> Result<Vec<ArtistData>, String> {
+        let url = QueryType::UserTopArtists.make_endpoint(&cx.api_endpoint, None);
+
+        // the top artists are a single page of MAX_RESPONSE_ITEMS, the most a request can return
+        let params: Vec<(&str, String)> = vec![
+            ("time_range", range.api_value().to_string()),
+            ("limit", MAX_RESPONSE_ITEMS.to_string()),
+        ];
+
+        let raw_data = Self::send_request(cx, Method::GET, build_url(url, params), None)?;
+
+        result_parser::parse_top_artists(raw_data)
+    }
+
     pub fn get_album_tracks(
         cx: &AppContext,
         playlist_id: String,
     ) -> Result<Vec<TrackData>, String> {
-        let url = QueryType::AlbumTracks.make_endpoint(API_ENDPOINT, Some(playlist_id.clone()));
+        let url = QueryType::AlbumTracks.make_endpoint(&cx.api_endpoint, Some(playlist_id.clone()));
 
         let mut tracks: Vec<TrackData> = Vec::new();
         let mut count: usize = 0;
@@ -232,17 +389,212 @@ impl APIQuery {
         Ok(tracks)
     }
 
+    /// Get the id of the logged in user
+    pub fn get_user_id(cx: &AppContext) -> Result<String, String> {
+        let url = QueryType::CurrentUser.make_endpoint(&cx.api_endpoint, None);
+
+        let raw_data = Self::send_request(cx, Method::GET, url, None)?;
+
+        let val: serde_json::Value =
+            serde_json::from_str(raw_data.as_str()).map_err(|x| x.to_string())?;
+
+        match &val["id"] {
+            serde_json::Value::String(res) => Ok(res.clone()),
+            _ => Err("Value 'id' in user response data is an unexpected type.".to_string()),
+        }
+    }
+
+    /// Create an empty private playlist owned by the logged in user
+    pub fn create_playlist(cx: &AppContext, name: &String) -> Result<PlaylistData, String> {
+        let user_id = Self::get_user_id(cx)?;
+        let url = QueryType::UserCreatePlaylist.make_endpoint(&cx.api_endpoint, Some(user_id));
+
+        let body = json!({ "name": name, "public": false }).to_string();
+
+        let raw_data = Self::send_request(cx, Method::POST, url, Some(body))?;
+
+        let val: serde_json::Value =
+            serde_json::from_str(raw_data.as_str()).map_err(|x| x.to_string())?;
+
+        let id = match &val["id"] {
+            serde_json::Value::String(res) => res.clone(),
+            _ => {
+                return Err(
+                    "Value 'id' in created playlist response data is an unexpected type."
+                        .to_string(),
+                );
+            }
+        };
+
+        Ok(PlaylistData {
+            tracks_api: QueryType::UserPlaylistTracks
+                .make_endpoint(&cx.api_endpoint, Some(id.clone())),
+            id,
+            name: name.clone(),
+            tracks: Vec::new(),
+            track_count: 0,
+            snapshot_id: String::new(),
+        })
+    }
+
+    /// Add tracks to the end of a playlist, in batches of MAX_MODIFY_ITEMS
+    pub fn add_playlist_tracks(
+        cx: &AppContext,
+        playlist_id: String,
+        track_ids: &[String],
+    ) -> Result<(), String> {
+        let url = QueryType::UserPlaylistTracks.make_endpoint(&cx.api_endpoint, Some(playlist_id));
+
+        for batch in track_ids.chunks(MAX_MODIFY_ITEMS) {
+            let uris: Vec<String> = batch
+                .iter()
+                .map(|x| format!("spotify:track:{}", x))
+                .collect();
+
+            let body = json!({ "uris": uris }).to_string();
+
+            Self::send_request(cx, Method::POST, url.clone(), Some(body))?;
+        }
+
+        Ok(())
+    }
+
+    /// The current version of a playlist, changes every time the playlist is edited
+    pub fn get_playlist_snapshot_id(
+        cx: &AppContext,
+        playlist_id: String,
+    ) -> Result<String, String> {
+        let url = QueryType::Playlist.make_endpoint(&cx.api_endpoint, Some(playlist_id));
+
+        let raw_data = Self::send_request(cx, Method::GET, url + "?fields=snapshot_id", None)?;
+
+        Self::parse_snapshot_id(raw_data)
+    }
+
+    /// Move the track at range_start to before the track at insert_before, returns the playlist's new snapshot id
+    pub fn move_playlist_track(
+        cx: &AppContext,
+        playlist_id: String,
+        range_start: usize,
+        insert_before: usize,
+        snapshot_id: String,
+    ) -> Result<String, String> {
+        let url = QueryType::UserPlaylistTracks.make_endpoint(&cx.api_endpoint, Some(playlist_id));
+
+        let body = json!({
+            "range_start": range_start,
+            "insert_before": insert_before,
+            "range_length": 1,
+            "snapshot_id": snapshot_id,
+        })
+        .to_string();
+
+        let raw_data = Self::send_request(cx, Method::PUT, url, Some(body))?;
+
+        Self::parse_snapshot_id(raw_data)
+    }
+
+    fn parse_snapshot_id(raw_data: String) -> Result<String, String> {
+        let val: serde_json::Value =
+            serde_json::from_str(raw_data.as_str()).map_err(|x| x.to_string())?;
+
+        match &val["snapshot_id"] {
+            serde_json::Value::String(res) => Ok(res.clone()),
+            _ => Err("Value 'snapshot_id' in response data is an unexpected type.".to_string()),
+        }
+    }
+
+    /// Add tracks to a playlist starting at the position, at most MAX_MODIFY_ITEMS at once
+    pub fn insert_playlist_tracks(
+        cx: &AppContext,
+        playlist_id: String,
+        track_ids: &[String],
+        position: usize,
+    ) -> Result<(), String> {
+        let url = QueryType::UserPlaylistTracks.make_endpoint(&cx.api_endpoint, Some(playlist_id));
+
+        for (i, batch) in track_ids.chunks(MAX_MODIFY_ITEMS).enumerate() {
+            let uris: Vec<String> = batch
+                .iter()
+                .map(|x| format!("spotify:track:{}", x))
+                .collect();
+
+            let body =
+                json!({ "uris": uris, "position": position + i * MAX_MODIFY_ITEMS }).to_string();
+
+            Self::send_request(cx, Method::POST, url.clone(), Some(body))?;
+        }
+
+        Ok(())
+    }
+
+    /// Replace every track in a playlist, the first batch replaces the tracks and the rest are added after it
+    pub fn replace_playlist_tracks(
+        cx: &AppContext,
+        playlist_id: String,
+        track_ids: &[String],
+    ) -> Result<(), String> {
+        let url = QueryType::UserPlaylistTracks
+            .make_endpoint(&cx.api_endpoint, Some(playlist_id.clone()));
+
+        let split = track_ids.len().min(MAX_MODIFY_ITEMS);
+        let uris: Vec<String> = track_ids[..split]
+            .iter()
+            .map(|x| format!("spotify:track:{}", x))
+            .collect();
+
+        let body = json!({ "uris": uris }).to_string();
+
+        Self::send_request(cx, Method::PUT, url, Some(body))?;
+
+        Self::add_playlist_tracks(cx, playlist_id, &track_ids[split..])
+    }
+
+    /// Remove every occurrence of the tracks from a playlist, in batches of MAX_MODIFY_ITEMS
+    pub fn remove_playlist_tracks(
+        cx: &AppContext,
+        playlist_id: String,
+        track_ids: &[String],
+    ) -> Result<(), String> {
+        let url = QueryType::UserPlaylistTracks.make_endpoint(&cx.api_endpoint, Some(playlist_id));
+
+        for batch in track_ids.chunks(MAX_MODIFY_ITEMS) {
+            let tracks: Vec<serde_json::Value> = batch
+                .iter()
+                .map(|x| json!({ "uri": format!("spotify:track:{}", x) }))
+                .collect();
+
+            let body = json!({ "tracks": tracks }).to_string();
+
+            Self::send_request(cx, Method::DELETE, url.clone(), Some(body))?;
+        }
+
+        Ok(())
+    }
+
     /// Spawns a thread to send the API request async, returns data using a channel
-    fn send_async(url: String, tx: Sender<Result<String, String>>, token: String) {
+    fn send_async(
+        method: Method,
+        url: String,
+        body: Option<String>,
+        tx: Sender<Result<String, String>>,
+        token: String,
+    ) {
         thread::spawn(move || {
             let rt = Runtime::new().expect("Could not init tokio runtime");
             rt.block_on(async move {
                 let client = reqwest::Client::new();
-                let resp_result = client
-                    .get(url)
-                    .header("Authorization", format!("Bearer {}", token))
-                    .send()
-                    .await;
+                let mut request = client
+                    .request(method, url)
+                    .header("Authorization", format!("Bearer {}", token));
+
+                if let Some(body) = body {
+                    request = request
+                        .header("Content-Type", "application/json")
+                        .body(body);
+                }
+
+                let resp_result = request.send().await;
 
                 let resp: Response = match resp_result {
                     Ok(res) => res,
@@ -253,7 +605,7 @@ impl APIQuery {
                     }
                 };
 
-                if resp.status() != 200 {
+                if !resp.status().is_success() {
                     tx.send(Err(format!("API query failed with code {}", resp.status())))
                         .expect("Failed to send success response down request channel. (1)");
                     return;
@@ -276,8 +628,22 @@ impl APIQuery {
         });
     }
 
+    /// Nothing is sent when running offline
+    fn check_online(cx: &AppContext) -> Result<(), String> {
+        if cx.offline {
+            return Err(
+                "This needs the Spotify API, which isn't used when running with --offline."
+                    .to_string(),
+            );
+        }
+
+        Ok(())
+    }
+
     /// Send the given request
     fn send(self, cx: &AppContext) -> Result<String, String> {
+        Self::check_online(cx)?;
+
         let mut params: Vec<(&str, String)> = Vec::new();
         // build the param list
         params.push(("limit", self.limit.to_string()));
@@ -292,10 +658,25 @@ impl APIQuery {
 
         let (tx, rx) = channel::<Result<String, String>>();
         // println!("{:?}", final_url);
-        Self::send_async(final_url, tx, cx.token.clone());
+        Self::send_async(Method::GET, final_url, None, tx, cx.token.clone());
 
         let res = rx.recv().expect("API request thread stopped unexpectedly.");
 
         res
     }
+
+    /// Send a request without paging parameters, e.g. to change a playlist
+    fn send_request(
+        cx: &AppContext,
+        method: Method,
+        url: String,
+        body: Option<String>,
+    ) -> Result<String, String> {
+        Self::check_online(cx)?;
+
+        let (tx, rx) = channel::<Result<String, String>>();
+        Self::send_async(method, url, body, tx, cx.token.clone());
+
+        rx.recv().expect("API request thread stopped unexpectedly.")
+    }
 }
@@ -1,7 +1,10 @@
+use crate::api::API_ENDPOINT;
 use crate::auth::code::AuthFileContent;
+use crate::config::args::OutputFormat;
 use crate::config::user_config::UserConfig;
 use crate::query::data::Data;
 use crate::utils::file::{File, read_file};
+use std::path::PathBuf;
 
 #[derive(Clone)]
 pub struct AppContext {
@@ -12,13 +15,30 @@ pub struct AppContext {
     pub data: Data,
     pub user_config: UserConfig,
     pub save_file: Option<String>,
+    /// Show the changes statements would make to playlists without making them
+    pub dry_run: bool,
+    /// False when running queries from -e or stdin, nothing can be asked of the user
+    pub interactive: bool,
+    /// Make changes over the confirm threshold without asking
+    pub assume_yes: bool,
+    pub output_format: OutputFormat,
+    /// Start CSV and TSV output with a line of column names
+    pub output_header: bool,
+    /// Start saved CSV and TSV files with the query and the time it was run
+    pub output_metadata: bool,
+    /// Data comes from the imported library and the API is never used
+    pub offline: bool,
+    /// Where requests are sent, the Spotify Web API unless it is pointed at e.g. a mock server
+    pub api_endpoint: String,
+    /// Where the login, config, cache and imported data are kept, the OS's config folder when None
+    pub config_dir: Option<PathBuf>,
 }
 
 impl AppContext {
     pub fn load() -> Result<Self, String> {
         let mut cx = Self::default();
 
-        let auth_file_contents = match read_file(File::Auth) {
+        let auth_file_contents = match read_file(&cx, File::Auth) {
             Ok(res) => res,
             Err(_) => return Ok(Self::default()),
         };
@@ -29,7 +49,7 @@ impl AppContext {
         cx.token = auth_data.token;
         cx.refresh_token = auth_data.refresh_token;
         cx.expires_after = auth_data.expires_after;
-        cx.user_config = UserConfig::load()?;
+        cx.user_config = UserConfig::load(&cx)?;
 
         Ok(cx)
     }
@@ -45,6 +65,15 @@ impl Default for AppContext {
             data: Default::default(),
             user_config: UserConfig::default(),
             save_file: None,
+            dry_run: false,
+            interactive: true,
+            assume_yes: false,
+            output_format: OutputFormat::Table,
+            output_header: true,
+            output_metadata: false,
+            offline: false,
+            api_endpoint: API_ENDPOINT.to_string(),
+            config_dir: None,
         }
     }
 }
@@ -89,6 +89,7 @@ pub fn refresh_token(_cx: &mut AppContext) -> Result<(), String> {
     let refresh_token_data = parse_access_token_res(res)?;
 
     write_file(
+        _cx,
         File::Auth,
         create_file_content(refresh_token_data.clone())?,
         Overwrite,
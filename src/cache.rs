@@ -1,6 +1,6 @@
 use crate::app_context::AppContext;
-use crate::query::data::{AlbumData, DATA_TTL, PlaylistData, TrackData};
-use crate::utils::date::{Date, DateSource};
+use crate::query::data::{AlbumData, ArtistData, DATA_TTL, PlaylistData, TrackData};
+use crate::utils::date::Date;
 use crate::utils::file::File as _File;
 use crate::utils::utils::secs_now;
 use std::cmp::PartialEq;
@@ -15,16 +15,17 @@ pub trait ToCSV {
 impl ToCSV for TrackData {
     fn csv(&self) -> String {
         format!(
-            "{},{},{},{},{},{},{},{},{}",
+            "{},{},{},{},{},{},{},{},{},{}",
             self.id,
-            self.name,
+            escape(&self.name),
             self.duration,
             self.release_date.format(),
-            self.album_name,
+            escape(&self.album_name),
             self.album_id,
-            self.artists.join("|"), // connected with pipes to not interfere with over CSV
+            escape(&self.artists.join("|")), // connected with pipes to not interfere with over CSV
             self.added_at.format(),
-            self.popularity
+            self.popularity,
+            self.artist_ids.join("|")
         )
     }
 }
@@ -32,8 +33,12 @@ impl ToCSV for TrackData {
 impl ToCSV for PlaylistData {
     fn csv(&self) -> String {
         format!(
-            "{},{},{},{}",
-            self.id, self.name, self.tracks_api, self.track_count
+            "{},{},{},{},{}",
+            self.id,
+            escape(&self.name),
+            self.tracks_api,
+            self.track_count,
+            self.snapshot_id
         )
     }
 }
@@ -41,20 +46,71 @@ impl ToCSV for PlaylistData {
 impl ToCSV for AlbumData {
     fn csv(&self) -> String {
         format!(
-            "{},{},{},{},{},{},{},{}",
+            "{},{},{},{},{},{},{},{},{}",
             self.id,
-            self.name,
+            escape(&self.name),
             self.track_count,
             self.popularity,
             self.album_type,
             self.release_date.format(),
-            self.artists.join("|"),
-            self.saved_at.format()
+            escape(&self.artists.join("|")),
+            self.saved_at.format(),
+            self.artist_ids.join("|")
+        )
+    }
+}
+
+impl ToCSV for ArtistData {
+    fn csv(&self) -> String {
+        format!(
+            "{},{},{},{},{}",
+            self.id,
+            escape(&self.name),
+            escape(&self.genres.join("|")),
+            self.followers,
+            self.popularity
         )
     }
 }
 
-trait FromCSV {
+/// Commas in text are escaped with a backslash so they don't split the value
+pub fn escape(value: &str) -> String {
+    value.replace("\\", "\\\\").replace(",", "\\,")
+}
+
+/// Split a line on the commas that aren't escaped, removing the escapes
+pub fn split_csv(line: &str) -> Vec<String> {
+    let mut values: Vec<String> = vec![String::new()];
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(res) = chars.next() {
+                    values.last_mut().unwrap().push(res) // values always has at least one item
+                }
+            }
+            ',' => values.push(String::new()),
+            _ => values.last_mut().unwrap().push(c), // values always has at least one item
+        }
+    }
+
+    values
+}
+
+/// Artist ids joined with pipes, missing in lines written before they were stored
+fn split_ids(value: Option<&String>) -> Vec<String> {
+    value
+        .map(|x| {
+            x.split("|")
+                .filter(|x| !x.is_empty())
+                .map(|x| x.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub trait FromCSV {
     fn deserialise(lines: Vec<String>) -> Result<Self, String>
     where
         Self: Sized;
@@ -69,10 +125,7 @@ impl FromCSV for Vec<TrackData> {
         for line in lines {
             let mut data = TrackData::default();
 
-            let split = line
-                .split(",")
-                .map(|x| x.to_string())
-                .collect::<Vec<String>>();
+            let split = split_csv(&line);
 
             data.id = split[0].to_string();
             data.name = split[1].to_string();
@@ -87,6 +140,7 @@ impl FromCSV for Vec<TrackData> {
             data.popularity = split[8]
                 .parse()
                 .map_err(|_| "Cloud not parse track popularity into u8")?;
+            data.artist_ids = split_ids(split.get(9));
 
             tracks.push(data)
         }
@@ -105,13 +159,11 @@ impl FromCSV for PlaylistData {
 
         let mut data = PlaylistData::default();
 
-        let split = lines[0]
-            .split(",")
-            .map(|x| x.to_string())
-            .collect::<Vec<String>>();
+        let split = split_csv(&lines[0]);
 
-        if split.len() != 4 {
-            return Err("Playlist data CSV line does not contain 4 values.".to_string());
+        // caches from before the snapshot id was stored have 4 values
+        if split.len() != 4 && split.len() != 5 {
+            return Err("Playlist data CSV line does not contain 5 values.".to_string());
         }
 
         data.id = split[0].clone();
@@ -120,6 +172,7 @@ impl FromCSV for PlaylistData {
         data.track_count = split[3]
             .parse()
             .map_err(|_| "Could not parse track count into a u64.".to_string())?;
+        data.snapshot_id = split.get(4).cloned().unwrap_or_default();
         data.tracks = <Vec<TrackData> as FromCSV>::deserialise(lines[1..].to_vec())?;
 
         Ok(data)
@@ -134,13 +187,11 @@ impl FromCSV for AlbumData {
 
         let mut data = AlbumData::default();
 
-        let split = lines[0]
-            .split(",")
-            .map(|x| x.to_string())
-            .collect::<Vec<String>>();
+        let split = split_csv(&lines[0]);
 
-        if split.len() != 8 {
-            return Err("Album data CSV line does not contain 8 values.".to_string());
+        // caches from before the artist ids were stored have 8 values
+        if split.len() != 8 && split.len() != 9 {
+            return Err("Album data CSV line does not contain 9 values.".to_string());
         }
 
         data.id = split[0].clone();
@@ -152,17 +203,50 @@ impl FromCSV for AlbumData {
             .parse()
             .map_err(|_| "Could not parse popularity into a u64.".to_string())?;
         data.album_type = split[4].clone();
-        data.release_date = Date::new(split[5].clone(), DateSource::User)?;
+        data.release_date = Date::from_iso8601(split[5].clone())?;
         data.artists = split[6].clone().split("|").map(|x| x.to_string()).collect();
-        data.saved_at = Date::new(split[7].clone(), DateSource::User)?;
+        data.saved_at = Date::from_iso8601(split[7].clone())?;
+        data.artist_ids = split_ids(split.get(8));
         data.tracks = <Vec<TrackData> as FromCSV>::deserialise(lines[1..].to_vec())?;
 
         Ok(data)
     }
 }
 
-pub fn load_cache() -> Result<Option<impl Iterator<Item = String>>, String> {
-    let Ok(cache_file) = File::open(_File::Cache.path()?) else {
+impl FromCSV for Vec<ArtistData> {
+    fn deserialise(lines: Vec<String>) -> Result<Self, String> {
+        let mut artists: Vec<ArtistData> = Vec::new();
+        for line in lines {
+            let split = split_csv(&line);
+
+            if split.len() != 5 {
+                return Err("Artist data CSV line does not contain 5 values.".to_string());
+            }
+
+            artists.push(ArtistData {
+                id: split[0].clone(),
+                name: split[1].clone(),
+                // an artist without genres is an empty value, not one empty genre
+                genres: split[2]
+                    .split("|")
+                    .filter(|x| !x.is_empty())
+                    .map(|x| x.to_string())
+                    .collect(),
+                followers: split[3]
+                    .parse()
+                    .map_err(|_| "Could not parse followers into a u64.".to_string())?,
+                popularity: split[4]
+                    .parse()
+                    .map_err(|_| "Could not parse popularity into a u8.".to_string())?,
+            })
+        }
+
+        Ok(artists)
+    }
+}
+
+pub fn load_cache(cx: &AppContext) -> Result<Option<impl Iterator<Item = String> + use<>>, String> {
+    let Ok(cache_file) = File::open(_File::Cache.path(cx)?) else {
         return Ok(None);
     };
     let cache_file_reader = BufReader::new(cache_file);
@@ -189,12 +273,18 @@ pub fn load_cache() -> Result<Option<impl Iterator<Item = String>>, String> {
 pub struct DeserialisedCache {
     pub playlists: Vec<PlaylistData>,
     pub albums: Vec<AlbumData>,
+    pub artists: Vec<ArtistData>,
+    pub liked: Vec<TrackData>,
+    pub library_artists: Vec<ArtistData>,
 }
 
 #[derive(PartialEq)]
 enum DataType {
     Playlist,
     Album,
+    Artists,
+    Liked,
+    LibraryArtists,
 }
 
 /// Doesn't do that much error checking, relies on the format being correct
@@ -203,49 +293,56 @@ pub fn deserialise_cache(data: impl Iterator<Item = String>) -> Result<Deseriali
 
     let mut playlists: Vec<PlaylistData> = Vec::new();
     let mut albums: Vec<AlbumData> = Vec::new();
+    let mut artists: Vec<ArtistData> = Vec::new();
+    let mut liked: Vec<TrackData> = Vec::new();
+    let mut library_artists: Vec<ArtistData> = Vec::new();
 
     let mut currently_reading: DataType;
 
-    loop {
-        let line = match data_iter.next() {
-            Some(res) => res,
-            None => {
-                println!("breaking");
-                break
-            },
-        };
-
+    while let Some(line) = data_iter.next() {
         let bi = line.split(" ").next().unwrap();
         match bi {
             // there must be at least one item in the iter
             "ALBUM" => currently_reading = DataType::Album,
             "PLAYLIST" => currently_reading = DataType::Playlist,
+            "ARTISTS" => currently_reading = DataType::Artists,
+            "LIKED" => currently_reading = DataType::Liked,
+            "LIBRARY_ARTISTS" => currently_reading = DataType::LibraryArtists,
             _ => return Err(format!("Unknown block identifier reached ({})", bi)),
         };
 
         let mut lines = Vec::new();
         loop {
             let p = data_iter.peek();
-            if p.is_some() && vec!["ALBUM", "PLAYLIST"].contains(&p.clone().unwrap().as_str()) {
+            if p.is_some() && ["ALBUM", "PLAYLIST", "ARTISTS", "LIKED", "LIBRARY_ARTISTS"].contains(&p.clone().unwrap().as_str()) {
                 break;
             }
 
             let data_line = data_iter.next();
             if data_line.is_none() {
-                println!("breaking");
                 break;
             }
 
             lines.push(data_line.unwrap().to_string())
         }
 
-        if currently_reading == DataType::Playlist {
-            playlists.push(PlaylistData::deserialise(lines)?)
-        } else {
-            albums.push(AlbumData::deserialise(lines)?)
+        match currently_reading {
+            DataType::Playlist => playlists.push(PlaylistData::deserialise(lines)?),
+            DataType::Album => albums.push(AlbumData::deserialise(lines)?),
+            DataType::Artists => artists.append(&mut <Vec<ArtistData>>::deserialise(lines)?),
+            DataType::Liked => liked.append(&mut <Vec<TrackData>>::deserialise(lines)?),
+            DataType::LibraryArtists => {
+                library_artists.append(&mut <Vec<ArtistData>>::deserialise(lines)?)
+            }
         }
     }
-    Ok(DeserialisedCache { playlists, albums })
+    Ok(DeserialisedCache {
+        playlists,
+        albums,
+        artists,
+        liked,
+        library_artists,
+    })
 }
 
 /// Cache format
@@ -254,9 +351,21 @@ pub fn deserialise_cache(data: impl Iterator<Item = String>) -> Result<Deseriali
 /// <track 1 data as csv>
 /// <track 2 data as csv>
 /// ...
+/// ARTISTS
+/// <followed artist 1 data as csv>
+/// ...
+/// LIKED
+/// <liked track 1 data as csv>
+/// ...
+/// LIBRARY_ARTISTS
+/// <artist 1 of the library data as csv>
+/// ...
 pub fn serialise_cache(cx: &AppContext) -> Result<String, String> {
     let pd = cx.data.playlist_data.clone();
     let ad = cx.data.saved_album_data.clone();
+    let fd = cx.data.followed_artist_data.clone();
+    let ld = cx.data.liked_track_data.clone();
+    let lad = cx.data.library_artist_data.clone();
 
     let count = cx.data.count_cache_lines();
     let mut write_buffer: Vec<String> = Vec::with_capacity(count);
@@ -283,5 +392,111 @@ pub fn serialise_cache(cx: &AppContext) -> Result<String, String> {
         }
     }
 
+    if let Some(artists) = fd {
+        write_buffer.push("ARTISTS".to_string());
+        for i in artists {
+            write_buffer.push(i.csv())
+        }
+    }
+
+    if let Some(tracks) = ld {
+        write_buffer.push("LIKED".to_string());
+        for i in tracks {
+            write_buffer.push(i.csv())
+        }
+    }
+
+    if let Some(artists) = lad {
+        write_buffer.push("LIBRARY_ARTISTS".to_string());
+        for i in artists {
+            write_buffer.push(i.csv())
+        }
+    }
+
     Ok(write_buffer.join("\n"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn artist(id: &str, name: &str, genres: &[&str]) -> ArtistData {
+        ArtistData {
+            id: id.to_string(),
+            name: name.to_string(),
+            genres: genres.iter().map(|x| x.to_string()).collect(),
+            followers: 1200,
+            popularity: 70,
+        }
+    }
+
+    /// The cache as load_cache gives it, without the time it was written
+    fn round_trip(cx: &AppContext) -> DeserialisedCache {
+        let cache = serialise_cache(cx).unwrap();
+        deserialise_cache(cache.lines().skip(1).map(|x| x.to_string())).unwrap()
+    }
+
+    #[test]
+    fn followed_artists_round_trip() {
+        let mut cx = AppContext::default();
+        cx.data.playlist_data = Some(vec![PlaylistData {
+            id: "pl1".to_string(),
+            name: "All".to_string(),
+            ..Default::default()
+        }]);
+        cx.data.followed_artist_data = Some(vec![
+            artist("a1", "Crosby, Stills & Nash", &["folk rock", "soft, rock"]),
+            artist("a2", "Back\\slash", &[]),
+        ]);
+
+        let cache = round_trip(&cx);
+
+        assert_eq!(cache.playlists.len(), 1);
+        assert_eq!(cache.artists.len(), 2);
+        assert_eq!(cache.artists[0].id, "a1");
+        assert_eq!(cache.artists[0].name, "Crosby, Stills & Nash");
+        assert_eq!(cache.artists[0].genres, vec!["folk rock", "soft, rock"]);
+        assert_eq!(cache.artists[0].followers, 1200);
+        assert_eq!(cache.artists[0].popularity, 70);
+        assert_eq!(cache.artists[1].name, "Back\\slash");
+        assert!(cache.artists[1].genres.is_empty());
+        assert!(cache.library_artists.is_empty());
+    }
+
+    #[test]
+        fn liked_tracks_round_trip() {
+        let mut cx = AppContext::default();
+        cx.data.liked_track_data = Some(vec![TrackData {
+            id: "t1".to_string(),
+            name: "Suite: Judy Blue Eyes, Pt. 1".to_string(),
+            duration: 445000,
+            release_date: Date::from_iso8601("1969-05-29T00:00:00Z".to_string()).unwrap(),
+            album_name: "Crosby, Stills & Nash".to_string(),
+            album_id: "al1".to_string(),
+            artists: vec!["Crosby, Stills & Nash".to_string()],
+            added_at: Date::from_iso8601("2024-02-01T10:00:00Z".to_string()).unwrap(),
+            popularity: 64,
+            artist_ids: vec!["a1".to_string()],
+            genres: Vec::new(),
+        }]);
+        cx.data.library_artist_data = Some(vec![artist("a1", "Crosby, Stills & Nash", &[])]);
+
+        let cache = round_trip(&cx);
+
+        // the LIBRARY_ARTISTS header ends the liked block
+        assert_eq!(cache.liked.len(), 1);
+        assert_eq!(cache.library_artists.len(), 1);
+        assert!(cache.artists.is_empty());
+
+        let track = &cache.liked[0];
+        assert_eq!(track.id, "t1");
+        assert_eq!(track.name, "Suite: Judy Blue Eyes, Pt. 1");
+        assert_eq!(track.duration, 445000);
+        assert_eq!(track.album_name, "Crosby, Stills & Nash");
+        assert_eq!(track.artists, vec!["Crosby, Stills & Nash"]);
+        assert_eq!(track.artist_ids, vec!["a1"]);
+        assert_eq!(track.popularity, 64);
+        assert_eq!(track.release_date.format(), "1969-05-29T00:00:00.000");
+        assert_eq!(track.added_at.format(), "2024-02-01T00:00:00.000");
+    }
+}
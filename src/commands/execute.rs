@@ -0,0 +1,93 @@
+use crate::app_context::AppContext;
+use crate::query::run::run_query;
+use std::io::{Read, stdin};
+
+/// Run each query in the input in order, stopping at the first one that fails
+pub fn execute(cx: &mut AppContext, input: String) -> Result<(), String> {
+    for query in split_queries(&input) {
+        run_query(query, cx)?;
+    }
+
+    Ok(())
+}
+
+pub fn read_stdin() -> Result<String, String> {
+    let mut input = String::new();
+    stdin()
+        .read_to_string(&mut input)
+        .map_err(|x| x.to_string())?;
+
+    Ok(input)
+}
+
+/// Split the input into queries on semicolons that aren't in quotes, the last query doesn't need one
+fn split_queries(input: &str) -> Vec<String> {
+    let mut queries: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+
+    for c in input.chars() {
+        current.push(c);
+
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == ';' => {
+                queries.push(current.trim().to_string());
+                current = String::new();
+            }
+            None => {}
+        }
+    }
+
+    if !current.trim().is_empty() {
+        queries.push(format!("{};", current.trim()));
+    }
+
+    queries.retain(|x| x != ";");
+    queries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_semicolons() {
+        assert_eq!(
+            split_queries("SELECT name FROM LIKED; SELECT id FROM LIKED;"),
+            vec!["SELECT name FROM LIKED;", "SELECT id FROM LIKED;"]
+        );
+    }
+
+    #[test]
+    fn keeps_quoted_semicolons() {
+        assert_eq!(
+            split_queries(
+                "SELECT name FROM LIKED WHERE name == 'a;b'; SELECT name FROM LIKED WHERE name == \"c;d\";"
+            ),
+            vec![
+                "SELECT name FROM LIKED WHERE name == 'a;b';",
+                "SELECT name FROM LIKED WHERE name == \"c;d\";"
+            ]
+        );
+    }
+
+    #[test]
+    fn adds_the_last_semicolon() {
+        assert_eq!(
+            split_queries("SELECT name FROM LIKED;\nSELECT id FROM LIKED\n"),
+            vec!["SELECT name FROM LIKED;", "SELECT id FROM LIKED;"]
+        );
+    }
+
+    #[test]
+    fn skips_empty_statements() {
+        assert_eq!(
+            split_queries(";; SELECT name FROM LIKED;  ;\n"),
+            vec!["SELECT name FROM LIKED;"]
+        );
+        assert!(split_queries("  \n").is_empty());
+    }
+}
@@ -0,0 +1,368 @@
+use crate::app_context::AppContext;
+use crate::query::data::{AlbumData, PlaylistData, TrackData, load_data_source};
+use crate::query::tokenise::DataSource;
+use crate::utils::logger::{info_nnl, success};
+use rusqlite::{Connection, Transaction, params};
+use std::collections::HashMap;
+
+/// Tables written by `spotifyQL export --sqlite`
+/// Dates are ISO 8601 text, artists only have a name in the loaded data so they get a local id
+/// Albums that are only known through a playlist track have just their id, name and release date
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS artists (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE IF NOT EXISTS albums (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    album_type TEXT,
+    release_date TEXT,
+    popularity INTEGER,
+    track_count INTEGER,
+    saved_at TEXT
+);
+CREATE TABLE IF NOT EXISTS album_artists (
+    album_id TEXT NOT NULL REFERENCES albums(id),
+    position INTEGER NOT NULL,
+    artist_id INTEGER NOT NULL REFERENCES artists(id),
+    PRIMARY KEY (album_id, position)
+);
+CREATE TABLE IF NOT EXISTS tracks (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    release_date TEXT,
+    popularity INTEGER,
+    album_id TEXT REFERENCES albums(id)
+);
+CREATE TABLE IF NOT EXISTS track_artists (
+    track_id TEXT NOT NULL REFERENCES tracks(id),
+    position INTEGER NOT NULL,
+    artist_id INTEGER NOT NULL REFERENCES artists(id),
+    PRIMARY KEY (track_id, position)
+);
+CREATE TABLE IF NOT EXISTS playlists (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    track_count INTEGER NOT NULL,
+    snapshot_id TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS playlist_tracks (
+    playlist_id TEXT NOT NULL REFERENCES playlists(id),
+    position INTEGER NOT NULL,
+    track_id TEXT NOT NULL REFERENCES tracks(id),
+    added_at TEXT,
+    PRIMARY KEY (playlist_id, position)
+);
+CREATE TABLE IF NOT EXISTS album_tracks (
+    album_id TEXT NOT NULL REFERENCES albums(id),
+    position INTEGER NOT NULL,
+    track_id TEXT NOT NULL REFERENCES tracks(id),
+    PRIMARY KEY (album_id, position)
+);
+";
+
+/// Emptied before every export so running it again gives the same database, children first
+const TABLES: [&str; 8] = [
+    "playlist_tracks",
+    "album_tracks",
+    "track_artists",
+    "album_artists",
+    "playlists",
+    "tracks",
+    "albums",
+    "artists",
+];
+
+/// Write the playlists and saved albums to a SQLite database at the path
+pub fn export(cx: &mut AppContext, path: &str) -> Result<(), String> {
+    load_data_source(cx, DataSource::Playlists)?;
+    load_data_source(cx, DataSource::SavedAlbums)?;
+
+    let playlists = cx.data.playlist_data.as_deref().unwrap_or_default();
+    let albums = cx.data.saved_album_data.as_deref().unwrap_or_default();
+
+    info_nnl!("Exporting to {}.", path);
+
+    let mut conn = Connection::open(path)
+        .map_err(|x| format!("Could not open {} to export to it. ({})", path, x))?;
+    write_export(&mut conn, playlists, albums)?;
+
+    success!(
+        "Exported {} playlists and {} saved albums to {}.",
+        playlists.len(),
+        albums.len(),
+        path
+    );
+    Ok(())
+}
+
+/// Replace everything in the database with the playlists and albums, in a single transaction
+fn write_export(
+    conn: &mut Connection,
+    playlists: &[PlaylistData],
+    albums: &[AlbumData],
+) -> Result<(), String> {
+    let tx = conn.transaction().map_err(|x| x.to_string())?;
+
+    tx.execute_batch(SCHEMA).map_err(|x| x.to_string())?;
+    for table in TABLES {
+        tx.execute(&format!("DELETE FROM {}", table), [])
+            .map_err(|x| x.to_string())?;
+    }
+
+    let mut artists: HashMap<String, i64> = HashMap::new();
+
+    // saved albums go in first so they keep their full details
+    for album in albums {
+        insert_album(&tx, &mut artists, album)?;
+    }
+
+    for playlist in playlists {
+        insert_playlist(&tx, &mut artists, playlist)?;
+    }
+
+    for album in albums {
+        for (position, track) in album.tracks.iter().enumerate() {
+            insert_track(&tx, &mut artists, track, &album.id)?;
+            tx.prepare_cached(
+                "INSERT INTO album_tracks (album_id, position, track_id) VALUES (?1, ?2, ?3)",
+            )
+            .and_then(|mut x| x.execute(params![album.id, position, track.id]))
+            .map_err(|x| x.to_string())?;
+        }
+    }
+
+    tx.commit().map_err(|x| x.to_string())
+}
+
+fn insert_album(
+    tx: &Transaction,
+    artists: &mut HashMap<String, i64>,
+    album: &AlbumData,
+) -> Result<(), String> {
+    tx.prepare_cached(
+        "INSERT INTO albums (id, name, album_type, release_date, popularity, track_count, saved_at) \
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )
+    .and_then(|mut x| {
+        x.execute(params![
+            album.id,
+            album.name,
+            album.album_type,
+            album.release_date.format(),
+            album.popularity,
+            album.track_count,
+            album.saved_at.format()
+        ])
+    })
+    .map_err(|x| x.to_string())?;
+
+    for (position, name) in album.artists.iter().enumerate() {
+        let artist_id = artist_id(tx, artists, name)?;
+        tx.prepare_cached(
+            "INSERT INTO album_artists (album_id, position, artist_id) VALUES (?1, ?2, ?3)",
+        )
+        .and_then(|mut x| x.execute(params![album.id, position, artist_id]))
+        .map_err(|x| x.to_string())?;
+    }
+
+    Ok(())
+}
+
+fn insert_playlist(
+    tx: &Transaction,
+    artists: &mut HashMap<String, i64>,
+    playlist: &PlaylistData,
+) -> Result<(), String> {
+    tx.prepare_cached(
+        "INSERT INTO playlists (id, name, track_count, snapshot_id) VALUES (?1, ?2, ?3, ?4)",
+    )
+    .and_then(|mut x| {
+        x.execute(params![
+            playlist.id,
+            playlist.name,
+            playlist.track_count,
+            playlist.snapshot_id
+        ])
+    })
+    .map_err(|x| x.to_string())?;
+
+    for (position, track) in playlist.tracks.iter().enumerate() {
+        // albums of playlist tracks may not be saved, so only what the track knows is stored
+        tx.prepare_cached(
+            "INSERT OR IGNORE INTO albums (id, name, release_date) VALUES (?1, ?2, ?3)",
+        )
+        .and_then(|mut x| {
+            x.execute(params![
+                track.album_id,
+                track.album_name,
+                track.release_date.format()
+            ])
+        })
+        .map_err(|x| x.to_string())?;
+
+        insert_track(tx, artists, track, &track.album_id)?;
+
+        tx.prepare_cached(
+            "INSERT INTO playlist_tracks (playlist_id, position, track_id, added_at) \
+            VALUES (?1, ?2, ?3, ?4)",
+        )
+        .and_then(|mut x| {
+            x.execute(params![
+                playlist.id,
+                position,
+                track.id,
+                track.added_at.format()
+            ])
+        })
+        .map_err(|x| x.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Add the track and its artists, a track in several playlists or albums is only added once
+fn insert_track(
+    tx: &Transaction,
+    artists: &mut HashMap<String, i64>,
+    track: &TrackData,
+    album_id: &str,
+) -> Result<(), String> {
+    let inserted = tx
+        .prepare_cached(
+            "INSERT OR IGNORE INTO tracks (id, name, duration_ms, release_date, popularity, album_id) \
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .and_then(|mut x| {
+            x.execute(params![
+                track.id,
+                track.name,
+                track.duration,
+                track.release_date.format(),
+                track.popularity,
+                album_id
+            ])
+        })
+        .map_err(|x| x.to_string())?;
+
+    if inserted == 0 {
+        return Ok(());
+    }
+
+    for (position, name) in track.artists.iter().enumerate() {
+        let artist_id = artist_id(tx, artists, name)?;
+        tx.prepare_cached(
+            "INSERT INTO track_artists (track_id, position, artist_id) VALUES (?1, ?2, ?3)",
+        )
+        .and_then(|mut x| x.execute(params![track.id, position, artist_id]))
+        .map_err(|x| x.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Look up the artist's id, adding them if they haven't been seen yet
+fn artist_id(
+    tx: &Transaction,
+    artists: &mut HashMap<String, i64>,
+    name: &str,
+) -> Result<i64, String> {
+    if let Some(id) = artists.get(name) {
+        return Ok(*id);
+    }
+
+    tx.prepare_cached("INSERT INTO artists (name) VALUES (?1)")
+        .and_then(|mut x| x.execute(params![name]))
+        .map_err(|x| x.to_string())?;
+
+    let id = tx.last_insert_rowid();
+    artists.insert(name.to_string(), id);
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::date::{Date, DateSource};
+
+    fn date(date: &str) -> Date {
+        Date::new(date.to_string(), DateSource::Spotify).unwrap()
+    }
+
+    fn track(id: &str, name: &str, album_id: &str) -> TrackData {
+        TrackData {
+            id: id.to_string(),
+            name: name.to_string(),
+            duration: 200_000,
+            release_date: date("1965-08-06"),
+            album_name: "Help!".to_string(),
+            album_id: album_id.to_string(),
+            artists: vec!["The Beatles".to_string()],
+            added_at: date("2024-01-01"),
+            ..Default::default()
+        }
+    }
+
+    fn counts(conn: &Connection) -> Vec<i64> {
+        TABLES
+            .iter()
+            .map(|table| {
+                conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |x| x.get(0))
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn exporting_again_gives_the_same_database() {
+        let help = track("1", "Help!", "album1");
+        let playlists = vec![PlaylistData {
+            id: "pl1".to_string(),
+            name: "All".to_string(),
+            tracks: vec![help.clone(), track("2", "Something", "album2")],
+            track_count: 2,
+            snapshot_id: "loaded".to_string(),
+            ..Default::default()
+        }];
+        let albums = vec![AlbumData {
+            id: "album1".to_string(),
+            name: "Help!".to_string(),
+            track_count: 2,
+            tracks: vec![help, track("3", "Yesterday", "album1")],
+            album_type: "album".to_string(),
+            release_date: date("1965-08-06"),
+            artists: vec!["The Beatles".to_string()],
+            saved_at: date("2023-05-01"),
+            ..Default::default()
+        }];
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        write_export(&mut conn, &playlists, &albums).unwrap();
+        let first = counts(&conn);
+
+        // playlist_tracks, album_tracks, track_artists, album_artists, playlists, tracks, albums, artists
+        assert_eq!(first, vec![2, 2, 3, 1, 1, 3, 2, 1]);
+
+        write_export(&mut conn, &playlists, &albums).unwrap();
+        assert_eq!(counts(&conn), first);
+
+        // the track in both the playlist and the saved album is stored once, with the saved album's details kept
+        let shared: i64 = conn
+            .query_row("SELECT COUNT(*) FROM tracks WHERE id = '1'", [], |x| {
+                x.get(0)
+            })
+            .unwrap();
+        assert_eq!(shared, 1);
+
+        let album_type: Option<String> = conn
+            .query_row(
+                "SELECT album_type FROM albums WHERE id = 'album1'",
+                [],
+                |x| x.get(0),
+            )
+            .unwrap();
+        assert_eq!(album_type, Some("album".to_string()));
+    }
+}
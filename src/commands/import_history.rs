@@ -0,0 +1,245 @@
+use crate::app_context::AppContext;
+use crate::history::{HistoryRecord, history_csv};
+use crate::utils::date::Date;
+use crate::utils::file::{File, WriteMode, delete_file, rename_file, write_file};
+use crate::utils::logger::{info_nnl, success};
+use serde::de::{Error, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer as _};
+use serde_json::Deserializer;
+use std::fmt::Formatter;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Plays written to the history file at once
+const WRITE_BATCH: usize = 1000;
+
+/// An entry of a Streaming_History_Audio_*.json file, the fields that aren't used are skipped
+#[derive(Deserialize)]
+struct ExportRecord {
+    ts: String,
+    platform: Option<String>,
+    ms_played: u64,
+    master_metadata_track_name: Option<String>,
+    master_metadata_album_artist_name: Option<String>,
+    master_metadata_album_album_name: Option<String>,
+    spotify_track_uri: Option<String>,
+    reason_start: Option<String>,
+    reason_end: Option<String>,
+    shuffle: Option<bool>,
+    skipped: Option<bool>,
+}
+
+impl ExportRecord {
+    /// Podcast episodes and audiobooks have no track uri, they aren't kept
+    fn into_record(self) -> Result<Option<HistoryRecord>, String> {
+        let Some(id) = self
+            .spotify_track_uri
+            .as_deref()
+            .and_then(|x| x.strip_prefix("spotify:track:"))
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(HistoryRecord {
+            played_at: Date::from_timestamp(self.ts)?,
+            ms_played: self.ms_played,
+            name: self.master_metadata_track_name.unwrap_or_default(),
+            artist: self.master_metadata_album_artist_name.unwrap_or_default(),
+            album_name: self.master_metadata_album_album_name.unwrap_or_default(),
+            id: id.to_string(),
+            reason_start: self.reason_start.unwrap_or_default(),
+            reason_end: self.reason_end.unwrap_or_default(),
+            shuffle: self.shuffle.unwrap_or(false),
+            skipped: self.skipped.unwrap_or(false),
+            platform: self.platform.unwrap_or_default(),
+        }))
+    }
+}
+
+/// Goes through the array in an export file one entry at a time so the whole file is never in memory
+struct RecordVisitor<'a, F>
+where
+    F: FnMut(ExportRecord) -> Result<(), String>,
+{
+    f: &'a mut F,
+}
+
+impl<'de, F> Visitor<'de> for RecordVisitor<'_, F>
+where
+    F: FnMut(ExportRecord) -> Result<(), String>,
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "an array of streaming history entries")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        while let Some(record) = seq.next_element::<ExportRecord>()? {
+            (self.f)(record).map_err(A::Error::custom)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Import the Streaming_History_Audio_*.json files of Spotify's extended streaming history export in the folder
+/// Replaces anything imported before, as each export has the full history
+pub fn import_history(cx: &AppContext, dir: &str) -> Result<(), String> {
+    let mut files: Vec<(u64, String)> = Path::new(dir)
+        .read_dir()
+        .map_err(|x| format!("Could not read {}. ({})", dir, x))?
+        .filter_map(|x| x.ok())
+        .map(|x| x.file_name().to_string_lossy().to_string())
+        .filter_map(|x| {
+            let number = x
+                .strip_prefix("Streaming_History_Audio_")?
+                .strip_suffix(".json")?
+                .rsplit('_')
+                .next()?
+                .parse()
+                .ok()?;
+            Some((number, x))
+        })
+        .collect();
+
+    if files.is_empty() {
+        return Err(format!(
+            "No Streaming_History_Audio_*.json files in {}.",
+            dir
+        ));
+    }
+
+    // the files are numbered in date order, e.g. Streaming_History_Audio_2023-2024_3.json, and _10 comes after _9
+    files.sort();
+
+    info_nnl!("Importing {} streaming history files.", files.len());
+
+    // the old history is only replaced once every file has been read
+    write_file(
+        cx,
+        File::StreamingHistoryImport,
+        String::new(),
+        WriteMode::Overwrite,
+    )?;
+
+    let (imported, skipped) = match import_files(cx, dir, &files) {
+        Ok(res) => res,
+        Err(x) => {
+            delete_file(cx, File::StreamingHistoryImport)?;
+            return Err(x);
+        }
+    };
+
+    rename_file(cx, File::StreamingHistoryImport, File::StreamingHistory)?;
+
+    success!(
+        "Imported {} plays, skipped {} podcast and audiobook entries.",
+        imported,
+        skipped
+    );
+    Ok(())
+}
+
+/// Write the plays of each file to the import file, returns how many were imported and skipped
+fn import_files(
+    cx: &AppContext,
+    dir: &str,
+    files: &[(u64, String)],
+) -> Result<(usize, usize), String> {
+    let mut buf: Vec<String> = Vec::new();
+    let mut imported: usize = 0;
+    let mut skipped: usize = 0;
+
+    for (_, file_name) in files {
+        let path = Path::new(dir).join(file_name);
+        let reader = BufReader::new(
+            std::fs::File::open(&path)
+                .map_err(|x| format!("Could not open {}. ({})", file_name, x))?,
+        );
+
+        let mut add = |record: ExportRecord| -> Result<(), String> {
+            match record.into_record()? {
+                Some(res) => {
+                    buf.push(history_csv(&res) + "\n");
+                    imported += 1;
+                }
+                None => skipped += 1,
+            }
+
+            if buf.len() == WRITE_BATCH {
+                write_file(
+                    cx,
+                    File::StreamingHistoryImport,
+                    buf.concat(),
+                    WriteMode::Append,
+                )?;
+                buf.clear();
+            }
+
+            Ok(())
+        };
+
+        let mut de = Deserializer::from_reader(reader);
+        (&mut de)
+            .deserialize_seq(RecordVisitor { f: &mut add })
+            .and_then(|_| de.end())
+            .map_err(|x| format!("Could not read {}. ({})", file_name, x))?;
+    }
+
+    write_file(
+        cx,
+        File::StreamingHistoryImport,
+        buf.concat(),
+        WriteMode::Append,
+    )?;
+
+    Ok((imported, skipped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export_record(uri: Option<&str>) -> ExportRecord {
+        ExportRecord {
+            ts: "2024-03-01T12:30:00Z".to_string(),
+            platform: Some("linux".to_string()),
+            ms_played: 1000,
+            master_metadata_track_name: uri.map(|_| "Help!".to_string()),
+            master_metadata_album_artist_name: uri.map(|_| "The Beatles".to_string()),
+            master_metadata_album_album_name: uri.map(|_| "Help!".to_string()),
+            spotify_track_uri: uri.map(|x| x.to_string()),
+            reason_start: None,
+            reason_end: None,
+            shuffle: None,
+            skipped: Some(true),
+        }
+    }
+
+    #[test]
+    fn keeps_tracks() {
+        let record = export_record(Some("spotify:track:abc"))
+            .into_record()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(record.id, "abc");
+        assert_eq!(record.name, "Help!");
+        assert_eq!(record.artist, "The Beatles");
+        assert!(record.skipped);
+        assert!(!record.shuffle);
+    }
+
+    #[test]
+    fn skips_podcast_entries() {
+        assert!(export_record(None).into_record().unwrap().is_none());
+
+        let mut episode = export_record(None);
+        episode.spotify_track_uri = Some("spotify:episode:abc".to_string());
+        assert!(episode.into_record().unwrap().is_none());
+    }
+}
@@ -0,0 +1,347 @@
+use crate::app_context::AppContext;
+use crate::cache::ToCSV;
+use crate::query::data::{AlbumData, ArtistData, PlaylistData, TrackData};
+use crate::utils::date::{Date, DateSource};
+use crate::utils::file::{File, WriteMode, write_file};
+use crate::utils::logger::{info_nnl, success};
+use serde::Deserialize;
+use std::path::Path;
+
+/// YourLibrary.json from Spotify's account data export, shows, episodes and banned items aren't used
+#[derive(Deserialize)]
+struct ExportLibrary {
+    #[serde(default)]
+    tracks: Vec<ExportLibraryItem>,
+    #[serde(default)]
+    albums: Vec<ExportLibraryItem>,
+    #[serde(default)]
+    artists: Vec<ExportArtist>,
+}
+
+/// A saved track or album, albums have no track field
+#[derive(Deserialize)]
+struct ExportLibraryItem {
+    artist: String,
+    album: String,
+    track: Option<String>,
+    uri: String,
+}
+
+#[derive(Deserialize)]
+struct ExportArtist {
+    name: String,
+    uri: String,
+}
+
+/// Playlist1.json, Playlist2.json, ... from the export
+#[derive(Deserialize)]
+struct ExportPlaylists {
+    playlists: Vec<ExportPlaylist>,
+}
+
+#[derive(Deserialize)]
+struct ExportPlaylist {
+    name: String,
+    #[serde(default)]
+    items: Vec<ExportPlaylistItem>,
+}
+
+/// Episodes, audiobooks and local files have no track, they aren't kept
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportPlaylistItem {
+    track: Option<ExportTrack>,
+    added_date: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportTrack {
+    track_name: String,
+    artist_name: String,
+    album_name: String,
+    track_uri: String,
+}
+
+/// The id at the end of a Spotify URI, e.g. spotify:track:<id>
+fn uri_id(uri: &str, item_type: &str) -> Option<String> {
+    uri.strip_prefix(&format!("spotify:{}:", item_type))
+        .map(|x| x.to_string())
+}
+
+/// The export only names the tracks, so their duration, release date and popularity are left empty
+fn library_track(name: String, artist: String, album_name: String, id: String) -> TrackData {
+    TrackData {
+        id,
+        name,
+        artists: vec![artist],
+        album_name,
+        ..Default::default()
+    }
+}
+
+fn read_json<T>(path: &Path) -> Result<T, String>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let content = std::fs::read_to_string(path)
+        .map_err(|x| format!("Could not read {}. ({})", path.display(), x))?;
+
+    serde_json::from_str(&content)
+        .map_err(|x| format!("Could not read {}. ({})", path.display(), x))
+}
+
+/// Import YourLibrary.json and the Playlist*.json files of Spotify's account data export in the folder
+/// The library is saved in the cache format so it can be loaded in place of the API with --offline
+pub fn import_library(cx: &AppContext, dir: &str) -> Result<(), String> {
+    let dir = Path::new(dir);
+
+    let library: ExportLibrary = read_json(&dir.join("YourLibrary.json"))?;
+
+    let mut playlist_files: Vec<(u64, String)> = dir
+        .read_dir()
+        .map_err(|x| format!("Could not read {}. ({})", dir.display(), x))?
+        .filter_map(|x| x.ok())
+        .map(|x| x.file_name().to_string_lossy().to_string())
+        .filter_map(|x| {
+            let number = x
+                .strip_prefix("Playlist")?
+                .strip_suffix(".json")?
+                .parse()
+                .ok()?;
+            Some((number, x))
+        })
+        .collect();
+
+    // Playlist10.json comes after Playlist9.json
+    playlist_files.sort();
+
+    info_nnl!("Importing library from {}.", dir.display());
+
+    let mut playlists: Vec<PlaylistData> = Vec::new();
+    for (_, file_name) in &playlist_files {
+        let export: ExportPlaylists = read_json(&dir.join(file_name))?;
+
+        for playlist in export.playlists {
+            let mut tracks: Vec<TrackData> = Vec::new();
+
+            for item in playlist.items {
+                let Some(track) = item.track else {
+                    continue;
+                };
+                let Some(id) = uri_id(&track.track_uri, "track") else {
+                    continue;
+                };
+
+                let mut data =
+                    library_track(track.track_name, track.artist_name, track.album_name, id);
+                if let Some(res) = item.added_date {
+                    data.added_at = Date::new(res, DateSource::Spotify)?;
+                }
+
+                tracks.push(data)
+            }
+
+            // the export has no playlist ids so each one gets a local id
+            playlists.push(PlaylistData {
+                id: format!("local:playlist:{}", playlists.len() + 1),
+                name: playlist.name,
+                track_count: tracks.len() as u64,
+                tracks,
+                ..Default::default()
+            })
+        }
+    }
+
+    let liked: Vec<TrackData> = library
+        .tracks
+        .into_iter()
+        .filter_map(|x| {
+            let id = uri_id(&x.uri, "track")?;
+            Some(library_track(x.track?, x.artist, x.album, id))
+        })
+        .collect();
+
+    let albums: Vec<AlbumData> = library
+        .albums
+        .into_iter()
+        .filter_map(|x| {
+            Some(AlbumData {
+                id: uri_id(&x.uri, "album")?,
+                name: x.album,
+                artists: vec![x.artist],
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    let artists: Vec<ArtistData> = library
+        .artists
+        .into_iter()
+        .filter_map(|x| {
+            Some(ArtistData {
+                id: uri_id(&x.uri, "artist")?,
+                name: x.name,
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    // the same blocks as the cache, without the epoch line as the library doesn't expire
+    let mut buf: Vec<String> = Vec::new();
+    for playlist in &playlists {
+        buf.push("PLAYLIST".to_string());
+        buf.push(playlist.csv());
+        for track in &playlist.tracks {
+            buf.push(track.csv())
+        }
+    }
+
+    for album in &albums {
+        buf.push("ALBUM".to_string());
+        buf.push(album.csv());
+    }
+
+    buf.push("ARTISTS".to_string());
+    for artist in &artists {
+        buf.push(artist.csv())
+    }
+
+    buf.push("LIKED".to_string());
+    for track in &liked {
+        buf.push(track.csv())
+    }
+
+    write_file(cx, File::Library, buf.join("\n"), WriteMode::Overwrite)?;
+
+    success!(
+        "Imported {} playlists, {} saved albums, {} followed artists and {} liked tracks.",
+        playlists.len(),
+        albums.len(),
+        artists.len(),
+        liked.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::data::load_data_source;
+    use crate::query::tokenise::DataSource;
+    use serde_json::json;
+    use std::fs::{create_dir_all, remove_dir_all};
+    use std::path::PathBuf;
+
+    /// An empty folder in the temp folder
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("spotifyQL-{}-{}", std::process::id(), name));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn track(id: &str, name: &str) -> serde_json::Value {
+        json!({
+            "track": {
+                "trackName": name,
+                "artistName": "The Beatles",
+                "albumName": "Help!",
+                "trackUri": format!("spotify:track:{}", id),
+            },
+            "episode": null,
+            "addedDate": "2024-01-15",
+        })
+    }
+
+    fn playlist_file(dir: &Path, number: u64, items: Vec<serde_json::Value>) {
+        let export = json!({
+            "playlists": [{ "name": format!("Playlist {}", number), "items": items }]
+        });
+        std::fs::write(
+            dir.join(format!("Playlist{}.json", number)),
+            export.to_string(),
+        )
+        .unwrap();
+    }
+
+    /// A small account data export, Playlist1 has a local file and an episode
+    fn export(dir: &Path) {
+        let library = json!({
+            "tracks": [
+                { "artist": "The Beatles", "album": "Help!", "track": "Help!", "uri": "spotify:track:t1" },
+                { "artist": "Someone", "album": "Local", "track": "Demo", "uri": "spotify:local:Someone:Local:Demo:120" },
+            ],
+            "albums": [{ "artist": "The Beatles", "album": "Help!", "uri": "spotify:album:al1" }],
+            "artists": [{ "name": "The Beatles", "uri": "spotify:artist:ar1" }],
+            "shows": [],
+            "episodes": [],
+        });
+        std::fs::write(dir.join("YourLibrary.json"), library.to_string()).unwrap();
+
+        playlist_file(
+            dir,
+            1,
+            vec![
+                track("t1", "Help!"),
+                json!({
+                    "track": {
+                        "trackName": "Demo",
+                        "artistName": "Someone",
+                        "albumName": "Local",
+                        "trackUri": "spotify:local:Someone:Local:Demo:120",
+                    },
+                    "addedDate": "2024-01-16",
+                }),
+                json!({
+                    "track": null,
+                    "episode": { "episodeName": "An episode", "episodeUri": "spotify:episode:e1" },
+                    "addedDate": "2024-01-17",
+                }),
+            ],
+        );
+        playlist_file(dir, 10, vec![track("t3", "Ticket To Ride")]);
+        playlist_file(dir, 9, vec![track("t2", "Yesterday, Live")]);
+    }
+
+    #[test]
+    fn imported_library_loads_offline() {
+        let dir = temp_dir("export");
+        export(&dir);
+
+        let mut cx = AppContext {
+            config_dir: Some(temp_dir("import-library")),
+            offline: true,
+            ..Default::default()
+        };
+
+        import_library(&cx, dir.to_str().unwrap()).unwrap();
+        load_data_source(&mut cx, DataSource::Playlists).unwrap();
+
+        let playlists = cx.data.playlist_data.clone().unwrap();
+        let names: Vec<&str> = playlists.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, vec!["Playlist 1", "Playlist 9", "Playlist 10"]);
+        assert_eq!(playlists[2].id, "local:playlist:3");
+
+        // the local file and the episode aren't kept
+        let ids: Vec<&str> = playlists[0].tracks.iter().map(|x| x.id.as_str()).collect();
+        assert_eq!(ids, vec!["t1"]);
+        assert_eq!(playlists[0].track_count, 1);
+
+        let track = &playlists[1].tracks[0];
+        assert_eq!(track.name, "Yesterday, Live");
+        assert_eq!(track.artists, vec!["The Beatles"]);
+        assert_eq!(track.album_name, "Help!");
+        assert_eq!(track.added_at.format(), "2024-01-15T00:00:00.000");
+
+        let liked = cx.data.liked_track_data.clone().unwrap();
+        assert_eq!(liked.len(), 1);
+        assert_eq!(liked[0].id, "t1");
+        assert_eq!(cx.data.saved_album_data.clone().unwrap()[0].id, "al1");
+        assert_eq!(
+            cx.data.followed_artist_data.clone().unwrap()[0].name,
+            "The Beatles"
+        );
+    }
+}
@@ -33,12 +33,20 @@ pub fn login(cx: &mut AppContext) -> Result<(), String> {
     playlist-read-private - read all of a users playlists
     user-library-read - gives access to saved content
     user-follow-read - check if current user follows certain artist or user, get followed artists
+    playlist-modify-public - create playlists and change the tracks in public playlists
+    playlist-modify-private - create playlists and change the tracks in private playlists
+    user-top-read - get the users top tracks and artists
+    user-read-recently-played - get the tracks the user played most recently
     */
 
     let scope = vec![
         "playlist-read-private",
         "user-library-read",
         "user-follow-read",
+        "playlist-modify-public",
+        "playlist-modify-private",
+        "user-top-read",
+        "user-read-recently-played",
     ];
     let redirect = "http://127.0.0.1:5907";
 
@@ -104,6 +112,7 @@ pub fn login(cx: &mut AppContext) -> Result<(), String> {
     success!("Parsed access token response.");
     stdout.flush().unwrap();
     write_file(
+        cx,
         File::Auth,
         create_file_content(access_token_data.clone())?,
         Overwrite,
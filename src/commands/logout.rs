@@ -1,6 +1,7 @@
+use crate::app_context::AppContext;
 use crate::utils::file::{File, delete_file};
 
-pub fn logout() -> Result<(), String> {
+pub fn logout(cx: &AppContext) -> Result<(), String> {
     // delete auth file
-    delete_file(File::Auth)
+    delete_file(cx, File::Auth)
 }
@@ -1,3 +1,7 @@
+pub mod execute;
+pub mod export;
+pub mod import_history;
+pub mod import_library;
 pub mod input;
 pub mod login;
 pub mod logout;
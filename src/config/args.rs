@@ -5,6 +5,11 @@ pub enum Command {
     Login,
     Logout,
     CLI,
+    Export,
+    /// Import the extended streaming history export in the folder
+    ImportHistory(String),
+    /// Import the account data export in the folder as the library used offline
+    ImportLibrary(String),
 }
 
 #[derive(PartialEq)]
@@ -14,24 +19,110 @@ pub enum UIMode {
     CLI,
 }
 
+/// How query results are printed to stdout
+#[derive(PartialEq, Clone, Debug)]
+pub enum OutputFormat {
+    /// The padded table used in the interactive CLI
+    Table,
+    /// RFC 4180 comma separated values, fields with commas, quotes or line breaks are quoted
+    Csv,
+    /// Tab separated values, tabs, line breaks and backslashes in fields are escaped with a backslash
+    Tsv,
+    /// An array with an object for each row
+    Json,
+    /// One JSON object per line, for jq and streaming
+    Ndjson,
+    /// A GitHub pipe table
+    Markdown,
+    /// A page with just the table
+    Html,
+    /// An extended M3U playlist of the tracks, with their Spotify URIs
+    M3u8,
+    /// An XML shareable playlist of the tracks, with their Spotify URIs
+    Xspf,
+}
+
+impl OutputFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.to_lowercase().as_str() {
+            "table" => Ok(OutputFormat::Table),
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "markdown" | "md" => Ok(OutputFormat::Markdown),
+            "html" => Ok(OutputFormat::Html),
+            "m3u8" | "m3u" => Ok(OutputFormat::M3u8),
+            "xspf" => Ok(OutputFormat::Xspf),
+            _ => Err(format!(
+                "Unknown output format {}, expected table, csv, tsv, json, ndjson, markdown, html, m3u8 or xspf.",
+                format
+            )),
+        }
+    }
+
+    /// The format for a file name's extension, e.g. top.md is Markdown
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+
+        match extension.to_lowercase().as_str() {
+            "csv" => Some(OutputFormat::Csv),
+            "tsv" | "tab" => Some(OutputFormat::Tsv),
+            "json" => Some(OutputFormat::Json),
+            "ndjson" | "jsonl" => Some(OutputFormat::Ndjson),
+            "md" | "markdown" => Some(OutputFormat::Markdown),
+            "html" | "htm" => Some(OutputFormat::Html),
+            "m3u8" | "m3u" => Some(OutputFormat::M3u8),
+            "xspf" => Some(OutputFormat::Xspf),
+            _ => None,
+        }
+    }
+
+    /// Playlist files list the tracks behind the rows rather than the selected columns
+    pub fn is_playlist(&self) -> bool {
+        matches!(self, OutputFormat::M3u8 | OutputFormat::Xspf)
+    }
+}
+
 pub struct RunContext {
     pub command: Command,
     pub ui_mode: UIMode,
     pub file_output: Option<String>,
+    pub dry_run: bool,
+    /// A query passed with -e, it is run and then the program exits
+    pub execute: Option<String>,
+    pub format: OutputFormat,
+    /// Make changes over the confirm threshold without asking, needed when there is no terminal to ask on
+    pub yes: bool,
+    /// Leave out the header line of CSV and TSV output
+    pub no_header: bool,
+    /// Start saved CSV and TSV files with the query and the time it was run
+    pub metadata: bool,
+    /// The database file written by export
+    pub sqlite: Option<String>,
+    /// Query the imported library without logging in or using the network
+    pub offline: bool,
 }
 
 impl RunContext {
-    pub fn new() -> Self {
-        let mut _args = env::args().collect::<Vec<String>>();
-        println!("{:?}", _args);
-        _args.retain(|x| !x.ends_with("spotifyQL") && !x.ends_with("spotifyQL.exe")); // remove the binary's name from the args
-        println!("{:?}", _args);
+    pub fn new() -> Result<Self, String> {
+        Self::from_args(env::args().skip(1)) // skip the binary's name
+    }
 
-        let mut args = _args.into_iter().peekable();
+    fn from_args(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.peekable();
 
         let mut command: Command = Command::CLI;
         let mut ui_mode = UIMode::Default;
         let mut file_output: Option<String> = None;
+        let mut dry_run = false;
+        let mut execute: Option<String> = None;
+        let mut format = OutputFormat::Table;
+        let mut yes = false;
+        let mut no_header = false;
+        let mut metadata = false;
+        let mut sqlite: Option<String> = None;
+        let mut offline = false;
 
         while args.peek().is_some() {
             let arg = args.next().unwrap();
@@ -39,19 +130,92 @@ impl RunContext {
                 command = Command::Login
             } else if arg == "logout" {
                 command = Command::Logout
+            } else if arg == "export" {
+                command = Command::Export
+            } else if arg == "import-history" {
+                command = Command::ImportHistory(value(&mut args, &arg)?)
+            } else if arg == "import-library" {
+                command = Command::ImportLibrary(value(&mut args, &arg)?)
             } else if arg == "--no-tui" {
                 ui_mode = UIMode::CLI;
             } else if arg == "--tui" {
                 ui_mode = UIMode::TUI
             } else if arg == "--file" {
-                file_output = args.next();
+                file_output = Some(value(&mut args, &arg)?);
+            } else if arg == "--dry-run" {
+                dry_run = true
+            } else if arg == "-e" || arg == "--execute" {
+                execute = Some(value(&mut args, &arg)?);
+            } else if arg == "--format" {
+                format = OutputFormat::parse(&value(&mut args, &arg)?)?;
+            } else if arg == "-y" || arg == "--yes" {
+                yes = true
+            } else if arg == "--no-header" {
+                no_header = true
+            } else if arg == "--metadata" {
+                metadata = true
+            } else if arg == "--sqlite" {
+                sqlite = Some(value(&mut args, &arg)?);
+            } else if arg == "--offline" {
+                offline = true
+            } else {
+                return Err(format!("Unknown argument {}.", arg));
             }
         }
 
-        Self {
+        if command == Command::Export && sqlite.is_none() {
+            return Err("export needs a file to write to, use --sqlite <file>.".to_string());
+        } else if command != Command::Export && sqlite.is_some() {
+            return Err("--sqlite can only be used with export.".to_string());
+        }
+
+        Ok(Self {
             command,
             ui_mode,
             file_output,
-        }
+            dry_run,
+            execute,
+            format,
+            yes,
+            no_header,
+            metadata,
+            sqlite,
+            offline,
+        })
+    }
+}
+
+/// The value following a flag, e.g. the query after -e
+fn value(args: &mut impl Iterator<Item = String>, flag: &String) -> Result<String, String> {
+    match args.next() {
+        Some(res) => Ok(res),
+        None => Err(format!("{} needs a value.", flag)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_context(args: &[&str]) -> Result<RunContext, String> {
+        RunContext::from_args(args.iter().map(|x| x.to_string()))
+    }
+
+    #[test]
+    fn reads_flags() {
+        let rc = run_context(&["-e", "SELECT name FROM LIKED;", "--format", "csv", "-y"]).unwrap();
+
+        assert_eq!(rc.execute, Some("SELECT name FROM LIKED;".to_string()));
+        assert_eq!(rc.format, OutputFormat::Csv);
+        assert!(rc.yes);
+    }
+
+    #[test]
+    fn rejects_unknown_flags() {
+        assert_eq!(
+            run_context(&["--verbose"]).err(),
+            Some("Unknown argument --verbose.".to_string())
+        );
+        assert!(run_context(&["-e"]).is_err());
     }
 }
@@ -1,3 +1,4 @@
+use crate::app_context::AppContext;
 use crate::utils::file::{File, read_file};
 use crate::utils::logger::warning;
 use serde::Deserialize;
@@ -7,6 +8,8 @@ pub struct UserConfig {
     pub debug: bool,
     pub tui: bool,
     pub cache: bool,
+    /// Statements changing more tracks than this have to be confirmed before they run
+    pub confirm_threshold: usize,
 }
 
 #[derive(Deserialize)]
@@ -14,13 +17,19 @@ struct ConfigFileContent {
     debug: bool,
     tui: bool,
     cache: bool,
+    #[serde(default = "default_confirm_threshold")]
+    confirm_threshold: usize,
+}
+
+fn default_confirm_threshold() -> usize {
+    50
 }
 
 impl UserConfig {
-    pub fn load() -> Result<UserConfig, String> {
+    pub fn load(app_cx: &AppContext) -> Result<UserConfig, String> {
         let mut cx = Self::default();
 
-        let config_file_contents = match read_file(File::Config) {
+        let config_file_contents = match read_file(app_cx, File::Config) {
             Ok(res) => res,
             Err(_) => {
                 warning!("Could not load user config, resorting to default");
@@ -34,6 +43,7 @@ impl UserConfig {
         cx.debug = user_config.debug;
         cx.tui = user_config.tui;
         cx.cache = user_config.cache;
+        cx.confirm_threshold = user_config.confirm_threshold;
 
         Ok(cx)
     }
@@ -45,6 +55,7 @@ impl Default for UserConfig {
             debug: false,
             tui: true,
             cache: false,
+            confirm_threshold: default_confirm_threshold(),
         }
     }
 }
@@ -0,0 +1,183 @@
+use crate::app_context::AppContext;
+use crate::cache::{escape, split_csv};
+use crate::query::data::KeyAccess;
+use crate::query::value::Value;
+use crate::utils::date::Date;
+use crate::utils::file::File as _File;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// A track play from Spotify's extended streaming history export
+#[derive(Clone, Debug, Default)]
+pub struct HistoryRecord {
+    /// When playback stopped, the export's ts
+    pub played_at: Date,
+    pub ms_played: u64,
+    pub name: String,
+    /// The album artist, the export only has the one
+    pub artist: String,
+    pub album_name: String,
+    pub id: String,
+    pub reason_start: String,
+    pub reason_end: String,
+    pub shuffle: bool,
+    pub skipped: bool,
+    pub platform: String,
+}
+
+impl KeyAccess for HistoryRecord {
+    fn access<T>(&self, key: T) -> Result<Value, String>
+    where
+        T: AsRef<str> + Display,
+    {
+        match key.as_ref() {
+            "played_at" => Ok(Value::Date(self.played_at.clone())),
+            "ms_played" => Ok(Value::Int(self.ms_played.cast_signed())),
+            "hours_played" => Ok(Value::Float(self.ms_played as f64 / 3_600_000f64)),
+            "name" => Ok(Value::Str(self.name.clone())),
+            "artist" => Ok(Value::Str(self.artist.clone())),
+            "album_name" => Ok(Value::Str(self.album_name.clone())),
+            "id" => Ok(Value::Str(self.id.clone())),
+            "reason_start" => Ok(Value::Str(self.reason_start.clone())),
+            "reason_end" => Ok(Value::Str(self.reason_end.clone())),
+            "shuffle" => Ok(Value::Bool(self.shuffle)),
+            "skipped" => Ok(Value::Bool(self.skipped)),
+            "platform" => Ok(Value::Str(self.platform.clone())),
+            _ => Err(format!(
+                "SYNTAX ERROR: {} is not a valid attribute for streaming history data.",
+                key
+            )),
+        }
+    }
+
+    fn attributes() -> Vec<String> {
+        [
+            "played_at",
+            "ms_played",
+            "hours_played",
+            "name",
+            "artist",
+            "album_name",
+            "id",
+            "reason_start",
+            "reason_end",
+            "shuffle",
+            "skipped",
+            "platform",
+        ]
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<String>>()
+    }
+}
+
+/// Streaming history file format, one play per line in the order they were imported
+/// <played at>,<ms played>,<name>,<artist>,<album name>,<id>,<reason start>,<reason end>,<shuffle>,<skipped>,<platform>
+pub fn history_csv(record: &HistoryRecord) -> String {
+    format!(
+        "{},{},{},{},{},{},{},{},{},{},{}",
+        record.played_at.format(),
+        record.ms_played,
+        escape(&record.name),
+        escape(&record.artist),
+        escape(&record.album_name),
+        record.id,
+        escape(&record.reason_start),
+        escape(&record.reason_end),
+        record.shuffle,
+        record.skipped,
+        escape(&record.platform)
+    )
+}
+
+fn parse_history_line(line: &str) -> Result<HistoryRecord, String> {
+    let split = split_csv(line);
+    if split.len() != 11 {
+        return Err(format!("Invalid streaming history line ({}).", line));
+    }
+
+    Ok(HistoryRecord {
+        played_at: Date::from_timestamp(split[0].clone())?,
+        ms_played: split[1]
+            .parse()
+            .map_err(|_| "Could not parse ms_played into u64")?,
+        name: split[2].clone(),
+        artist: split[3].clone(),
+        album_name: split[4].clone(),
+        id: split[5].clone(),
+        reason_start: split[6].clone(),
+        reason_end: split[7].clone(),
+        shuffle: split[8] == "true",
+        skipped: split[9] == "true",
+        platform: split[10].clone(),
+    })
+}
+
+/// Whether import-history has been run
+pub fn history_imported(cx: &AppContext) -> Result<bool, String> {
+    Ok(_File::StreamingHistory.path(cx)?.exists())
+}
+
+/// Pass each imported play to f as it is read, the history can be far too big to hold all of it at once
+pub fn read_history<F>(cx: &AppContext, mut f: F) -> Result<(), String>
+where
+    F: FnMut(HistoryRecord) -> Result<(), String>,
+{
+    let history_file = File::open(_File::StreamingHistory.path(cx)?).map_err(|_| {
+        "No streaming history has been imported, run 'spotifyQL import-history <dir>' first."
+            .to_string()
+    })?;
+
+    for line in BufReader::new(history_file).lines() {
+        let line = line.map_err(|x| x.to_string())?;
+        if line.is_empty() {
+            continue;
+        }
+
+        f(parse_history_line(&line)?)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_line_round_trip() {
+        let record = HistoryRecord {
+            played_at: Date::from_timestamp("2024-03-01T12:30:00Z".to_string()).unwrap(),
+            ms_played: 215000,
+            name: "Hello, Goodbye".to_string(),
+            artist: "AC\\DC".to_string(),
+            album_name: "Magical Mystery Tour, Remastered".to_string(),
+            id: "abc".to_string(),
+            reason_start: "clickrow".to_string(),
+            reason_end: "trackdone".to_string(),
+            shuffle: true,
+            skipped: false,
+            platform: "linux, desktop".to_string(),
+        };
+
+        let parsed = parse_history_line(&history_csv(&record)).unwrap();
+
+        assert_eq!(parsed.played_at.format(), record.played_at.format());
+        assert_eq!(parsed.ms_played, 215000);
+        assert_eq!(parsed.name, "Hello, Goodbye");
+        assert_eq!(parsed.artist, "AC\\DC");
+        assert_eq!(parsed.album_name, "Magical Mystery Tour, Remastered");
+        assert_eq!(parsed.id, "abc");
+        assert_eq!(parsed.reason_start, "clickrow");
+        assert_eq!(parsed.reason_end, "trackdone");
+        assert!(parsed.shuffle);
+        assert!(!parsed.skipped);
+        assert_eq!(parsed.platform, "linux, desktop");
+    }
+
+    #[test]
+    fn history_line_needs_every_field() {
+        assert!(parse_history_line("2024-03-01T12:30:00Z,1000,name").is_err());
+    }
+}
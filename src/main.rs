@@ -1,14 +1,20 @@
 use crate::auth::token_refresh::refresh_token;
+use crate::commands::execute::{execute, read_stdin};
+use crate::commands::export::export;
+use crate::commands::import_history::import_history;
+use crate::commands::import_library::import_library;
 use crate::commands::input::input_loop;
 use crate::config::args::UIMode;
 use crate::ui::tui::TUI;
-use crate::utils::logger::{fatal, info_nnl, success, warning};
+use crate::utils::logger::{error, fatal, info_nnl, set_quiet, success, warning};
 use crate::utils::utils::secs_now;
 use crate::{
     commands::{login::login, logout::logout},
     config::args::{Command, RunContext},
 };
 use app_context::AppContext;
+use std::io::{IsTerminal, stdin};
+use std::process::ExitCode;
 
 mod api;
 mod app_context;
@@ -16,18 +22,71 @@ mod auth;
 mod cache;
 mod commands;
 mod config;
+mod history;
+mod play_log;
 mod query;
+mod snapshot;
 mod ui;
 mod utils;
 
-fn main() {
-    let rc = RunContext::new();
+/// Exit codes, 0 is success
+const EXIT_FAILURE: u8 = 1; // a query failed or the app could not start
+const EXIT_USAGE: u8 = 2; // bad command line arguments
+
+fn main() -> ExitCode {
+    let rc = match RunContext::new() {
+        Ok(res) => res,
+        Err(err) => {
+            error!("{}", err);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    // logging out and importing only need to know where the files are kept, not the login
+    let file_cx = AppContext::default();
 
     if rc.command == Command::Logout {
-        if let Err(err) = logout() {
+        if let Err(err) = logout(&file_cx) {
             fatal!("{}", err);
+            return ExitCode::from(EXIT_FAILURE);
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    // importing is offline so it doesn't need the app context or a login
+    if let Command::ImportHistory(dir) = &rc.command {
+        if let Err(err) = import_history(&file_cx, dir) {
+            error!("{}", err);
+            return ExitCode::from(EXIT_FAILURE);
         }
-        return;
+        return ExitCode::SUCCESS;
+    } else if let Command::ImportLibrary(dir) = &rc.command {
+        if let Err(err) = import_library(&file_cx, dir) {
+            error!("{}", err);
+            return ExitCode::from(EXIT_FAILURE);
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    // queries passed with -e or piped in are run without any prompts and the program exits after
+    let one_shot = if rc.command != Command::CLI {
+        None
+    } else if let Some(query) = rc.execute {
+        Some(query)
+    } else if !stdin().is_terminal() {
+        match read_stdin() {
+            Ok(res) => Some(res),
+            Err(err) => {
+                fatal!("{}", err);
+                return ExitCode::from(EXIT_FAILURE);
+            }
+        }
+    } else {
+        None
+    };
+
+    if one_shot.is_some() {
+        set_quiet(true);
     }
 
     let mut cx = match AppContext::load() {
@@ -35,7 +94,7 @@ fn main() {
         Err(err) => {
             warning!("{}", err);
             fatal!("Could not load app context, run 'spotifyQL logout' before trying again.");
-            return;
+            return ExitCode::from(EXIT_FAILURE);
         }
     };
 
@@ -46,51 +105,88 @@ fn main() {
     }
 
     cx.save_file = rc.file_output;
+    cx.dry_run = rc.dry_run;
+    cx.assume_yes = rc.yes;
+    cx.output_format = rc.format;
+    cx.output_header = !rc.no_header;
+    cx.output_metadata = rc.metadata;
+    cx.offline = rc.offline;
 
     if rc.command == Command::Login {
         if let Err(err) = login(&mut cx) {
-            fatal!("{}", err)
+            fatal!("{}", err);
+            return ExitCode::from(EXIT_FAILURE);
         }
-    } else if rc.command == Command::CLI {
-        if cx.token.len() == 0 {
+    } else if rc.command == Command::CLI || rc.command == Command::Export {
+        // offline the imported library is used, so there is no need to be logged in
+        if cx.token.len() == 0 && !cx.offline {
+            if one_shot.is_some() || rc.command == Command::Export {
+                fatal!("You are not logged in, run 'spotifyQL login' first.");
+                return ExitCode::from(EXIT_FAILURE);
+            }
+
             warning!("You are not logged in and are being automatically sent to the login flow.");
 
             info_nnl!("Logging out.");
-            if let Err(err) = logout() {
+            if let Err(err) = logout(&cx) {
                 fatal!("{}", err);
             }
             success!("Logged out.");
 
             if let Err(err) = login(&mut cx) {
                 fatal!("{}", err);
-                return;
+                return ExitCode::from(EXIT_FAILURE);
             }
         }
 
-        if secs_now() > cx.expires_after {
+        if secs_now() > cx.expires_after && !cx.offline {
             info_nnl!("Refreshing token.");
             if let Err(err) = refresh_token(&mut cx) {
-                fatal!("{}", err)
+                fatal!("{}", err);
+                return ExitCode::from(EXIT_FAILURE);
             }
             success!("Refreshed token.");
         }
 
-        if cx.user_config.tui {
+        if let Some(path) = rc.sqlite {
+            cx.user_config.tui = false;
+            cx.interactive = false;
+
+            if let Err(err) = export(&mut cx, &path) {
+                error!("{}", err);
+                return ExitCode::from(EXIT_FAILURE);
+            }
+        } else if let Some(input) = one_shot {
+            cx.user_config.tui = false;
+            cx.interactive = false;
+
+            if let Err(err) = execute(&mut cx, input) {
+                error!("{}", err);
+                return ExitCode::from(EXIT_FAILURE);
+            }
+        } else if cx.user_config.tui {
             match TUI::new() {
                 Ok(mut res) => {
                     if let Err(err) = res.run(&mut cx) {
-                        TUI::leave_tui_mode();
-                        fatal!("{}", err)
+                        if let Err(res) = TUI::leave_tui_mode() {
+                            error!("{}", res);
+                        }
+                        fatal!("{}", err);
+                        return ExitCode::from(EXIT_FAILURE);
                     }
                 }
                 Err(err) => {
-                    fatal!("{}", err)
+                    fatal!("{}", err);
+                    return ExitCode::from(EXIT_FAILURE);
                 }
             }
         } else {
             if let Err(err) = input_loop(&mut cx) {
-                fatal!("{}", err)
+                fatal!("{}", err);
+                return ExitCode::from(EXIT_FAILURE);
             }
         }
     }
+
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,226 @@
+use crate::app_context::AppContext;
+use crate::cache::{FromCSV, ToCSV, escape, split_csv};
+use crate::query::data::{KeyAccess, TrackData};
+use crate::query::value::Value;
+use crate::utils::date::Date;
+use crate::utils::file::{File, WriteMode, read_file, write_file};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt::Display;
+
+/// A track the user played, from the recently played API or the local play log
+#[derive(Clone, Debug)]
+pub struct Play {
+    pub played_at: Date,
+    /// URI of the playlist, album or artist it was played from, empty if there was none
+    pub context: String,
+    pub context_type: String,
+    pub track: TrackData,
+}
+
+impl KeyAccess for Play {
+    fn access<T>(&self, key: T) -> Result<Value, String>
+    where
+        T: AsRef<str> + Display,
+    {
+        match key.as_ref() {
+            "played_at" => Ok(Value::Date(self.played_at.clone())),
+            "context" => Ok(if self.context.is_empty() {
+                Value::Null
+            } else {
+                Value::Str(self.context.clone())
+            }),
+            "context_type" => Ok(if self.context_type.is_empty() {
+                Value::Null
+            } else {
+                Value::Str(self.context_type.clone())
+            }),
+            "added_at" => Err(format!(
+                "SYNTAX ERROR: {} is not a valid attribute for recently played data.",
+                key
+            )),
+            _ => self.track.access(key),
+        }
+    }
+
+    fn attributes() -> Vec<String> {
+        let mut attributes = vec![
+            "played_at".to_string(),
+            "context".to_string(),
+            "context_type".to_string(),
+        ];
+        attributes.extend(
+            TrackData::attributes()
+                .into_iter()
+                .filter(|x| x != "added_at"),
+        );
+
+        attributes
+    }
+}
+
+/// Play log file format, newest play first
+/// <played at>,<context>,<context type>,<track data as csv>
+/// ...
+fn play_csv(play: &Play) -> String {
+    format!(
+        "{},{},{},{}",
+        play.played_at.format(),
+        escape(&play.context),
+        play.context_type,
+        play.track.csv()
+    )
+}
+
+/// Every play in the local log, newest first
+pub fn load_plays(cx: &AppContext) -> Result<Vec<Play>, String> {
+    if !File::PlayLog.path(cx)?.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut plays: Vec<Play> = Vec::new();
+
+    for line in read_file(cx, File::PlayLog)?.lines() {
+        if line.is_empty() {
+            continue;
+        }
+
+        let split = split_csv(line);
+        if split.len() < 4 {
+            return Err(format!("Invalid play log line ({}).", line));
+        }
+
+        // the track fields were unescaped by the split so they are escaped again to be read as a track line
+        let track_line = split[3..]
+            .iter()
+            .map(|x| escape(x))
+            .collect::<Vec<String>>()
+            .join(",");
+
+        match Vec::<TrackData>::deserialise(vec![track_line])?.pop() {
+            Some(track) => plays.push(Play {
+                played_at: Date::from_timestamp(split[0].clone())?,
+                context: split[1].clone(),
+                context_type: split[2].clone(),
+                track,
+            }),
+            None => return Err(format!("Invalid play log line ({}).", line)),
+        }
+    }
+
+    Ok(plays)
+}
+
+/// Add the fetched plays to the local log, skipping any already in it, and return the whole log
+/// The API only returns the last 50 plays so the log is what lets history build up across sessions
+pub fn merge_plays(cx: &AppContext, fetched: Vec<Play>) -> Result<Vec<Play>, String> {
+    let mut plays = load_plays(cx)?;
+
+    let mut seen: HashSet<(String, String)> = plays
+        .iter()
+        .map(|x| (x.played_at.format(), x.track.id.clone()))
+        .collect();
+
+    for play in fetched {
+        if seen.insert((play.played_at.format(), play.track.id.clone())) {
+            plays.push(play)
+        }
+    }
+
+    plays.sort_by(|a, b| {
+        b.played_at
+            .partial_cmp(&a.played_at)
+            .unwrap_or(Ordering::Equal)
+    });
+
+    let buf: Vec<String> = plays.iter().map(play_csv).collect();
+    write_file(cx, File::PlayLog, buf.join("\n"), WriteMode::Overwrite)?;
+
+    Ok(plays)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    /// A config folder of its own so the log starts empty
+    fn context(name: &str) -> AppContext {
+        let dir = std::env::temp_dir().join(format!("spotifyQL-{}-{}", std::process::id(), name));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+
+        AppContext {
+            config_dir: Some(dir),
+            ..Default::default()
+        }
+    }
+
+    fn play(played_at: &str, id: &str) -> Play {
+        let played_at = Date::from_timestamp(played_at.to_string()).unwrap();
+
+        Play {
+            played_at: played_at.clone(),
+            context: "spotify:playlist:pl1".to_string(),
+            context_type: "playlist".to_string(),
+            track: TrackData {
+                id: id.to_string(),
+                name: format!("{}, live", id),
+                release_date: Date::year(2013).unwrap(),
+                added_at: played_at,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn ids(plays: &[Play]) -> Vec<String> {
+        plays
+            .iter()
+            .map(|x| format!("{} {}", x.played_at.format(), x.track.id))
+            .collect()
+    }
+
+    #[test]
+    fn merged_plays_are_deduped_and_newest_first() {
+        let cx = context("merge-plays");
+
+        merge_plays(
+            &cx,
+            vec![
+                play("2024-03-01T10:00:00Z", "t1"),
+                play("2024-03-01T09:00:00Z", "t2"),
+            ],
+        )
+        .unwrap();
+
+        // the same track played again is kept, the same play fetched again isn't
+        let plays = merge_plays(
+            &cx,
+            vec![
+                play("2024-03-01T12:00:00Z", "t1"),
+                play("2024-03-01T10:00:00Z", "t1"),
+                play("2024-03-01T11:00:00Z", "t3"),
+            ],
+        )
+        .unwrap();
+
+        let expected = vec![
+            "2024-03-01T12:00:00.000 t1",
+            "2024-03-01T11:00:00.000 t3",
+            "2024-03-01T10:00:00.000 t1",
+            "2024-03-01T09:00:00.000 t2",
+        ];
+        assert_eq!(ids(&plays), expected);
+        assert_eq!(ids(&load_plays(&cx).unwrap()), expected);
+
+        let loaded = load_plays(&cx).unwrap();
+        assert_eq!(loaded[0].context, "spotify:playlist:pl1");
+        assert_eq!(loaded[0].context_type, "playlist");
+        assert_eq!(loaded[0].track.name, "t1, live");
+    }
+
+    #[test]
+    fn no_log_is_no_plays() {
+        assert!(load_plays(&context("no-play-log")).unwrap().is_empty());
+    }
+}
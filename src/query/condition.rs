@@ -1,21 +1,19 @@
 use crate::query::data::KeyAccess;
+use crate::query::expression::Expression;
 use crate::query::tokenise::{Logical, Operator};
 use crate::query::value::Value;
-use std::fmt::Debug;
 
-pub fn compute_conditions<T: KeyAccess + Debug>(
-    data: &T,
-    conditions: Condition,
-) -> Result<bool, String> {
+pub fn compute_conditions<T: KeyAccess>(data: &T, conditions: &Condition) -> Result<bool, String> {
     let is_valid;
 
     let mut current_condition = conditions.clone();
     let mut current_op: Logical;
 
     // do the first condition outside loop to set up the tree
-    let res = data
-        .access(current_condition.attribute)?
-        .compare(current_condition.value, current_condition.operation)?;
+    let res = current_condition.lhs.resolve(data)?.compare(
+        current_condition.rhs.resolve(data)?,
+        current_condition.operation,
+    )?;
 
     let mut result_tree = ConditionResult {
         val: res,
@@ -30,9 +28,10 @@ pub fn compute_conditions<T: KeyAccess + Debug>(
             break;
         }
 
-        let res = data
-            .access(current_condition.attribute)?
-            .compare(current_condition.value, current_condition.operation)?;
+        let res = current_condition.lhs.resolve(data)?.compare(
+            current_condition.rhs.resolve(data)?,
+            current_condition.operation,
+        )?;
 
         result_tree.add_next_condition(current_op, res)
     }
@@ -113,10 +112,7 @@ pub fn compute_conditions<T: KeyAccess + Debug>(
         }
         result_tree = match new_tree {
             Some(res) => res,
-            None => {
-                println!("i don't think you should be able to see this");
-                break;
-            }
+            None => break,
         }
     }
 
@@ -128,21 +124,82 @@ pub fn compute_conditions<T: KeyAccess + Debug>(
     } else {
         is_valid = result_tree.val;
     }
-    println!("{}", is_valid);
     Ok(is_valid)
 }
 
 pub type NextCondition = (Logical, Box<Condition>);
 
+/// One side of a condition
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Attribute(String),
+    Value(Value),
+    /// An expression worked out for each row, e.g. a CASE or COUNT(name) on grouped rows
+    Expression(Expression),
+}
+
+impl Operand {
+    /// The value of the operand for a row
+    pub fn resolve<T>(&self, data: &T) -> Result<Value, String>
+    where
+        T: KeyAccess,
+    {
+        match self {
+            Operand::Attribute(res) => data.access(res),
+            Operand::Value(res) => Ok(res.clone()),
+            Operand::Expression(res) => res.evaluate(data),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Condition {
-    pub attribute: String,
+    pub lhs: Operand,
     pub operation: Operator,
-    pub value: Value,
+    pub rhs: Operand,
     pub next: Option<NextCondition>,
 }
 
 impl Condition {
+    /// The attributes of the data read by every condition in the chain
+    pub fn attributes(&self) -> Vec<String> {
+        let mut attributes: Vec<String> = Vec::new();
+        let mut current = Some(self);
+
+        while let Some(condition) = current {
+            for operand in [&condition.lhs, &condition.rhs] {
+                match operand {
+                    Operand::Attribute(res) => attributes.push(res.clone()),
+                    Operand::Expression(res) => attributes.extend(res.attributes()),
+                    Operand::Value(_) => {}
+                }
+            }
+
+            current = condition.next.as_ref().map(|(_, next)| next.as_ref());
+        }
+
+        attributes
+    }
+
+    /// Whether any operand in the chain uses an aggregation
+    pub fn has_aggregate(&self) -> bool {
+        let mut current = Some(self);
+
+        while let Some(condition) = current {
+            for operand in [&condition.lhs, &condition.rhs] {
+                if let Operand::Expression(res) = operand
+                    && res.has_aggregate()
+                {
+                    return true;
+                }
+            }
+
+            current = condition.next.as_ref().map(|(_, next)| next.as_ref());
+        }
+
+        false
+    }
+
     pub fn add_next_condition(&mut self, logical: Logical, condition: Condition) {
         let mut current = self;
 
@@ -1,13 +1,16 @@
 use crate::api::APIQuery;
 use crate::app_context::AppContext;
 use crate::cache::{deserialise_cache, load_cache, serialise_cache};
+use crate::history::history_imported;
+use crate::play_log::{Play, load_plays, merge_plays};
 use crate::query::{tokenise::DataSource, value::Value as DValue};
 use crate::utils::date::Date;
 use crate::utils::file::File as FileType;
-use crate::utils::file::{WriteMode, write_file};
+use crate::utils::file::{WriteMode, read_file, write_file};
 use crate::utils::logger::info;
 use crate::utils::utils::secs_now;
-use std::fmt::Display;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
 
 pub const DATA_TTL: u64 = 60 * 30;
 
@@ -30,6 +33,10 @@ pub struct TrackData {
     pub artists: Vec<String>,
     pub added_at: Date,
     pub popularity: u8, // value between 0 and 100
+    /// Ids of the artists in the same order as their names, local files have none
+    pub artist_ids: Vec<String>,
+    /// Every genre of the track's artists, only set once the artists have been loaded
+    pub genres: Vec<String>,
 }
 
 impl KeyAccess for TrackData {
@@ -53,6 +60,9 @@ impl KeyAccess for TrackData {
             )),
             "added_at" => Ok(DValue::Date(self.added_at.clone())),
             "popularity" => Ok(DValue::Int(self.popularity.cast_signed().into())),
+            "genres" => Ok(DValue::List(
+                self.genres.clone().into_iter().map(DValue::Str).collect(),
+            )),
             _ => Err(format!(
                 "SYNTAX ERROR: {} is not a valid attribute for track data.",
                 key
@@ -71,6 +81,7 @@ impl KeyAccess for TrackData {
             "artists",
             "added_at",
             "popularity",
+            "genres",
         ]
         .iter()
         .map(|x| x.to_string())
@@ -85,6 +96,8 @@ pub struct PlaylistData {
     pub tracks: Vec<TrackData>,
     pub tracks_api: String,
     pub track_count: u64,
+    /// The version of the playlist the tracks were loaded from, empty if it isn't known
+    pub snapshot_id: String,
 }
 
 impl KeyAccess for PlaylistData {
@@ -123,6 +136,8 @@ pub struct AlbumData {
     pub release_date: Date,
     pub artists: Vec<String>,
     pub saved_at: Date,
+    pub artist_ids: Vec<String>,
+    pub genres: Vec<String>,
 }
 
 impl KeyAccess for AlbumData {
@@ -145,6 +160,9 @@ impl KeyAccess for AlbumData {
                     .collect(),
             )),
             "saved_at" => Ok(DValue::Date(self.saved_at.clone())),
+            "genres" => Ok(DValue::List(
+                self.genres.clone().into_iter().map(DValue::Str).collect(),
+            )),
             _ => Err(format!(
                 "SYNTAX ERROR: {} is not a valid attribute for album data.",
                 key
@@ -162,6 +180,7 @@ impl KeyAccess for AlbumData {
             "release_date",
             "artists",
             "saved_at",
+            "genres",
         ]
         .iter()
         .map(|x| x.to_string())
@@ -169,6 +188,216 @@ impl KeyAccess for AlbumData {
     }
 }
 
+#[derive(Clone, Debug, Default)]
+pub struct ArtistData {
+    pub id: String,
+    pub name: String,
+    pub genres: Vec<String>,
+    pub followers: u64,
+    pub popularity: u8, // value between 0 and 100
+}
+
+impl KeyAccess for ArtistData {
+    fn access<T>(&self, key: T) -> Result<DValue, String>
+    where
+        T: AsRef<str> + Display,
+    {
+        match key.as_ref() {
+            "id" => Ok(DValue::Str(self.id.clone())),
+            "name" => Ok(DValue::Str(self.name.clone())),
+            "genres" => Ok(DValue::List(
+                self.genres.clone().into_iter().map(DValue::Str).collect(),
+            )),
+            "followers" => Ok(DValue::Int(self.followers.cast_signed())),
+            "popularity" => Ok(DValue::Int(self.popularity.cast_signed().into())),
+            _ => Err(format!(
+                "SYNTAX ERROR: {} is not a valid attribute for artist data.",
+                key
+            )),
+        }
+    }
+
+    fn attributes() -> Vec<String> {
+        ["id", "name", "genres", "followers", "popularity"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>()
+    }
+}
+
+/// How far back the user's top tracks and artists are worked out over
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum TimeRange {
+    /// About the last 4 weeks
+    Short,
+    /// About the last 6 months
+    Medium,
+    /// About the last year
+    Long,
+}
+
+impl TimeRange {
+    pub fn parse(range: &str) -> Result<Self, String> {
+        match range.trim().to_lowercase().as_str() {
+            "short" => Ok(TimeRange::Short),
+            "" | "medium" => Ok(TimeRange::Medium),
+            "long" => Ok(TimeRange::Long),
+            _ => Err(format!(
+                "SYNTAX ERROR: Unknown time range {}, expected short, medium or long",
+                range
+            )),
+        }
+    }
+
+    /// The time_range parameter of the top items endpoint
+    pub fn api_value(&self) -> &str {
+        match self {
+            TimeRange::Short => "short_term",
+            TimeRange::Medium => "medium_term",
+            TimeRange::Long => "long_term",
+        }
+    }
+}
+
+impl Display for TimeRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TimeRange::Short => "short",
+                TimeRange::Medium => "medium",
+                TimeRange::Long => "long",
+            }
+        )
+    }
+}
+
+/// One of the user's top tracks, top tracks aren't added anywhere so they have no added_at
+#[derive(Clone, Debug, Default)]
+pub struct TopTrack {
+    /// Numbered from 1, the most listened to track is first
+    pub rank: i64,
+    pub track: TrackData,
+}
+
+impl KeyAccess for TopTrack {
+    fn access<T>(&self, key: T) -> Result<DValue, String>
+    where
+        T: AsRef<str> + Display,
+    {
+        match key.as_ref() {
+            "rank" => Ok(DValue::Int(self.rank)),
+            "added_at" => Err(format!(
+                "SYNTAX ERROR: {} is not a valid attribute for top track data.",
+                key
+            )),
+            _ => self.track.access(key),
+        }
+    }
+
+    fn attributes() -> Vec<String> {
+        let mut attributes = vec!["rank".to_string()];
+        attributes.extend(
+            TrackData::attributes()
+                .into_iter()
+                .filter(|x| x != "added_at"),
+        );
+
+        attributes
+    }
+}
+
+/// One of the user's top artists
+#[derive(Clone, Debug, Default)]
+pub struct TopArtist {
+    /// Numbered from 1, the most listened to artist is first
+    pub rank: i64,
+    pub artist: ArtistData,
+}
+
+impl KeyAccess for TopArtist {
+    fn access<T>(&self, key: T) -> Result<DValue, String>
+    where
+        T: AsRef<str> + Display,
+    {
+        match key.as_ref() {
+            "rank" => Ok(DValue::Int(self.rank)),
+            _ => self.artist.access(key),
+        }
+    }
+
+    fn attributes() -> Vec<String> {
+        let mut attributes = vec!["rank".to_string()];
+        attributes.extend(ArtistData::attributes());
+
+        attributes
+    }
+}
+
+/// A row built while running a query rather than fetched from the API, e.g. a group of tracks
+#[derive(Clone, Debug, Default)]
+pub struct Row {
+    pub values: Vec<(String, DValue)>,
+}
+
+impl Row {
+    /// Build a row holding every attribute of the data
+    pub fn from_data<T>(data: &T) -> Result<Row, String>
+    where
+        T: KeyAccess,
+    {
+        Self::from_attributes(data, &T::attributes())
+    }
+
+    /// A row holding only some of the attributes of the data
+    pub fn from_attributes<T>(data: &T, attributes: &[String]) -> Result<Row, String>
+    where
+        T: KeyAccess,
+    {
+        let mut row = Row::default();
+
+        for attr in attributes {
+            let value = data.access(attr)?;
+            row.values.push((attr.clone(), value));
+        }
+
+        Ok(row)
+    }
+
+    pub fn contains(&self, key: &String) -> bool {
+        self.values.iter().any(|(k, _)| k == key)
+    }
+
+    /// Set the value of an attribute, replacing it if it already exists
+    pub fn insert(&mut self, key: String, value: DValue) {
+        match self.values.iter_mut().find(|(k, _)| *k == key) {
+            Some(res) => res.1 = value,
+            None => self.values.push((key, value)),
+        }
+    }
+}
+
+impl KeyAccess for Row {
+    fn access<T>(&self, key: T) -> Result<DValue, String>
+    where
+        T: AsRef<str> + Display,
+    {
+        match self.values.iter().find(|(k, _)| k == key.as_ref()) {
+            Some((_, res)) => Ok(res.clone()),
+            None => Err(format!(
+                "SYNTAX ERROR: {} is not a valid attribute for this result.",
+                key
+            )),
+        }
+    }
+
+    /// The attributes of a row depend on the query that built it
+    fn attributes() -> Vec<String> {
+        Vec::new()
+    }
+}
+
 #[derive(Clone)]
 pub struct Data {
     /// Creation time of the playlist data
@@ -177,6 +406,21 @@ pub struct Data {
     /// Creation time of the saved album data
     pub saved_album_data_ct: u64,
     pub saved_album_data: Option<Vec<AlbumData>>,
+    /// Creation time of the followed artist data
+    pub followed_artist_data_ct: u64,
+    pub followed_artist_data: Option<Vec<ArtistData>>,
+    /// Creation time of the Liked Songs data
+    pub liked_track_data_ct: u64,
+    pub liked_track_data: Option<Vec<TrackData>>,
+    /// Top tracks and artists for each time range with their creation time, they change daily so they aren't cached to disk
+    pub top_track_data: HashMap<TimeRange, (u64, Vec<TopTrack>)>,
+    pub top_artist_data: HashMap<TimeRange, (u64, Vec<TopArtist>)>,
+    /// Creation time of the recently played data, which is the whole play log rather than just the last 50 plays
+    pub recently_played_data_ct: u64,
+    pub recently_played_data: Option<Vec<Play>>,
+    /// Creation time of the details of every artist seen in the library
+    pub library_artist_data_ct: u64,
+    pub library_artist_data: Option<Vec<ArtistData>>,
 }
 
 impl Data {
@@ -196,6 +440,18 @@ impl Data {
             }
         }
 
+        if let Some(artists) = &self.followed_artist_data {
+            count += 1 + artists.len();
+        }
+
+        if let Some(tracks) = &self.liked_track_data {
+            count += 1 + tracks.len();
+        }
+
+        if let Some(artists) = &self.library_artist_data {
+            count += 1 + artists.len();
+        }
+
         count
     }
 }
@@ -207,21 +463,88 @@ impl Default for Data {
             playlist_data: None,
             saved_album_data_ct: 0,
             saved_album_data: None,
+            followed_artist_data_ct: 0,
+            followed_artist_data: None,
+            liked_track_data_ct: 0,
+            liked_track_data: None,
+            top_track_data: HashMap::new(),
+            top_artist_data: HashMap::new(),
+            recently_played_data_ct: 0,
+            recently_played_data: None,
+            library_artist_data_ct: 0,
+            library_artist_data: None,
+        }
+    }
+}
+
+/// Offline the data comes from the imported library and the local logs rather than the cache or the API
+fn load_offline(cx: &mut AppContext, source: DataSource) -> Result<(), String> {
+    match source {
+        DataSource::TopTracks(_) | DataSource::TopArtists(_) => Err(format!(
+            "{} needs the Spotify API, which isn't used when running with --offline.",
+            source
+        )),
+        DataSource::RecentlyPlayed => {
+            // only the plays already in the play log
+            if cx.data.recently_played_data.is_none() {
+                cx.data.recently_played_data = Some(load_plays(cx)?);
+            }
+
+            Ok(())
+        }
+        DataSource::StreamingHistory => {
+            if !history_imported(cx)? {
+                return Err("No streaming history has been imported, run 'spotifyQL import-history <dir>' first.".to_string());
+            }
+
+            Ok(())
+        }
+        // the artist details need the API so the genres of the tracks and albums are left empty
+        DataSource::Artists => load_offline(cx, DataSource::Playlists),
+        _ => {
+            // the whole library is loaded at once
+            if cx.data.playlist_data.is_some() {
+                return Ok(());
+            }
+
+            if !FileType::Library.path(cx)?.exists() {
+                return Err(
+                    "No library has been imported, run 'spotifyQL import-library <dir>' first."
+                        .to_string(),
+                );
+            }
+
+            let content = read_file(cx, FileType::Library)?;
+            let data = deserialise_cache(content.lines().map(|x| x.to_string()))?;
+
+            cx.data.playlist_data = Some(data.playlists);
+            cx.data.saved_album_data = Some(data.albums);
+            cx.data.followed_artist_data = Some(data.artists);
+            cx.data.liked_track_data = Some(data.liked);
+
+            if cx.user_config.debug && !cx.user_config.tui {
+                info!("Loaded imported library")
+            }
+
+            Ok(())
         }
     }
 }
 
 pub fn load_data_source(cx: &mut AppContext, source: DataSource) -> Result<(), String> {
+    if cx.offline {
+        return load_offline(cx, source);
+    }
+
     // load in cache
     // check for any missing data (e.g. there is only album data but the query needs playlist data
     // if there is missing data fetch correct data
     // overwrite cache with new data
 
-    if let Some(cache_text) = load_cache()? {
+    if let Some(cache_text) = load_cache(cx)? {
         let data = deserialise_cache(cache_text)?;
 
         cx.data.playlist_data = if data.playlists.len() > 0 {
-            println!("{:?}", data.playlists);
             Some(data.playlists)
         } else {
             None
@@ -234,10 +557,31 @@ pub fn load_data_source(cx: &mut AppContext, source: DataSource) -> Result<(), S
             None
         };
         cx.data.saved_album_data_ct = secs_now();
+
+        cx.data.followed_artist_data = if !data.artists.is_empty() {
+            Some(data.artists)
+        } else {
+            None
+        };
+        cx.data.followed_artist_data_ct = secs_now();
+
+        cx.data.liked_track_data = if !data.liked.is_empty() {
+            Some(data.liked)
+        } else {
+            None
+        };
+        cx.data.liked_track_data_ct = secs_now();
+
+        cx.data.library_artist_data = if !data.library_artists.is_empty() {
+            Some(data.library_artists)
+        } else {
+            None
+        };
+        cx.data.library_artist_data_ct = secs_now();
     }
 
     match source {
-        DataSource::Playlist(_) | DataSource::Playlists => {
+        DataSource::Playlist(_) | DataSource::Playlists | DataSource::Duplicates(..) => {
             let mut load = false;
 
             if cx.data.playlist_data.is_some() {
@@ -257,7 +601,7 @@ pub fn load_data_source(cx: &mut AppContext, source: DataSource) -> Result<(), S
             }
             if cx.user_config.cache {
                 let sd = serialise_cache(&cx)?;
-                write_file(FileType::Cache, sd, WriteMode::Overwrite)?
+                write_file(cx, FileType::Cache, sd, WriteMode::Overwrite)?
             }
         }
         DataSource::SavedAlbum(_) | DataSource::SavedAlbums => {
@@ -280,10 +624,331 @@ pub fn load_data_source(cx: &mut AppContext, source: DataSource) -> Result<(), S
             }
             if cx.user_config.cache {
                 let sd = serialise_cache(&cx)?;
-                write_file(FileType::Cache, sd, WriteMode::Overwrite)?
+                write_file(cx, FileType::Cache, sd, WriteMode::Overwrite)?
+            }
+        }
+        DataSource::FollowedArtists => {
+            let mut load = false;
+
+            if cx.data.followed_artist_data.is_some() {
+                if cx.data.followed_artist_data_ct + DATA_TTL < secs_now() {
+                    load = true;
+                }
+            } else {
+                load = true;
+            }
+
+            if load {
+                cx.data.followed_artist_data = Some(APIQuery::get_followed_artists(cx)?);
+                cx.data.followed_artist_data_ct = secs_now();
+            }
+            if cx.user_config.debug && !cx.user_config.tui {
+                info!("Loaded followed artist data")
+            }
+            if cx.user_config.cache {
+                let sd = serialise_cache(cx)?;
+                write_file(cx, FileType::Cache, sd, WriteMode::Overwrite)?
+            }
+        }
+        DataSource::TopTracks(range) => {
+            let load = match cx.data.top_track_data.get(&range) {
+                Some((ct, _)) => ct + DATA_TTL < secs_now(),
+                None => true,
+            };
+
+            if load {
+                let tracks = APIQuery::get_top_tracks(cx, &range)?
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, track)| TopTrack {
+                        rank: i as i64 + 1,
+                        track,
+                    })
+                    .collect();
+
+                cx.data.top_track_data.insert(range, (secs_now(), tracks));
+            }
+            if cx.user_config.debug && !cx.user_config.tui {
+                info!("Loaded top track data")
+            }
+        }
+        DataSource::TopArtists(range) => {
+            let load = match cx.data.top_artist_data.get(&range) {
+                Some((ct, _)) => ct + DATA_TTL < secs_now(),
+                None => true,
+            };
+
+            if load {
+                let artists = APIQuery::get_top_artists(cx, &range)?
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, artist)| TopArtist {
+                        rank: i as i64 + 1,
+                        artist,
+                    })
+                    .collect();
+
+                cx.data.top_artist_data.insert(range, (secs_now(), artists));
+            }
+            if cx.user_config.debug && !cx.user_config.tui {
+                info!("Loaded top artist data")
+            }
+        }
+        DataSource::StreamingHistory => {
+            // the history is read while the query runs as it can be too big to load
+            if !history_imported(cx)? {
+                return Err("No streaming history has been imported, run 'spotifyQL import-history <dir>' first.".to_string());
+            }
+        }
+        DataSource::RecentlyPlayed => {
+            let mut load = false;
+
+            if cx.data.recently_played_data.is_some() {
+                if cx.data.recently_played_data_ct + DATA_TTL < secs_now() {
+                    load = true;
+                }
+            } else {
+                load = true;
+            }
+
+            // the play log is kept whether or not caching is on, it is the only record of older plays
+            if load {
+                let plays = APIQuery::get_recently_played(cx)?;
+                cx.data.recently_played_data = Some(merge_plays(cx, plays)?);
+                cx.data.recently_played_data_ct = secs_now();
+            }
+            if cx.user_config.debug && !cx.user_config.tui {
+                info!("Loaded recently played data")
+            }
+        }
+        DataSource::Liked => {
+            let mut load = false;
+
+            if cx.data.liked_track_data.is_some() {
+                if cx.data.liked_track_data_ct + DATA_TTL < secs_now() {
+                    load = true;
+                }
+            } else {
+                load = true;
+            }
+
+            if load {
+                cx.data.liked_track_data = Some(APIQuery::get_liked_tracks(cx)?);
+                cx.data.liked_track_data_ct = secs_now();
+            }
+            if cx.user_config.debug && !cx.user_config.tui {
+                info!("Loaded liked track data")
+            }
+            if cx.user_config.cache {
+                let sd = serialise_cache(cx)?;
+                write_file(cx, FileType::Cache, sd, WriteMode::Overwrite)?
+            }
+        }
+        DataSource::Artists => {
+            // every artist in the library, and the genres of the tracks and albums come from them
+            load_data_source(cx, DataSource::Playlists)?;
+            load_data_source(cx, DataSource::SavedAlbums)?;
+            load_data_source(cx, DataSource::Liked)?;
+
+            let mut ids = library_artist_ids(&cx.data);
+            // the tracks outside the library that are loaded get genres too
+            for (_, tracks) in cx.data.top_track_data.values() {
+                for top in tracks {
+                    ids.extend(top.track.artist_ids.iter().cloned())
+                }
+            }
+            for play in cx.data.recently_played_data.iter().flatten() {
+                ids.extend(play.track.artist_ids.iter().cloned())
+            }
+
+            load_artists(cx, ids)?;
+
+            if cx.user_config.debug && !cx.user_config.tui {
+                info!("Loaded library artist data")
+            }
+            if cx.user_config.cache {
+                let sd = serialise_cache(cx)?;
+                write_file(cx, FileType::Cache, sd, WriteMode::Overwrite)?
             }
         }
     }
 
     Ok(())
 }
+
+/// Give the tracks or albums of the source the genres of their artists, only the artists of the source are fetched
+pub fn load_genres(cx: &mut AppContext, source: &DataSource) -> Result<(), String> {
+    if cx.offline {
+        // the artist details need the API so the genres are left empty
+        return Ok(());
+    }
+
+    load_artists(cx, source_artist_ids(&cx.data, source))?;
+
+    if cx.user_config.debug && !cx.user_config.tui {
+        info!("Loaded genres")
+    }
+    if cx.user_config.cache {
+        let sd = serialise_cache(cx)?;
+        write_file(cx, FileType::Cache, sd, WriteMode::Overwrite)?
+    }
+
+    Ok(())
+}
+
+/// Fetch the artists that haven't been fetched before, then give every loaded track and album the genres of its artists
+fn load_artists(cx: &mut AppContext, ids: Vec<String>) -> Result<(), String> {
+    if cx.data.library_artist_data.is_some()
+        && cx.data.library_artist_data_ct + DATA_TTL < secs_now()
+    {
+        cx.data.library_artist_data = None;
+    }
+
+    let mut artists = cx.data.library_artist_data.take().unwrap_or_default();
+
+    let known: HashSet<String> = artists.iter().map(|x| x.id.clone()).collect();
+    let mut missing: Vec<String> = Vec::new();
+
+    for id in ids {
+        if !known.contains(&id) && !missing.contains(&id) {
+            missing.push(id)
+        }
+    }
+
+    if !missing.is_empty() {
+        artists.append(&mut APIQuery::get_artists(cx, &missing)?);
+        cx.data.library_artist_data_ct = secs_now();
+    }
+
+    set_genres(&mut cx.data, &artists);
+    cx.data.library_artist_data = Some(artists);
+
+    Ok(())
+}
+
+/// The ids of the artists of the tracks or albums in a source, in the order they are seen
+fn source_artist_ids(data: &Data, source: &DataSource) -> Vec<String> {
+    let mut ids: Vec<String> = Vec::new();
+
+    match source {
+        DataSource::Playlist(name) => {
+            if let Some(playlist) = data
+                .playlist_data
+                .iter()
+                .flatten()
+                .find(|x| x.name == *name)
+            {
+                for track in &playlist.tracks {
+                    ids.extend(track.artist_ids.iter().cloned())
+                }
+            }
+        }
+        DataSource::SavedAlbum(name) => {
+            for album in data
+                .saved_album_data
+                .iter()
+                .flatten()
+                .filter(|x| x.name == *name)
+            {
+                for track in &album.tracks {
+                    ids.extend(track.artist_ids.iter().cloned())
+                }
+            }
+        }
+        DataSource::SavedAlbums => {
+            for album in data.saved_album_data.iter().flatten() {
+                ids.extend(album.artist_ids.iter().cloned())
+            }
+        }
+        DataSource::Liked => {
+            for track in data.liked_track_data.iter().flatten() {
+                ids.extend(track.artist_ids.iter().cloned())
+            }
+        }
+        DataSource::TopTracks(range) => {
+            if let Some((_, tracks)) = data.top_track_data.get(range) {
+                for top in tracks {
+                    ids.extend(top.track.artist_ids.iter().cloned())
+                }
+            }
+        }
+        DataSource::RecentlyPlayed => {
+            for play in data.recently_played_data.iter().flatten() {
+                ids.extend(play.track.artist_ids.iter().cloned())
+            }
+        }
+        _ => {}
+    }
+
+    ids
+}
+
+/// The ids of the artists of every playlist track, saved album and liked track, in the order they are seen
+pub fn library_artist_ids(data: &Data) -> Vec<String> {
+    let mut ids: Vec<String> = Vec::new();
+
+    for playlist in data.playlist_data.iter().flatten() {
+        for track in &playlist.tracks {
+            ids.extend(track.artist_ids.iter().cloned())
+        }
+    }
+
+    for album in data.saved_album_data.iter().flatten() {
+        ids.extend(album.artist_ids.iter().cloned());
+        for track in &album.tracks {
+            ids.extend(track.artist_ids.iter().cloned())
+        }
+    }
+
+    for track in data.liked_track_data.iter().flatten() {
+        ids.extend(track.artist_ids.iter().cloned())
+    }
+
+    ids
+}
+
+/// Give every loaded track and album the genres of all its artists
+fn set_genres(data: &mut Data, artists: &[ArtistData]) {
+    let genres: HashMap<&str, &Vec<String>> =
+        artists.iter().map(|x| (x.id.as_str(), &x.genres)).collect();
+
+    let genres_of = |ids: &Vec<String>| -> Vec<String> {
+        let mut result: Vec<String> = Vec::new();
+        for id in ids {
+            for genre in genres.get(id.as_str()).into_iter().flat_map(|x| x.iter()) {
+                if !result.contains(genre) {
+                    result.push(genre.clone())
+                }
+            }
+        }
+
+        result
+    };
+
+    for playlist in data.playlist_data.iter_mut().flatten() {
+        for track in &mut playlist.tracks {
+            track.genres = genres_of(&track.artist_ids)
+        }
+    }
+
+    for album in data.saved_album_data.iter_mut().flatten() {
+        album.genres = genres_of(&album.artist_ids);
+        for track in &mut album.tracks {
+            track.genres = genres_of(&track.artist_ids)
+        }
+    }
+
+    for track in data.liked_track_data.iter_mut().flatten() {
+        track.genres = genres_of(&track.artist_ids)
+    }
+
+    for (_, tracks) in data.top_track_data.values_mut() {
+        for top in tracks {
+            top.track.genres = genres_of(&top.track.artist_ids)
+        }
+    }
+
+    for play in data.recently_played_data.iter_mut().flatten() {
+        play.track.genres = genres_of(&play.track.artist_ids)
+    }
+}
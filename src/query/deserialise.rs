@@ -1,8 +1,9 @@
 /// Extract data with complete error handling
 pub mod result_parser {
-    use crate::query::data::{AlbumData, PlaylistData, TrackData};
+    use crate::play_log::Play;
+    use crate::query::data::{AlbumData, ArtistData, PlaylistData, TrackData};
     use crate::utils::date::{Date, DateSource};
-    use serde_json::Value;
+    use serde_json::{Map, Value};
 
     pub fn parse_playlists(str_data: String) -> Result<Vec<PlaylistData>, String> {
         let mut playlists: Vec<PlaylistData> = Vec::new();
@@ -33,6 +34,13 @@ pub mod result_parser {
                                 .to_string(),
                         ),
                     };
+                    let snapshot_id = match &obj["snapshot_id"] {
+                        Value::String(res) => res.clone(),
+                        _ => return Err(
+                            "Value 'snapshot_id' in field 'items' in response data is an unexpected type."
+                                .to_string(),
+                        ),
+                    };
                     let track_data = match &obj["tracks"] {
                         Value::Object(tracks_obj) => {
                             let api = match &tracks_obj["href"] {
@@ -78,6 +86,7 @@ pub mod result_parser {
                         tracks: Vec::new(),
                         tracks_api: track_data.0,
                         track_count: track_data.1,
+                        snapshot_id,
                     })
                 }
                 _ => {
@@ -91,6 +100,194 @@ pub mod result_parser {
         Ok(playlists)
     }
 
+    /// The artists in a page of followed artists and the cursor for the next page, if there is one
+    pub fn parse_followed_artists(
+        str_data: String,
+    ) -> Result<(Vec<ArtistData>, Option<String>), String> {
+        let mut artists: Vec<ArtistData> = Vec::new();
+        let val: Value = serde_json::from_str(str_data.as_str()).map_err(|x| x.to_string())?;
+
+        let raw_artists: Vec<Value>;
+
+        if let Value::Array(ar) = &val["artists"]["items"] {
+            raw_artists = ar.clone();
+        } else {
+            return Err("'items' field in response data is an unexpected type. (4)".to_string());
+        }
+
+        let cursor = match &val["artists"]["cursors"]["after"] {
+            Value::String(res) => Some(res.clone()),
+            Value::Null => None,
+            _ => {
+                return Err(
+                    "Value 'after' in field 'cursors' in response data is an unexpected type."
+                        .to_string(),
+                );
+            }
+        };
+
+        for i in raw_artists {
+            match i {
+                Value::Object(obj) => artists.push(parse_artist(&obj)?),
+                _ => {
+                    return Err(
+                        "Value in field 'items' in response data is an unexpected type."
+                            .to_string(),
+                    );
+                }
+            }
+        }
+        Ok((artists, cursor))
+    }
+
+    /// A page of the user's top tracks, which are track objects on their own so they have no added_at
+    pub fn parse_top_tracks(str_data: String) -> Result<Vec<TrackData>, String> {
+        let mut tracks: Vec<TrackData> = Vec::new();
+        let val: Value = serde_json::from_str(str_data.as_str()).map_err(|x| x.to_string())?;
+
+        let raw_tracks: Vec<Value>;
+
+        if let Value::Array(tr) = &val["items"] {
+            raw_tracks = tr.clone();
+        } else {
+            return Err("'items' field in response data is an unexpected type. (5)".to_string());
+        }
+
+        for i in raw_tracks {
+            match i {
+                Value::Object(track) => tracks.push(parse_track(
+                    &track,
+                    Date::default(),
+                    &"top tracks".to_string(),
+                )?),
+                _ => {
+                    return Err(
+                        "Value in field 'items' in response data is an unexpected type."
+                            .to_string(),
+                    );
+                }
+            }
+        }
+        Ok(tracks)
+    }
+
+    /// The user's recent plays, each track with when it was played and what it was played from
+    pub fn parse_recently_played(str_data: String) -> Result<Vec<Play>, String> {
+        let mut plays: Vec<Play> = Vec::new();
+        let val: Value = serde_json::from_str(str_data.as_str()).map_err(|x| x.to_string())?;
+
+        let raw_plays: Vec<Value>;
+
+        if let Value::Array(pl) = &val["items"] {
+            raw_plays = pl.clone();
+        } else {
+            return Err("'items' field in response data is an unexpected type. (7)".to_string());
+        }
+
+        for i in raw_plays {
+            match i {
+                Value::Object(obj) => {
+                    let played_at = match &obj["played_at"] {
+                        Value::String(res) => Date::from_timestamp(res.clone())?,
+                        _ => {
+                            return Err("Value 'played_at' in field 'items' in response data is an unexpected type.".to_string())
+                        }
+                    };
+
+                    // the context is null when the track wasn't played from a playlist, album or artist
+                    let (context, context_type) = match &obj["context"] {
+                        Value::Object(res) => (
+                            res["uri"].as_str().unwrap_or_default().to_string(),
+                            res["type"].as_str().unwrap_or_default().to_string(),
+                        ),
+                        _ => (String::new(), String::new()),
+                    };
+
+                    // played tracks aren't added anywhere, the play time is kept as added_at so it can be cached like any other track
+                    let track = match &obj["track"] {
+                        Value::Object(track) => {
+                            parse_track(track, played_at.clone(), &"recently played".to_string())?
+                        }
+                        _ => {
+                            return Err(
+                                "Value 'track' in response data is an unexpected type.".to_string()
+                            );
+                        }
+                    };
+
+                    plays.push(Play {
+                        played_at,
+                        context,
+                        context_type,
+                        track,
+                    })
+                }
+                _ => {
+                    return Err(
+                        "Value in field 'items' in response data is an unexpected type."
+                            .to_string(),
+                    );
+                }
+            }
+        }
+        Ok(plays)
+    }
+
+    /// A page of the user's top artists
+    pub fn parse_top_artists(str_data: String) -> Result<Vec<ArtistData>, String> {
+        let mut artists: Vec<ArtistData> = Vec::new();
+        let val: Value = serde_json::from_str(str_data.as_str()).map_err(|x| x.to_string())?;
+
+        let raw_artists: Vec<Value>;
+
+        if let Value::Array(ar) = &val["items"] {
+            raw_artists = ar.clone();
+        } else {
+            return Err("'items' field in response data is an unexpected type. (6)".to_string());
+        }
+
+        for i in raw_artists {
+            match i {
+                Value::Object(obj) => artists.push(parse_artist(&obj)?),
+                _ => {
+                    return Err(
+                        "Value in field 'items' in response data is an unexpected type."
+                            .to_string(),
+                    );
+                }
+            }
+        }
+        Ok(artists)
+    }
+
+    /// The artists of a batch fetched by id, ids that don't match an artist are null and skipped
+    pub fn parse_artists(str_data: String) -> Result<Vec<ArtistData>, String> {
+        let mut artists: Vec<ArtistData> = Vec::new();
+        let val: Value = serde_json::from_str(str_data.as_str()).map_err(|x| x.to_string())?;
+
+        let raw_artists: Vec<Value>;
+
+        if let Value::Array(ar) = &val["artists"] {
+            raw_artists = ar.clone();
+        } else {
+            return Err("'artists' field in response data is an unexpected type. (8)".to_string());
+        }
+
+        for i in raw_artists {
+            match i {
+                Value::Object(obj) => artists.push(parse_artist(&obj)?),
+                Value::Null => {}
+                _ => {
+                    return Err(
+                        "Value in field 'artists' in response data is an unexpected type."
+                            .to_string(),
+                    );
+                }
+            }
+        }
+        Ok(artists)
+    }
+
     pub fn parse_albums(str_data: String) -> Result<Vec<AlbumData>, String> {
         let mut albums: Vec<AlbumData> = Vec::new();
         let val: Value = serde_json::from_str(str_data.as_str()).map_err(|x| x.to_string())?;
@@ -186,6 +383,7 @@ pub mod result_parser {
                             let artists = match &album_obj["artists"] {
                                 Value::Array(res) => {
                                     let mut map: Vec<String> = Vec::new();
+                                    let mut ids: Vec<String> = Vec::new();
 
                                     for artist in res {
                                         let name = match &artist["name"] {
@@ -199,9 +397,13 @@ pub mod result_parser {
                                         };
 
                                         map.push(name);
+
+                                        if let Value::String(res) = &artist["id"] {
+                                            ids.push(res.clone())
+                                        }
                                     }
 
-                                    map
+                                    (map, ids)
                                 }
                                 _ => {
                                     return Err(format!(
@@ -245,8 +447,10 @@ pub mod result_parser {
                         popularity: album_data.3,
                         album_type: album_data.4,
                         release_date: Date::new(album_data.5, DateSource::Spotify)?,
-                        artists: album_data.6,
+                        artists: album_data.6.0,
                         saved_at: Date::from_iso8601(added_at)?,
+                        artist_ids: album_data.6.1,
+                        genres: Vec::new(),
                     })
                 }
                 _ => {
@@ -285,157 +489,18 @@ pub mod result_parser {
                         }
                     };
 
-                    let track_data = match &obj["track"] {
-                        Value::Object(track) => {
-                            let id = match &track["id"] {
-                                Value::String(res) => res.clone(),
-                                _ => {
-                                    return Err(format!(
-                                        "Value 'id' in field 'track' of tracks '{}' in response data is an unexpected type.",
-                                        _debug_parent_id
-                                    ));
-                                }
-                            };
-                            let name = match &track["name"] {
-                                Value::String(res) => res.clone(),
-                                _ => {
-                                    return Err(format!(
-                                        "Value 'name' in field 'track' of tracks '{}' in response data is an unexpected type.",
-                                        _debug_parent_id
-                                    ));
-                                }
-                            };
-                            let duration = match &track["duration_ms"] {
-                                Value::Number(res) => {
-                                    if res.is_u64() {
-                                        res.as_u64().expect("You shouldn't see this error message")
-                                    } else {
-                                        return Err(format!(
-                                            "Value 'duration_ms' in field 'track' of tracks '{}' in response data is not a positive integer.",
-                                            _debug_parent_id
-                                        ));
-                                    }
-                                }
-                                _ => {
-                                    return Err(format!(
-                                        "Value 'duration_ms' in field 'track' of playlist {} in response data is an unexpected type.",
-                                        _debug_parent_id
-                                    ));
-                                }
-                            };
-                            let popularity = match &track["popularity"] {
-                                Value::Number(res) => {
-                                    if res.is_u64() {
-                                        let temp = res
-                                            .as_u64()
-                                            .expect("You shouldn't see this error message");
-
-                                        temp as u8 // this should be fine as popularity must be between 0 and 100
-                                    } else {
-                                        return Err(format!(
-                                            "Value 'popularity' in field 'track' of tracks '{}' in response data is not a positive integer.",
-                                            _debug_parent_id
-                                        ));
-                                    }
-                                }
-                                _ => {
-                                    return Err(format!(
-                                        "Value 'popularity' in field 'track' of tracks '{}' in response data is an unexpected type.",
-                                        _debug_parent_id
-                                    ));
-                                }
-                            };
-                            let album_data = match &track["album"] {
-                                Value::Object(album) => {
-                                    let id = match &album["id"] {
-                                        Value::String(res) => res.clone(),
-                                        _ => {
-                                            return Err(
-                                                "Value 'id' in field 'album' of field 'track' is an unexpected type.".to_string(),
-                                            );
-                                        }
-                                    };
-
-                                    let name = match &album["name"] {
-                                        Value::String(res) => res.clone(),
-                                        _ => {
-                                            return Err("Value 'name' in field 'album' of field 'track' is an unexpected type.".to_string());
-                                        }
-                                    };
-
-                                    let release_date = match &album["release_date"] {
-                                        Value::String(res) => res.clone(),
-                                        _ => {
-                                            return Err("Value 'release_date' in field 'album' for field 'track' is an unexpected type.".to_string())
-                                        }
-                                    };
-
-                                    (id, name, release_date)
-                                }
-                                _ => {
-                                    return Err(format!(
-                                        "Value of field 'album' of track {} in response data is an unexpected type.",
-                                        name
-                                    ));
-                                }
-                            };
-                            let artists = match &track["artists"] {
-                                Value::Array(res) => {
-                                    let mut map: Vec<String> = Vec::new();
-
-                                    for artist in res {
-                                        let name = match &artist["name"] {
-                                            Value::String(res) => res.clone(),
-                                            _ => {
-                                                return Err(format!(
-                                                    "Value 'name' in field 'artists' of track {} in response data is an unexpected type.",
-                                                    name
-                                                ));
-                                            }
-                                        };
-
-                                        map.push(name);
-                                    }
-
-                                    map
-                                }
-                                _ => {
-                                    return Err(format!(
-                                        "Value of field 'artists' of track {} in response data is an unexpected type.",
-                                        name
-                                    ));
-                                }
-                            };
-
-                            (
-                                id,
-                                name,
-                                duration,
-                                album_data.0,
-                                album_data.1,
-                                album_data.2,
-                                artists,
-                                popularity,
-                            )
-                        }
+                    match &obj["track"] {
+                        Value::Object(track) => tracks.push(parse_track(
+                            track,
+                            Date::from_iso8601(added_at)?,
+                            _debug_parent_id,
+                        )?),
                         _ => {
                             return Err(
                                 "Value 'track' in response data is an unexpected type.".to_string()
                             );
                         }
-                    };
-
-                    tracks.push(TrackData {
-                        id: track_data.0,
-                        name: track_data.1,
-                        duration: track_data.2,
-                        album_id: track_data.3,
-                        album_name: track_data.4,
-                        release_date: Date::new(track_data.5, DateSource::Spotify)?,
-                        artists: track_data.6,
-                        added_at: Date::from_iso8601(added_at)?,
-                        popularity: track_data.7,
-                    })
+                    }
                 }
                 _ => {
                     return Err(
@@ -447,4 +512,381 @@ pub mod result_parser {
         }
         Ok(tracks)
     }
+
+    /// A track object, added_at comes from the item holding it as not every response has one
+    fn parse_track(
+        track: &Map<String, Value>,
+        added_at: Date,
+        _debug_parent_id: &String,
+    ) -> Result<TrackData, String> {
+        let id = match &track["id"] {
+            Value::String(res) => res.clone(),
+            _ => {
+                return Err(format!(
+                    "Value 'id' in field 'track' of tracks '{}' in response data is an unexpected type.",
+                    _debug_parent_id
+                ));
+            }
+        };
+        let name = match &track["name"] {
+            Value::String(res) => res.clone(),
+            _ => {
+                return Err(format!(
+                    "Value 'name' in field 'track' of tracks '{}' in response data is an unexpected type.",
+                    _debug_parent_id
+                ));
+            }
+        };
+        let duration = match &track["duration_ms"] {
+            Value::Number(res) => {
+                if res.is_u64() {
+                    res.as_u64().expect("You shouldn't see this error message")
+                } else {
+                    return Err(format!(
+                        "Value 'duration_ms' in field 'track' of tracks '{}' in response data is not a positive integer.",
+                        _debug_parent_id
+                    ));
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Value 'duration_ms' in field 'track' of playlist {} in response data is an unexpected type.",
+                    _debug_parent_id
+                ));
+            }
+        };
+        let popularity = match &track["popularity"] {
+            Value::Number(res) => {
+                if res.is_u64() {
+                    let temp = res.as_u64().expect("You shouldn't see this error message");
+
+                    temp as u8 // this should be fine as popularity must be between 0 and 100
+                } else {
+                    return Err(format!(
+                        "Value 'popularity' in field 'track' of tracks '{}' in response data is not a positive integer.",
+                        _debug_parent_id
+                    ));
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Value 'popularity' in field 'track' of tracks '{}' in response data is an unexpected type.",
+                    _debug_parent_id
+                ));
+            }
+        };
+        let album_data = match &track["album"] {
+            Value::Object(album) => {
+                let id = match &album["id"] {
+                    Value::String(res) => res.clone(),
+                    _ => {
+                        return Err(
+                            "Value 'id' in field 'album' of field 'track' is an unexpected type."
+                                .to_string(),
+                        );
+                    }
+                };
+
+                let name = match &album["name"] {
+                    Value::String(res) => res.clone(),
+                    _ => {
+                        return Err(
+                            "Value 'name' in field 'album' of field 'track' is an unexpected type."
+                                .to_string(),
+                        );
+                    }
+                };
+
+                let release_date = match &album["release_date"] {
+                    Value::String(res) => res.clone(),
+                    _ => {
+                        return Err("Value 'release_date' in field 'album' for field 'track' is an unexpected type.".to_string())
+                    }
+                };
+
+                (id, name, release_date)
+            }
+            _ => {
+                return Err(format!(
+                    "Value of field 'album' of track {} in response data is an unexpected type.",
+                    name
+                ));
+            }
+        };
+        let (artists, artist_ids) = match &track["artists"] {
+            Value::Array(res) => {
+                let mut map: Vec<String> = Vec::new();
+                let mut ids: Vec<String> = Vec::new();
+
+                for artist in res {
+                    let name = match &artist["name"] {
+                        Value::String(res) => res.clone(),
+                        _ => {
+                            return Err(format!(
+                                "Value 'name' in field 'artists' of track {} in response data is an unexpected type.",
+                                name
+                            ));
+                        }
+                    };
+
+                    map.push(name);
+
+                    // the artists of local files have no id
+                    if let Value::String(res) = &artist["id"] {
+                        ids.push(res.clone())
+                    }
+                }
+
+                (map, ids)
+            }
+            _ => {
+                return Err(format!(
+                    "Value of field 'artists' of track {} in response data is an unexpected type.",
+                    name
+                ));
+            }
+        };
+
+        Ok(TrackData {
+            id,
+            name,
+            duration,
+            album_id: album_data.0,
+            album_name: album_data.1,
+            release_date: Date::new(album_data.2, DateSource::Spotify)?,
+            artists,
+            added_at,
+            popularity,
+            artist_ids,
+            genres: Vec::new(),
+        })
+    }
+
+    /// An artist object, from the followed artists or the user's top artists
+    fn parse_artist(obj: &Map<String, Value>) -> Result<ArtistData, String> {
+        let id = match &obj["id"] {
+            Value::String(res) => res.clone(),
+            _ => {
+                return Err(
+                    "Value 'id' in field 'items' in response data is an unexpected type."
+                        .to_string(),
+                );
+            }
+        };
+        let name = match &obj["name"] {
+            Value::String(res) => res.clone(),
+            _ => {
+                return Err(
+                    "Value 'name' in field 'items' in response data is an unexpected type."
+                        .to_string(),
+                );
+            }
+        };
+        let genres = match &obj["genres"] {
+            Value::Array(res) => {
+                let mut map: Vec<String> = Vec::new();
+
+                for genre in res {
+                    match genre {
+                        Value::String(res) => map.push(res.clone()),
+                        _ => {
+                            return Err(format!(
+                                "Value in field 'genres' of artist {} in response data is an unexpected type.",
+                                name
+                            ));
+                        }
+                    }
+                }
+
+                map
+            }
+            _ => {
+                return Err(format!(
+                    "Value of field 'genres' of artist {} in response data is an unexpected type.",
+                    name
+                ));
+            }
+        };
+        let followers = match &obj["followers"]["total"] {
+            Value::Number(res) => {
+                if res.is_u64() {
+                    res.as_u64().expect("You shouldn't see this error message")
+                } else {
+                    return Err(format!(
+                        "Value 'total' in field 'followers' of artist {} in response data is not a positive integer.",
+                        name
+                    ));
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Value 'total' in field 'followers' of artist {} in response data is an unexpected type.",
+                    name
+                ));
+            }
+        };
+        let popularity = match &obj["popularity"] {
+            Value::Number(res) => {
+                if res.is_u64() {
+                    let temp = res.as_u64().expect("You shouldn't see this error message");
+
+                    temp as u8 // this should be fine as popularity must be between 0 and 100
+                } else {
+                    return Err(format!(
+                        "Value 'popularity' of artist {} in response data is not a positive integer.",
+                        name
+                    ));
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Value 'popularity' of artist {} in response data is an unexpected type.",
+                    name
+                ));
+            }
+        };
+
+        Ok(ArtistData {
+            id,
+            name,
+            genres,
+            followers,
+            popularity,
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use serde_json::json;
+
+        fn artist(id: &str) -> Value {
+            json!({
+                "id": id,
+                "name": format!("{} name", id),
+                "genres": ["indie rock", "garage rock"],
+                "followers": { "total": 1200 },
+                "popularity": 70,
+            })
+        }
+
+        fn track(id: &str) -> Value {
+            json!({
+                "id": id,
+                "name": format!("{} name", id),
+                "duration_ms": 272000,
+                "popularity": 85,
+                "album": { "id": "al1", "name": "AM", "release_date": "2013-09-09" },
+                "artists": [{ "id": "ar1", "name": "Arctic Monkeys" }],
+            })
+        }
+
+        #[test]
+        fn followed_artists_page_has_the_next_cursor() {
+            let page = json!({
+                "artists": {
+                    "items": [artist("a1"), artist("a2")],
+                    "cursors": { "after": "a2" },
+                }
+            });
+
+            let (artists, cursor) = parse_followed_artists(page.to_string()).unwrap();
+
+            assert_eq!(cursor, Some("a2".to_string()));
+            assert_eq!(artists.len(), 2);
+            assert_eq!(artists[0].id, "a1");
+            assert_eq!(artists[0].name, "a1 name");
+            assert_eq!(artists[0].genres, vec!["indie rock", "garage rock"]);
+            assert_eq!(artists[0].followers, 1200);
+            assert_eq!(artists[0].popularity, 70);
+        }
+
+        #[test]
+        fn last_followed_artists_page_has_no_cursor() {
+            let page = json!({
+                "artists": {
+                    "items": [artist("a3")],
+                    "cursors": { "after": null },
+                }
+            });
+
+            let (artists, cursor) = parse_followed_artists(page.to_string()).unwrap();
+
+            assert_eq!(cursor, None);
+            assert_eq!(artists.len(), 1);
+
+            let page = json!({ "artists": { "items": [], "cursors": { "after": 5 } } });
+            assert!(parse_followed_artists(page.to_string()).is_err());
+        }
+
+        #[test]
+        fn top_tracks_keep_their_order() {
+            let page = json!({ "items": [track("t2"), track("t1")] });
+
+            let tracks = parse_top_tracks(page.to_string()).unwrap();
+
+            assert_eq!(tracks.len(), 2);
+            assert_eq!(tracks[0].id, "t2");
+            assert_eq!(tracks[1].id, "t1");
+            assert_eq!(tracks[0].album_name, "AM");
+            assert_eq!(tracks[0].artists, vec!["Arctic Monkeys"]);
+            assert_eq!(tracks[0].artist_ids, vec!["ar1"]);
+            // top tracks aren't added anywhere
+            assert_eq!(tracks[0].added_at, Date::default());
+        }
+
+        #[test]
+        fn top_artists_keep_their_order() {
+            let page = json!({ "items": [artist("a2"), artist("a1")] });
+
+            let artists = parse_top_artists(page.to_string()).unwrap();
+
+            assert_eq!(artists.len(), 2);
+            assert_eq!(artists[0].id, "a2");
+            assert_eq!(artists[1].id, "a1");
+            assert_eq!(artists[1].followers, 1200);
+        }
+
+        #[test]
+        fn top_items_must_be_objects() {
+            assert!(parse_top_tracks(json!({ "items": ["t1"] }).to_string()).is_err());
+            assert!(parse_top_artists(json!({ "items": null }).to_string()).is_err());
+        }
+
+        #[test]
+        fn recently_played_keeps_the_time_and_context() {
+            let page = json!({
+                "items": [
+                    {
+                        "track": track("t1"),
+                        "played_at": "2024-03-01T10:15:30.589Z",
+                        "context": { "uri": "spotify:playlist:pl1", "type": "playlist" },
+                    },
+                    {
+                        "track": track("t2"),
+                        "played_at": "2024-03-01T09:00:00Z",
+                        "context": null,
+                    },
+                ]
+            });
+
+            let plays = parse_recently_played(page.to_string()).unwrap();
+
+            assert_eq!(plays.len(), 2);
+            assert_eq!(plays[0].track.id, "t1");
+            assert_eq!(plays[0].played_at.format(), "2024-03-01T10:15:30.000");
+            assert_eq!(plays[0].track.added_at, plays[0].played_at);
+            assert_eq!(plays[0].context, "spotify:playlist:pl1");
+            assert_eq!(plays[0].context_type, "playlist");
+            assert_eq!(plays[1].context, "");
+            assert_eq!(plays[1].context_type, "");
+        }
+
+        #[test]
+        fn recently_played_needs_a_play_time() {
+            let page = json!({ "items": [{ "track": track("t1"), "played_at": null }] });
+
+            assert!(parse_recently_played(page.to_string()).is_err());
+        }
+    }
 }
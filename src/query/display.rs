@@ -1,6 +1,9 @@
 pub mod data_display {
+    use crate::app_context::AppContext;
+    use crate::config::args::OutputFormat;
     use crate::query::data::KeyAccess;
     use crate::query::statements::{Aggregation, AggregationResult};
+    use crate::query::value::Value;
     use std::collections::HashMap;
 
     pub fn build_table<T>(data: Vec<T>, attributes: Vec<String>) -> Result<Vec<String>, String>
@@ -74,16 +77,6 @@ pub mod data_display {
 
         Ok(chained)
     }
-    pub fn table<T>(data: Vec<T>, attributes: Vec<String>) -> Result<(), String>
-    where
-        T: KeyAccess,
-    {
-        let lines = build_table(data, attributes)?;
-
-        println!("{}", lines.join("\n"));
-
-        Ok(())
-    }
 
     pub fn build_aggregation_table(
         aggregation: Aggregation,
@@ -133,9 +126,682 @@ pub mod data_display {
         ]
     }
 
-    pub fn aggregation_table(aggregation: Aggregation, data: HashMap<String, AggregationResult>) {
-        let lines = build_aggregation_table(aggregation, data);
+    /// Print rows to stdout in the format picked with --format
+    pub fn print_rows<T>(
+        data: Vec<T>,
+        attributes: Vec<String>,
+        cx: &AppContext,
+    ) -> Result<(), String>
+    where
+        T: KeyAccess,
+    {
+        print_lines(format_rows(
+            data,
+            attributes,
+            &cx.output_format,
+            cx.output_header,
+        )?);
+
+        Ok(())
+    }
+
+    pub fn print_aggregation(
+        aggregation: Aggregation,
+        data: HashMap<String, AggregationResult>,
+        attributes: Vec<String>,
+        cx: &AppContext,
+    ) -> Result<(), String> {
+        print_lines(format_aggregation(
+            aggregation,
+            data,
+            attributes,
+            &cx.output_format,
+            cx.output_header,
+        )?);
+
+        Ok(())
+    }
+
+    fn print_lines(lines: Vec<String>) {
+        // no rows as NDJSON is no output at all
+        if !lines.is_empty() {
+            println!("{}", lines.join("\n"))
+        }
+    }
+
+    /// Only the given attributes are included, in the same order
+    pub fn format_rows<T>(
+        data: Vec<T>,
+        attributes: Vec<String>,
+        format: &OutputFormat,
+        header: bool,
+    ) -> Result<Vec<String>, String>
+    where
+        T: KeyAccess,
+    {
+        if *format == OutputFormat::Table {
+            return build_table(data, attributes);
+        } else if format.is_playlist() {
+            return playlist(data, format, header);
+        }
+
+        let mut rows: Vec<Vec<Value>> = Vec::new();
+        for row in data {
+            let mut buf: Vec<Value> = Vec::new();
+            for col in attributes.iter() {
+                buf.push(row.access(col)?);
+            }
+
+            rows.push(buf)
+        }
+
+        render(&attributes, rows, format, header)
+    }
+
+    /// A single aggregation over all the rows is one row, and one object in JSON, with the attributes in the order they were selected
+    pub fn format_aggregation(
+        aggregation: Aggregation,
+        data: HashMap<String, AggregationResult>,
+        attributes: Vec<String>,
+        format: &OutputFormat,
+        header: bool,
+    ) -> Result<Vec<String>, String> {
+        if *format == OutputFormat::Table {
+            return Ok(build_aggregation_table(aggregation, data));
+        }
+
+        let mut columns: Vec<String> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        for i in attributes {
+            let value = match data.get(&i) {
+                Some(AggregationResult::Int(res)) => Value::Int(*res),
+                Some(AggregationResult::Float(res)) => Value::Float(*res),
+                None => Value::Null,
+            };
+
+            columns.push(aggregation.format(&i));
+            values.push(value);
+        }
+
+        render(&columns, vec![values], format, header)
+    }
+
+    /// Every format other than the table and playlists, header is only used by CSV and TSV as the others always have one
+    fn render(
+        columns: &[String],
+        rows: Vec<Vec<Value>>,
+        format: &OutputFormat,
+        header: bool,
+    ) -> Result<Vec<String>, String> {
+        match format {
+            OutputFormat::Table => Err("Tables are built from the rows.".to_string()),
+            OutputFormat::M3u8 | OutputFormat::Xspf => Err(
+                "M3U8 and XSPF files can only be made from tracks, not an aggregation.".to_string(),
+            ),
+            OutputFormat::Csv | OutputFormat::Tsv => Ok(delimited(columns, rows, format, header)),
+            OutputFormat::Markdown => Ok(markdown(columns, rows)),
+            OutputFormat::Html => Ok(html(columns, rows)),
+            OutputFormat::Json => {
+                let objects: Vec<serde_json::Value> =
+                    rows.iter().map(|x| json_object(columns, x)).collect();
+
+                Ok(vec![
+                    serde_json::to_string_pretty(&objects).map_err(|x| x.to_string())?,
+                ])
+            }
+            OutputFormat::Ndjson => Ok(rows
+                .iter()
+                .map(|x| json_object(columns, x).to_string())
+                .collect()),
+        }
+    }
+
+    fn json_object(columns: &[String], row: &[Value]) -> serde_json::Value {
+        let mut object = serde_json::Map::new();
+        for (column, value) in columns.iter().zip(row) {
+            object.insert(column.clone(), value.json());
+        }
+
+        serde_json::Value::Object(object)
+    }
+
+    /// A column is right aligned when all of its values are numbers
+    fn numeric_columns(columns: &[String], rows: &[Vec<Value>]) -> Vec<bool> {
+        (0..columns.len())
+            .map(|i| {
+                rows.iter().any(|x| x[i] != Value::Null)
+                    && rows
+                        .iter()
+                        .all(|x| matches!(x[i], Value::Int(_) | Value::Float(_) | Value::Null))
+            })
+            .collect()
+    }
+
+    /// CSV or TSV lines, without line endings
+    fn delimited(
+        columns: &[String],
+        rows: Vec<Vec<Value>>,
+        format: &OutputFormat,
+        header: bool,
+    ) -> Vec<String> {
+        let (separator, escape): (&str, fn(String) -> String) = match format {
+            OutputFormat::Tsv => ("\t", tsv_field),
+            _ => (",", csv_field),
+        };
+
+        let mut lines: Vec<String> = Vec::new();
+
+        if header {
+            lines.push(
+                columns
+                    .iter()
+                    .map(|x| escape(x.clone()))
+                    .collect::<Vec<String>>()
+                    .join(separator),
+            );
+        }
+
+        for row in rows {
+            lines.push(
+                row.into_iter()
+                    .map(|x| match x {
+                        Value::Null => String::new(), // a missing value is an empty field
+                        _ => escape(x.to_string()),
+                    })
+                    .collect::<Vec<String>>()
+                    .join(separator),
+            );
+        }
+
+        lines
+    }
+
+    /// A GitHub pipe table, padded so it lines up as plain text too
+    fn markdown(columns: &[String], rows: Vec<Vec<Value>>) -> Vec<String> {
+        let numeric = numeric_columns(columns, &rows);
+
+        let head: Vec<String> = columns.iter().map(|x| markdown_field(x.clone())).collect();
+        let body: Vec<Vec<String>> = rows
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|x| match x {
+                        Value::Null => String::new(),
+                        _ => markdown_field(x.to_string()),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // the separator needs at least three dashes
+        let mut widths: Vec<usize> = head.iter().map(|x| x.chars().count().max(3)).collect();
+        for row in &body {
+            for (i, field) in row.iter().enumerate() {
+                widths[i] = widths[i].max(field.chars().count());
+            }
+        }
+
+        let line = |fields: &Vec<String>| {
+            let padded: Vec<String> = fields
+                .iter()
+                .enumerate()
+                .map(|(i, x)| {
+                    if numeric[i] {
+                        format!("{:>width$}", x, width = widths[i])
+                    } else {
+                        format!("{:<width$}", x, width = widths[i])
+                    }
+                })
+                .collect();
+
+            format!("| {} |", padded.join(" | "))
+        };
+
+        let sep_line: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(i, width)| {
+                if numeric[i] {
+                    format!("{:->w$}:", "", w = width - 1)
+                } else {
+                    format!("{:-<w$}", "", w = width)
+                }
+            })
+            .collect();
+
+        let mut lines: Vec<String> = vec![line(&head), format!("| {} |", sep_line.join(" | "))];
+        lines.extend(body.iter().map(line));
+
+        lines
+    }
+
+    /// A whole HTML page with the table and the styles it needs, so it can be opened or pasted on its own
+    fn html(columns: &[String], rows: Vec<Vec<Value>>) -> Vec<String> {
+        let numeric = numeric_columns(columns, &rows);
+
+        let cell = |tag: &str, i: usize, text: String| {
+            if numeric[i] {
+                format!("<{} class=\"num\">{}</{}>", tag, html_escape(&text), tag)
+            } else {
+                format!("<{}>{}</{}>", tag, html_escape(&text), tag)
+            }
+        };
+
+        let mut lines: Vec<String> = vec![
+            "<!DOCTYPE html>".to_string(),
+            "<html>".to_string(),
+            "<head>".to_string(),
+            "<meta charset=\"utf-8\">".to_string(),
+            "<title>spotifyQL</title>".to_string(),
+            "<style>table { border-collapse: collapse; } th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: left; } .num { text-align: right; }</style>".to_string(),
+            "</head>".to_string(),
+            "<body>".to_string(),
+            "<table>".to_string(),
+            format!(
+                "<thead><tr>{}</tr></thead>",
+                columns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, x)| cell("th", i, x.clone()))
+                    .collect::<String>()
+            ),
+            "<tbody>".to_string(),
+        ];
+
+        for row in rows {
+            lines.push(format!(
+                "<tr>{}</tr>",
+                row.into_iter()
+                    .enumerate()
+                    .map(|(i, x)| match x {
+                        Value::Null => cell("td", i, String::new()),
+                        _ => cell("td", i, x.to_string()),
+                    })
+                    .collect::<String>()
+            ));
+        }
+
+        lines.extend(
+            ["</tbody>", "</table>", "</body>", "</html>"]
+                .iter()
+                .map(|x| x.to_string()),
+        );
+
+        lines
+    }
+
+    /// The tracks behind the rows as an M3U8 or XSPF playlist, each located by its Spotify URI
+    /// header is the #EXTM3U line, which is left out when adding to an M3U8 file
+    fn playlist<T>(data: Vec<T>, format: &OutputFormat, header: bool) -> Result<Vec<String>, String>
+    where
+        T: KeyAccess,
+    {
+        let mut lines: Vec<String> = Vec::new();
+
+        if *format == OutputFormat::Xspf {
+            lines.push("<?xml version=\"1.0\" encoding=\"UTF-8\"?>".to_string());
+            lines.push("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">".to_string());
+            lines.push("  <trackList>".to_string());
+        } else if header {
+            lines.push("#EXTM3U".to_string());
+        }
+
+        for row in data {
+            let (id, name, duration, artists, album) = match (
+                row.access("id"),
+                row.access("name"),
+                row.access("duration"),
+                row.access("artists"),
+                row.access("album_name"),
+            ) {
+                (
+                    Ok(Value::Str(id)),
+                    Ok(Value::Str(name)),
+                    Ok(Value::Int(duration)),
+                    Ok(Value::List(artists)),
+                    Ok(Value::Str(album)),
+                ) => (
+                    id,
+                    name,
+                    duration,
+                    artists
+                        .iter()
+                        .map(|x| x.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    album,
+                ),
+                _ => {
+                    return Err(
+                        "Every row must be a track to make an M3U8 or XSPF file, the query cannot be grouped."
+                            .to_string(),
+                    );
+                }
+            };
+            let uri = format!("spotify:track:{}", id);
+
+            if *format == OutputFormat::Xspf {
+                lines.extend([
+                    "    <track>".to_string(),
+                    format!("      <location>{}</location>", html_escape(&uri)),
+                    format!("      <title>{}</title>", html_escape(&name)),
+                    format!("      <creator>{}</creator>", html_escape(&artists)),
+                    format!("      <album>{}</album>", html_escape(&album)),
+                    format!("      <duration>{}</duration>", duration),
+                    "    </track>".to_string(),
+                ]);
+            } else {
+                // EXTINF is in whole seconds and the title can't span lines
+                lines.push(
+                    format!("#EXTINF:{},{} - {}", (duration + 500) / 1000, artists, name)
+                        .replace(['\r', '\n'], " "),
+                );
+                lines.push(uri);
+            }
+        }
+
+        if *format == OutputFormat::Xspf {
+            lines.push("  </trackList>".to_string());
+            lines.push("</playlist>".to_string());
+        }
+
+        Ok(lines)
+    }
+
+    /// Fields with a comma, quote or line break are put in quotes, with any quotes in them doubled (RFC 4180)
+    fn csv_field(field: String) -> String {
+        if field.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field
+        }
+    }
+
+    /// TSV can't quote fields so tabs, line breaks and backslashes are escaped with a backslash
+    fn tsv_field(field: String) -> String {
+        field
+            .replace('\\', "\\\\")
+            .replace('\t', "\\t")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r")
+    }
+
+    /// Pipes and backslashes would break the table so they are escaped, and line breaks become <br>
+    fn markdown_field(field: String) -> String {
+        field
+            .replace('\\', "\\\\")
+            .replace('|', "\\|")
+            .replace("\r\n", "<br>")
+            .replace(['\r', '\n'], "<br>")
+    }
+
+    fn html_escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .replace('\'', "&#39;")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::query::data::TrackData;
+        use crate::utils::date::{Date, DateSource};
+
+        fn track(id: &str, name: &str, artists: &[&str], album_name: &str) -> TrackData {
+            TrackData {
+                id: id.to_string(),
+                name: name.to_string(),
+                duration: 187_400,
+                release_date: Date::new("2013-09-09".to_string(), DateSource::Spotify).unwrap(),
+                album_name: album_name.to_string(),
+                artists: artists.iter().map(|x| x.to_string()).collect(),
+                popularity: 85,
+                ..Default::default()
+            }
+        }
+
+        fn strings(values: &[&str]) -> Vec<String> {
+            values.iter().map(|x| x.to_string()).collect()
+        }
+
+        fn format(data: Vec<TrackData>, attributes: &[&str], format: OutputFormat) -> Vec<String> {
+            format_rows(data, strings(attributes), &format, true).unwrap()
+        }
+
+        #[test]
+        fn csv_quotes_fields() {
+            assert_eq!(csv_field("Help!".to_string()), "Help!");
+            assert_eq!(
+                csv_field("Hello, Goodbye".to_string()),
+                "\"Hello, Goodbye\""
+            );
+            assert_eq!(csv_field("12\" Mix".to_string()), "\"12\"\" Mix\"");
+            assert_eq!(csv_field("two\nlines".to_string()), "\"two\nlines\"");
+        }
+
+        #[test]
+        fn tsv_escapes_fields() {
+            assert_eq!(tsv_field("a\tb".to_string()), "a\\tb");
+            assert_eq!(tsv_field("two\nlines".to_string()), "two\\nlines");
+            assert_eq!(tsv_field("back\\slash".to_string()), "back\\\\slash");
+        }
+
+        #[test]
+        fn csv_has_a_header_and_only_the_projected_columns() {
+            let lines = format(
+                vec![
+                    track(
+                        "1",
+                        "Hello, Goodbye",
+                        &["The Beatles"],
+                        "Magical Mystery Tour",
+                    ),
+                    track("2", "Solo", &["Someone"], "Solo"),
+                ],
+                &["name", "popularity"],
+                OutputFormat::Csv,
+            );
+
+            assert_eq!(
+                lines,
+                strings(&["name,popularity", "\"Hello, Goodbye\",85", "Solo,85"])
+            );
+        }
+
+        #[test]
+        fn tsv_can_leave_out_the_header() {
+            let lines = format_rows(
+                vec![track("1", "a\tb", &["The Beatles"], "Help!")],
+                strings(&["name", "album_name"]),
+                &OutputFormat::Tsv,
+                false,
+            )
+            .unwrap();
+
+            assert_eq!(lines, strings(&["a\\tb\tHelp!"]));
+        }
+
+        #[test]
+        fn json_keeps_native_types() {
+            let lines = format(
+                vec![track(
+                    "1",
+                    "Help!",
+                    &["The Beatles", "George Martin"],
+                    "Help!",
+                )],
+                &["name", "popularity", "release_date", "artists"],
+                OutputFormat::Json,
+            );
+            let json: serde_json::Value = serde_json::from_str(&lines.join("\n")).unwrap();
+
+            assert_eq!(
+                json,
+                serde_json::json!([{
+                    "name": "Help!",
+                    "popularity": 85,
+                    "release_date": "2013-09-09T00:00:00.000",
+                    "artists": ["The Beatles", "George Martin"]
+                }])
+            );
+        }
 
-        println!("{}", lines.join("\n"))
+        #[test]
+        fn ndjson_has_an_object_per_line() {
+            let lines = format(
+                vec![
+                    track("1", "Help!", &["The Beatles"], "Help!"),
+                    track("2", "Solo", &["Someone"], "Solo"),
+                ],
+                &["id", "duration"],
+                OutputFormat::Ndjson,
+            );
+
+            assert_eq!(
+                lines,
+                strings(&[
+                    "{\"id\":\"1\",\"duration\":187400}",
+                    "{\"id\":\"2\",\"duration\":187400}"
+                ])
+            );
+        }
+
+        #[test]
+        fn markdown_escapes_pipes() {
+            assert_eq!(markdown_field("a|b".to_string()), "a\\|b");
+            assert_eq!(markdown_field("a\\b".to_string()), "a\\\\b");
+            assert_eq!(markdown_field("two\r\nlines".to_string()), "two<br>lines");
+        }
+
+        #[test]
+        fn markdown_right_aligns_numbers() {
+            let lines = format(
+                vec![
+                    track("1", "Help!", &["The Beatles"], "Help!"),
+                    track("2", "A|B", &["Someone"], "Solo"),
+                ],
+                &["name", "popularity"],
+                OutputFormat::Markdown,
+            );
+
+            assert_eq!(
+                lines,
+                strings(&[
+                    "| name  | popularity |",
+                    "| ----- | ---------: |",
+                    "| Help! |         85 |",
+                    "| A\\|B  |         85 |",
+                ])
+            );
+        }
+
+        #[test]
+        fn html_escapes_text() {
+            assert_eq!(
+                html_escape("<b>Tom & \"Jerry\"</b>"),
+                "&lt;b&gt;Tom &amp; &quot;Jerry&quot;&lt;/b&gt;"
+            );
+        }
+
+        #[test]
+        fn html_right_aligns_numbers() {
+            let lines = format(
+                vec![track("1", "Rock & <Roll>", &["The Beatles"], "Help!")],
+                &["name", "popularity"],
+                OutputFormat::Html,
+            );
+
+            assert!(
+                lines.contains(
+                    &"<thead><tr><th>name</th><th class=\"num\">popularity</th></tr></thead>"
+                        .to_string()
+                )
+            );
+            assert!(lines.contains(
+                &"<tr><td>Rock &amp; &lt;Roll&gt;</td><td class=\"num\">85</td></tr>".to_string()
+            ));
+        }
+
+        #[test]
+        fn m3u8_lists_track_uris() {
+            let lines = format(
+                vec![
+                    track("1", "Help!", &["The Beatles"], "Help!"),
+                    track("2", "Two\nLines", &["Someone", "Else"], "Solo"),
+                ],
+                &["name"],
+                OutputFormat::M3u8,
+            );
+
+            assert_eq!(
+                lines,
+                strings(&[
+                    "#EXTM3U",
+                    "#EXTINF:187,The Beatles - Help!",
+                    "spotify:track:1",
+                    "#EXTINF:187,Someone, Else - Two Lines",
+                    "spotify:track:2",
+                ])
+            );
+        }
+
+        #[test]
+        fn xspf_escapes_xml() {
+            let lines = format(
+                vec![track("1", "Rock & Roll", &["<Someone>"], "\"Quoted\"")],
+                &["name"],
+                OutputFormat::Xspf,
+            );
+
+            assert_eq!(
+                lines[3..],
+                strings(&[
+                    "    <track>",
+                    "      <location>spotify:track:1</location>",
+                    "      <title>Rock &amp; Roll</title>",
+                    "      <creator>&lt;Someone&gt;</creator>",
+                    "      <album>&quot;Quoted&quot;</album>",
+                    "      <duration>187400</duration>",
+                    "    </track>",
+                    "  </trackList>",
+                    "</playlist>",
+                ])
+            );
+        }
+
+        #[test]
+        fn aggregations_are_objects() {
+            let data = HashMap::from([
+                ("popularity".to_string(), AggregationResult::Float(64.4)),
+                ("duration".to_string(), AggregationResult::Float(200000.0)),
+            ]);
+
+            let lines = format_aggregation(
+                Aggregation::Average,
+                data.clone(),
+                strings(&["popularity", "duration"]),
+                &OutputFormat::Ndjson,
+                true,
+            )
+            .unwrap();
+            assert_eq!(
+                lines,
+                strings(&["{\"AVERAGE(popularity)\":64.4,\"AVERAGE(duration)\":200000.0}"])
+            );
+
+            let lines = format_aggregation(
+                Aggregation::Average,
+                data,
+                strings(&["popularity"]),
+                &OutputFormat::Json,
+                true,
+            )
+            .unwrap();
+            let json: serde_json::Value = serde_json::from_str(&lines.join("\n")).unwrap();
+            assert_eq!(json, serde_json::json!([{ "AVERAGE(popularity)": 64.4 }]));
+        }
     }
 }
@@ -0,0 +1,179 @@
+use crate::query::data::{KeyAccess, PlaylistData, TrackData};
+use crate::query::value::Value;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// How two tracks are decided to be the same song
+#[derive(Clone, PartialEq, Debug)]
+pub enum DuplicateMatch {
+    /// The same track id
+    Id,
+    /// The same name and artists once normalised, catches the same song on a different release
+    Name,
+}
+
+impl Display for DuplicateMatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                DuplicateMatch::Id => "Id",
+                DuplicateMatch::Name => "Name",
+            }
+        )
+    }
+}
+
+/// Which copy of a duplicated track DEDUPE leaves in the playlist
+#[derive(Clone, PartialEq, Debug)]
+pub enum Keep {
+    First,
+    Last,
+    MostPopular,
+}
+
+/// One copy of a track that appears more than once
+#[derive(Clone, Debug, Default)]
+pub struct Duplicate {
+    /// Numbered from 1, every copy of the same song has the same group
+    pub group: i64,
+    pub playlist: String,
+    /// Where the copy is in its playlist, starting at 0
+    pub position: i64,
+    pub track: TrackData,
+}
+
+impl KeyAccess for Duplicate {
+    fn access<T>(&self, key: T) -> Result<Value, String>
+    where
+        T: AsRef<str> + Display,
+    {
+        match key.as_ref() {
+            "duplicate_group" => Ok(Value::Int(self.group)),
+            "playlist_name" => Ok(Value::Str(self.playlist.clone())),
+            "position" => Ok(Value::Int(self.position)),
+            _ => self.track.access(key),
+        }
+    }
+
+    fn attributes() -> Vec<String> {
+        let mut attributes: Vec<String> = ["duplicate_group", "playlist_name", "position"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        attributes.extend(TrackData::attributes());
+
+        attributes
+    }
+}
+
+/// Lowercase the name and drop anything in brackets or after " - ", e.g. "Song - 2011 Remaster" and "Song (Live)" both become "song"
+fn normalise(name: &str) -> String {
+    let name = name.split(" - ").next().unwrap_or(name);
+
+    let mut res = String::new();
+    let mut depth = 0;
+
+    for c in name.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' if depth > 0 => depth -= 1,
+            _ if depth == 0 && c.is_alphanumeric() => res.extend(c.to_lowercase()),
+            _ if depth == 0 && c.is_whitespace() && !res.ends_with(' ') => res.push(' '),
+            _ => {}
+        }
+    }
+
+    res.trim().to_string()
+}
+
+fn match_key(track: &TrackData, duplicate_match: &DuplicateMatch) -> String {
+    match duplicate_match {
+        DuplicateMatch::Id => track.id.clone(),
+        DuplicateMatch::Name => {
+            let mut artists: Vec<String> = track.artists.iter().map(|x| normalise(x)).collect();
+            artists.sort();
+
+            format!("{}|{}", normalise(&track.name), artists.join("|"))
+        }
+    }
+}
+
+/// Every copy of the tracks that appear more than once across the playlists, grouped in the order they're first seen
+pub fn find_duplicates(
+    playlists: &[PlaylistData],
+    duplicate_match: &DuplicateMatch,
+) -> Vec<Duplicate> {
+    let mut groups: Vec<Vec<Duplicate>> = Vec::new();
+    let mut group_index: HashMap<String, usize> = HashMap::new();
+
+    for playlist in playlists {
+        for (position, track) in playlist.tracks.iter().enumerate() {
+            let key = match_key(track, duplicate_match);
+            let copy = Duplicate {
+                group: 0,
+                playlist: playlist.name.clone(),
+                position: position as i64,
+                track: track.clone(),
+            };
+
+            match group_index.get(&key) {
+                Some(i) => groups[*i].push(copy),
+                None => {
+                    group_index.insert(key, groups.len());
+                    groups.push(vec![copy]);
+                }
+            }
+        }
+    }
+
+    groups
+        .into_iter()
+        .filter(|copies| copies.len() > 1)
+        .enumerate()
+        .flat_map(|(i, copies)| {
+            copies.into_iter().map(move |mut x| {
+                x.group = i as i64 + 1;
+                x
+            })
+        })
+        .collect()
+}
+
+/// The positions of the copies to remove so that only one of each song is left in the playlist
+pub fn dedupe_positions(
+    playlist: &PlaylistData,
+    duplicate_match: &DuplicateMatch,
+    keep: &Keep,
+) -> Vec<usize> {
+    let duplicates = find_duplicates(std::slice::from_ref(playlist), duplicate_match);
+
+    let mut positions: Vec<usize> = Vec::new();
+
+    for group in duplicates.chunk_by(|a, b| a.group == b.group) {
+        // copies are in playlist order so the first is the earliest
+        let kept = match keep {
+            Keep::First => 0,
+            Keep::Last => group.len() - 1,
+            Keep::MostPopular => {
+                let mut best = 0;
+                for (i, copy) in group.iter().enumerate() {
+                    if copy.track.popularity > group[best].track.popularity {
+                        best = i;
+                    }
+                }
+                best
+            }
+        };
+
+        for (i, copy) in group.iter().enumerate() {
+            if i != kept {
+                positions.push(copy.position as usize);
+            }
+        }
+    }
+
+    positions.sort();
+    positions
+}
@@ -0,0 +1,286 @@
+use crate::query::condition::{Condition, compute_conditions};
+use crate::query::data::KeyAccess;
+use crate::query::statements::{Aggregation, OrderBy, RunningAggregate};
+use crate::query::tokenise::Order;
+use crate::query::value::Value;
+use crate::utils::sort::compare;
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
+/// Something that produces a value for each row, e.g. a SELECT item or an ORDER BY key
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expression {
+    Attribute(String),
+    /// Only valid on grouped rows, where the aggregation has already been computed
+    Aggregate(Aggregation, String),
+    /// Computed across all the rows once they have been filtered and grouped
+    Window(Window),
+    Case(Case),
+    /// One row for each element of a list attribute, the rows are expanded before anything else is computed
+    Unnest(String),
+}
+
+impl Expression {
+    /// The name of the column holding the result of the expression
+    pub fn name(&self) -> String {
+        match self {
+            Expression::Attribute(res) => res.clone(),
+            Expression::Aggregate(aggregation, attr) => aggregation.format(attr),
+            Expression::Window(window) => window.to_string(),
+            Expression::Case(_) => "CASE".to_string(),
+            Expression::Unnest(attr) => format!("UNNEST({})", attr),
+        }
+    }
+
+    /// The attributes of the data the expression reads
+    pub fn attributes(&self) -> Vec<String> {
+        match self {
+            Expression::Attribute(res)
+            | Expression::Aggregate(_, res)
+            | Expression::Unnest(res) => {
+                vec![res.clone()]
+            }
+            Expression::Window(window) => {
+                let mut attributes = window.partition_by.clone();
+
+                if let WindowFunction::Aggregate(_, res) = &window.function {
+                    attributes.push(res.clone());
+                }
+
+                for key in &window.order {
+                    attributes.extend(key.expression.attributes());
+                }
+
+                attributes
+            }
+            Expression::Case(case) => case
+                .branches
+                .iter()
+                .flat_map(|(condition, _)| condition.attributes())
+                .collect(),
+        }
+    }
+
+    /// Whether the expression uses an aggregation, so it can only be worked out once the rows are grouped
+    pub fn has_aggregate(&self) -> bool {
+        match self {
+            Expression::Aggregate(..) => true,
+            Expression::Case(case) => case
+                .branches
+                .iter()
+                .any(|(condition, _)| condition.has_aggregate()),
+            _ => false,
+        }
+    }
+
+    pub fn evaluate<T>(&self, data: &T) -> Result<Value, String>
+    where
+        T: KeyAccess,
+    {
+        match self {
+            Expression::Attribute(res) => data.access(res),
+            Expression::Aggregate(..) | Expression::Window(..) | Expression::Unnest(_) => {
+                data.access(self.name())
+            }
+            Expression::Case(case) => case.evaluate(data),
+        }
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum WindowFunction {
+    RowNumber,
+    Rank,
+    DenseRank,
+    /// A running aggregation, e.g. SUM(duration) OVER (ORDER BY added_at)
+    Aggregate(Aggregation, String),
+}
+
+impl Display for WindowFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                WindowFunction::RowNumber => "ROW_NUMBER()".to_string(),
+                WindowFunction::Rank => "RANK()".to_string(),
+                WindowFunction::DenseRank => "DENSE_RANK()".to_string(),
+                WindowFunction::Aggregate(aggregation, attr) => aggregation.format(attr),
+            }
+        )
+    }
+}
+
+/// A function applied over a partition of the rows, e.g. ROW_NUMBER() OVER (PARTITION BY artists ORDER BY popularity DESC)
+#[derive(Debug, PartialEq, Clone)]
+pub struct Window {
+    pub function: WindowFunction,
+    pub partition_by: Vec<String>,
+    pub order: OrderBy,
+}
+
+impl Window {
+    /// Compute the function for each row of a partition that is already in window order
+    pub fn compute<T>(&self, partition: &[T]) -> Result<Vec<Value>, String>
+    where
+        T: KeyAccess,
+    {
+        let mut values: Vec<Value> = Vec::with_capacity(partition.len());
+
+        let mut rank: usize = 0;
+        let mut dense_rank: usize = 0;
+        // running aggregates include every row up to the end of the current row's peers (rows equal in the window order)
+        let mut running = RunningAggregate::default();
+        let mut running_value = Value::Null;
+
+        for i in 0..partition.len() {
+            if i == 0 || compare(&partition[i - 1], &partition[i], &self.order)? != Ordering::Equal
+            {
+                rank = i + 1;
+                dense_rank += 1;
+
+                if let WindowFunction::Aggregate(aggregation, attr) = &self.function {
+                    // only the new peer group is added to the total so far
+                    running.add(aggregation, &partition[i], attr)?;
+                    let mut peer_end = i + 1;
+
+                    while peer_end < partition.len()
+                        && compare(&partition[i], &partition[peer_end], &self.order)?
+                            == Ordering::Equal
+                    {
+                        running.add(aggregation, &partition[peer_end], attr)?;
+                        peer_end += 1;
+                    }
+
+                    running_value = running.value(aggregation, attr)?;
+                }
+            }
+
+            values.push(match &self.function {
+                WindowFunction::RowNumber => Value::Int((i + 1) as i64),
+                WindowFunction::Rank => Value::Int(rank as i64),
+                WindowFunction::DenseRank => Value::Int(dense_rank as i64),
+                WindowFunction::Aggregate(..) => running_value.clone(),
+            });
+        }
+
+        Ok(values)
+    }
+}
+
+impl Display for Window {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut definition: Vec<String> = Vec::new();
+
+        if !self.partition_by.is_empty() {
+            definition.push(format!("PARTITION BY {}", self.partition_by.join(", ")));
+        }
+
+        if !self.order.is_empty() {
+            definition.push(format!(
+                "ORDER BY {}",
+                self.order
+                    .iter()
+                    .map(|x| match x.order {
+                        Order::Ascending => x.expression.name(),
+                        Order::Descending => format!("{} DESC", x.expression.name()),
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ));
+        }
+
+        write!(f, "{} OVER ({})", self.function, definition.join(" "))
+    }
+}
+
+/// CASE WHEN a THEN x WHEN b THEN y ELSE z END, a simple CASE (CASE attr WHEN value THEN x END) is stored as equality conditions
+#[derive(Debug, PartialEq, Clone)]
+pub struct Case {
+    pub branches: Vec<(Condition, Value)>,
+    /// The ELSE value, null when there is no ELSE
+    pub default: Value,
+}
+
+impl Case {
+    /// Every result must have the same type so the column can be compared and ordered, ints are widened to floats if needed
+    pub fn new(mut branches: Vec<(Condition, Value)>, mut default: Value) -> Result<Self, String> {
+        let mut results: Vec<&mut Value> = branches.iter_mut().map(|x| &mut x.1).collect();
+        results.push(&mut default);
+
+        let has_float = results.iter().any(|x| matches!(x, Value::Float(_)));
+
+        if has_float {
+            for result in results.iter_mut() {
+                if let Value::Int(res) = result {
+                    **result = Value::Float(*res as f64);
+                }
+            }
+        }
+
+        let mut kind: Option<&Value> = None;
+
+        for result in &results {
+            if **result == Value::Null {
+                continue;
+            }
+
+            match kind {
+                Some(res) if std::mem::discriminant(res) != std::mem::discriminant(*result) => {
+                    return Err(format!(
+                        "SYNTAX ERROR: All the results of a CASE must be the same type, found {} and {}",
+                        res, result
+                    ));
+                }
+                Some(_) => {}
+                None => kind = Some(result),
+            }
+        }
+
+        Ok(Self { branches, default })
+    }
+
+    /// The result of the first branch whose condition holds, otherwise the default
+    pub fn evaluate<T>(&self, data: &T) -> Result<Value, String>
+    where
+        T: KeyAccess,
+    {
+        for (condition, result) in &self.branches {
+            if compute_conditions(data, condition)? {
+                return Ok(result.clone());
+            }
+        }
+
+        Ok(self.default.clone())
+    }
+}
+
+/// An item in the SELECT list
+#[derive(Debug, PartialEq, Clone)]
+pub struct Column {
+    pub expression: Expression,
+    pub alias: Option<String>,
+}
+
+impl Column {
+    pub fn new(expression: Expression) -> Self {
+        Self {
+            expression,
+            alias: None,
+        }
+    }
+
+    /// The name the column is displayed and referenced with
+    pub fn name(&self) -> String {
+        match &self.alias {
+            Some(res) => res.clone(),
+            None => self.expression.name(),
+        }
+    }
+}
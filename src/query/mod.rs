@@ -2,6 +2,8 @@ pub mod condition;
 pub mod data;
 pub mod deserialise;
 pub mod display;
+pub mod duplicates;
+pub mod expression;
 pub mod parse;
 pub mod run;
 pub mod statements;
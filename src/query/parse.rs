@@ -1,8 +1,20 @@
-use crate::query::condition::Condition;
-use crate::query::data::{AlbumData, KeyAccess, PlaylistData, TrackData};
-use crate::query::statements::{Aggregation, OrderBy, SelectStatement};
+use crate::history::HistoryRecord;
+use crate::play_log::Play;
+use crate::query::condition::{Condition, Operand};
+use crate::query::data::{
+    AlbumData, ArtistData, KeyAccess, PlaylistData, TopArtist, TopTrack, TrackData,
+};
+use crate::query::duplicates::{Duplicate, DuplicateMatch, Keep};
+use crate::query::expression::{Case, Column, Expression, Window, WindowFunction};
+use crate::query::statements::{
+    Aggregation, OrderBy, OrderKey, OutputFile, SelectStatement, Statement,
+};
 use crate::query::tokenise::{DataSource, Logical, Operator, Order, Token};
 use crate::query::value::Value;
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+type TokenIter = Peekable<IntoIter<Token>>;
 
 fn safe_next(iter: &mut dyn Iterator<Item = Token>) -> Result<Token, String> {
     match iter.next() {
@@ -19,7 +31,68 @@ fn split_aggregated_attributes(attributes: String) -> Vec<String> {
         .collect::<Vec<String>>()
 }
 
-fn parse_where(tokens: &mut dyn Iterator<Item = Token>) -> Result<Option<Condition>, String> {
+/// Tokens that start a new clause and so end the one currently being parsed
+fn is_clause_keyword(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::WHERE | Token::GROUP | Token::HAVING | Token::QUALIFY | Token::ORDER | Token::INTO
+    )
+}
+
+/// The aggregation and attributes of an aggregation token e.g. COUNT(name)
+fn aggregate_token(token: &Token) -> Option<(Aggregation, String)> {
+    match token {
+        Token::COUNT(res) => Some((Aggregation::Count, res.clone())),
+        Token::AVERAGE(res) => Some((Aggregation::Average, res.clone())),
+        Token::SUM(res) => Some((Aggregation::Sum, res.clone())),
+        _ => None,
+    }
+}
+
+/// Add the aggregation of each attribute to the aggregate list if it is not already there, returns an expression for each aggregation
+fn register_aggregates(
+    aggregation: Aggregation,
+    attributes: String,
+    aggregates: &mut Vec<(Aggregation, String)>,
+) -> Vec<Expression> {
+    let mut columns: Vec<Expression> = Vec::new();
+
+    for attr in split_aggregated_attributes(attributes) {
+        columns.push(Expression::Aggregate(aggregation.clone(), attr.clone()));
+
+        let aggregate = (aggregation.clone(), attr);
+        if !aggregates.contains(&aggregate) {
+            aggregates.push(aggregate);
+        }
+    }
+
+    columns
+}
+
+/// One side of a condition, an attribute, a value or an expression like CASE or COUNT(name) that is worked out for each row
+fn parse_operand(
+    token: Token,
+    tokens: &mut TokenIter,
+    aggregates: &mut Vec<(Aggregation, String)>,
+) -> Result<Option<Operand>, String> {
+    match token {
+        Token::Attribute(res) => Ok(Some(Operand::Attribute(res))),
+        Token::Value(res) => Ok(Some(Operand::Value(res))),
+        res if starts_expression(&res) => match parse_expression(res, tokens, aggregates)? {
+            Expression::Window(res) => Err(format!(
+                "SYNTAX ERROR: {} can't be used in a condition, name it with AS and use QUALIFY",
+                res
+            )),
+            res => Ok(Some(Operand::Expression(res))),
+        },
+        _ => Ok(None),
+    }
+}
+
+fn parse_where(
+    tokens: &mut TokenIter,
+    aggregates: &mut Vec<(Aggregation, String)>,
+) -> Result<Option<Condition>, String> {
     // get conditions
     let mut tl_condition: Option<Condition> = None;
     let mut next_logical_op: Logical = Logical::Or; // should never get used, just to avoid uninitialised error below
@@ -31,106 +104,82 @@ fn parse_where(tokens: &mut dyn Iterator<Item = Token>) -> Result<Option<Conditi
     */
 
     loop {
-        let attr_t = match tokens.next() {
-            Some(res) => res,
+        // every condition should be made up of an operand, an operator and an operand
+        let lhs = match tokens.next() {
+            Some(res) => match parse_operand(res.clone(), tokens, aggregates)? {
+                Some(operand) => operand,
+                None => {
+                    return Err(format!(
+                        "SYNTAX ERROR: Condition is missing attribute at {}",
+                        res
+                    ));
+                }
+            },
             None => return Err(
                 "SYNTAX ERROR: Conditions should consist of an attribute, an operator and a value"
                     .to_string(),
             ),
         };
-        let attr: String;
-        let op: Operator;
-        let val: Value;
 
-        if let Token::Attribute(res) = attr_t {
-            // every condition should be made up of 3 tokens
-            attr = res;
-
-            op = match tokens.next() {
-                Some(res) => match res {
-                    Token::Operator(res) => res,
-                    _ => {
-                        return Err(format!("SYNTAX ERROR: Condition is missing operator at {}", res))
-                    }
-                },
-                None => {
-                    return Err("SYNTAX ERROR: Conditions should consist of an attribute, an operator and a value".to_string())
+        let mut op = match tokens.next() {
+            Some(res) => match res {
+                Token::Operator(res) => res,
+                _ => {
+                    return Err(format!(
+                        "SYNTAX ERROR: Condition is missing operator at {}",
+                        res
+                    ));
                 }
-            };
+            },
+            None => return Err(
+                "SYNTAX ERROR: Conditions should consist of an attribute, an operator and a value"
+                    .to_string(),
+            ),
+        };
 
-            if op == Operator::NotIn {
-                // ignore the IN token that should be after a NOT token
-                if let Some(Token::Operator(res)) = tokens.next() {
-                    if res != Operator::In {
-                        return Err(
-                            "SYNTAX ERROR: NOT can only be used to negate an IN operation."
-                                .to_string(),
-                        );
-                    }
+        if op == Operator::NotIn {
+            // ignore the IN token that should be after a NOT token
+            if let Some(Token::Operator(res)) = tokens.next() {
+                if res != Operator::In {
+                    return Err(
+                        "SYNTAX ERROR: NOT can only be used to negate an IN operation.".to_string(),
+                    );
                 }
             }
+        }
 
-            val = match tokens.next() {
-                Some(res) => match res {
-                    Token::Value(res) => res,
-                    _ => {
-                        return Err(format!("SYNTAX ERROR: Condition is missing value at {}", res))
-                    }
-                },
-                None => {
-                    return Err("SYNTAX ERROR: Conditions should consist of an attribute, an operator and a value".to_string())
-                }
-            };
-        } else if let Token::Value(res) = attr_t {
-            // this branch only happens when checking if a value is in a list e.g. WHERE "Arctic Monkeys" IN artists
-            val = res;
-
+        if op == Operator::ContainsAny {
             op = match tokens.next() {
-                Some(res) => match res {
-                    Token::Operator(res) => res,
-                    _ => {
-                        return Err(format!("SYNTAX ERROR: Condition is missing operator at {}", res))
-                    }
-                },
-                None => {
-                    return Err("SYNTAX ERROR: Conditions should consist of an attribute, an operator and a value".to_string())
+                Some(Token::ANY) => Operator::ContainsAny,
+                Some(Token::ALL) => Operator::ContainsAll,
+                _ => {
+                    return Err(
+                        "SYNTAX ERROR: CONTAINS must be followed by ANY or ALL.".to_string()
+                    );
                 }
             };
+        }
 
-            if op == Operator::NotIn {
-                // ignore the IN token that should be after a NOT token
-                if let Some(Token::Operator(res)) = tokens.next() {
-                    if res != Operator::In {
-                        return Err(
-                            "SYNTAX ERROR: NOT can only be used to negate an IN operation."
-                                .to_string(),
-                        );
-                    }
-                }
-            }
-
-            attr = match tokens.next() {
-                Some(res) => match res {
-                    Token::Attribute(res) => res,
-                    _ => {
-                        return Err(format!("SYNTAX ERROR: Condition is missing value at {}", res))
-                    }
-                },
+        let rhs = match tokens.next() {
+            Some(res) => match parse_operand(res.clone(), tokens, aggregates)? {
+                Some(operand) => operand,
                 None => {
-                    return Err("SYNTAX ERROR: Conditions should consist of an attribute, an operator and a value".to_string())
+                    return Err(format!(
+                        "SYNTAX ERROR: Condition is missing value at {}",
+                        res
+                    ));
                 }
-            };
-        } else {
-            return Err(format!(
-                "SYNTAX ERROR: Condition is missing attribute at {}",
-                attr_t
-            ));
-        }
+            },
+            None => return Err(
+                "SYNTAX ERROR: Conditions should consist of an attribute, an operator and a value"
+                    .to_string(),
+            ),
+        };
 
         let temp = Condition {
-            attribute: attr,
+            lhs,
             operation: op,
-            value: val,
+            rhs,
             next: None,
         };
         if tl_condition.is_some() {
@@ -142,6 +191,12 @@ fn parse_where(tokens: &mut dyn Iterator<Item = Token>) -> Result<Option<Conditi
             tl_condition = Some(temp);
         }
 
+        if let Some(next) = tokens.peek()
+            && is_clause_keyword(next)
+        {
+            break;
+        }
+
         match tokens.next() {
             Some(res) => match res {
                 Token::Logical(res) => next_logical_op = res,
@@ -159,15 +214,114 @@ fn parse_where(tokens: &mut dyn Iterator<Item = Token>) -> Result<Option<Conditi
     Ok(tl_condition)
 }
 
-fn parse_order(tokens: &mut dyn Iterator<Item = Token>) -> Result<Option<OrderBy>, String> {
+fn parse_group(tokens: &mut TokenIter) -> Result<Option<Vec<String>>, String> {
     let mut attributes: Vec<String> = Vec::new();
-    let mut order: Order = Order::Ascending;
 
     match tokens.next() {
         Some(res) => {
             match res {
                 Token::BY => {},
-                _ => return Err("SYNTAX ERROR: Incomplete compound keyword, ORDER must be followed with BY. (ORDER BY ... ASC)".to_string())
+                _ => return Err("SYNTAX ERROR: Incomplete compound keyword, GROUP must be followed with BY. (GROUP BY a, b, c)".to_string())
+            }
+        },
+        None => {
+            return Err("SYNTAX ERROR: Incorrect syntax at GROUP".to_string())
+        }
+    };
+
+    while let Some(Token::Attribute(_)) = tokens.peek() {
+        if let Some(Token::Attribute(attr)) = tokens.next() {
+            attributes.push(attr)
+        }
+    }
+
+    if attributes.is_empty() {
+        return Err(
+            "SYNTAX ERROR: Group statement has no attributes, must be in the form 'GROUP BY a, b, c'"
+                .to_string(),
+        );
+    }
+
+    Ok(Some(attributes))
+}
+
+/// An aggregation used outside the SELECT list can only be applied to one attribute
+fn single_aggregate(
+    aggregation: Aggregation,
+    attributes: String,
+    aggregates: &mut Vec<(Aggregation, String)>,
+) -> Result<Expression, String> {
+    let mut expressions = register_aggregates(aggregation, attributes, aggregates);
+
+    if expressions.len() != 1 {
+        return Err(format!(
+            "SYNTAX ERROR: Only one attribute can be aggregated at {}",
+            expressions
+                .iter()
+                .map(|x| x.name())
+                .collect::<Vec<String>>()
+                .join(", ")
+        ));
+    }
+
+    Ok(expressions.remove(0))
+}
+
+/// Whether the token is the start of an expression that gives each row a single value
+fn starts_expression(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Attribute(_)
+            | Token::COUNT(_)
+            | Token::AVERAGE(_)
+            | Token::SUM(_)
+            | Token::WindowFunction(_)
+            | Token::CASE
+    )
+}
+
+/// An attribute, aggregation, window function or CASE, parsed the same way in the SELECT list and ORDER BY
+fn parse_expression(
+    token: Token,
+    tokens: &mut TokenIter,
+    aggregates: &mut Vec<(Aggregation, String)>,
+) -> Result<Expression, String> {
+    match token {
+        Token::Attribute(res) => Ok(Expression::Attribute(res)),
+        Token::WindowFunction(res) => Ok(Expression::Window(parse_window(tokens, res)?)),
+        Token::CASE => Ok(Expression::Case(parse_case(tokens, aggregates)?)),
+        res => match aggregate_token(&res) {
+            Some((aggregation, attributes)) if tokens.peek() == Some(&Token::OVER) => {
+                // an aggregation over a window rather than a group e.g. SUM(duration) OVER (ORDER BY added_at)
+                let function = WindowFunction::Aggregate(aggregation, attributes.replace(" ", ""));
+                Ok(Expression::Window(parse_window(tokens, function)?))
+            }
+            Some((aggregation, attributes)) => {
+                single_aggregate(aggregation, attributes, aggregates)
+            }
+            None => Err(format!("SYNTAX ERROR: Expected an expression at {}", res)),
+        },
+    }
+}
+
+/// Whether any of the keys is a window function
+fn orders_by_window(order: &OrderBy) -> bool {
+    order
+        .iter()
+        .any(|x| matches!(x.expression, Expression::Window(_)))
+}
+
+fn parse_order(
+    tokens: &mut TokenIter,
+    aggregates: &mut Vec<(Aggregation, String)>,
+) -> Result<Option<OrderBy>, String> {
+    let mut keys: OrderBy = Vec::new();
+
+    match tokens.next() {
+        Some(res) => {
+            match res {
+                Token::BY => {},
+                _ => return Err("SYNTAX ERROR: Incomplete compound keyword, ORDER must be followed with BY. (ORDER BY a ASC, b DESC)".to_string())
             }
         },
         None => {
@@ -176,45 +330,557 @@ fn parse_order(tokens: &mut dyn Iterator<Item = Token>) -> Result<Option<OrderBy
     };
 
     loop {
-        match tokens.next() {
-            Some(res) => {
-                match res {
-                    Token::OrderDirection(ord) => {
-                        order = ord;
-                        break;
-                    },
-                    Token::Attribute(attr) => {
-                        attributes.push(attr)
+        let expression = match tokens.peek() {
+            Some(token) if starts_expression(token) => {
+                let token = safe_next(tokens)?;
+                parse_expression(token, tokens, aggregates)?
+            }
+            _ => break,
+        };
+
+        // the direction is optional and defaults to ascending
+        let order = match tokens.peek() {
+            Some(Token::OrderDirection(res)) => {
+                let order = res.clone();
+                tokens.next();
+                order
+            }
+            _ => Order::Ascending,
+        };
+
+        // nulls go last when ascending and first when descending unless NULLS FIRST/LAST is given
+        let mut nulls_first = order == Order::Descending;
+
+        if tokens.peek() == Some(&Token::NULLS) {
+            tokens.next();
+
+            nulls_first = match safe_next(tokens)? {
+                Token::FIRST => true,
+                Token::LAST => false,
+                res => {
+                    return Err(format!(
+                        "SYNTAX ERROR: NULLS must be followed by FIRST or LAST, error at {}",
+                        res
+                    ));
+                }
+            };
+        }
+
+        keys.push(OrderKey {
+            expression,
+            order,
+            nulls_first,
+        });
+    }
+
+    if keys.is_empty() {
+        return Err("SYNTAX ERROR: Order statement has no attributes, must be in the form 'ORDER BY a ASC, b DESC NULLS LAST'".to_string());
+    }
+
+    Ok(Some(keys))
+}
+
+/// Parse the OVER (PARTITION BY a, b ORDER BY c DESC) part of a window function
+fn parse_window(tokens: &mut TokenIter, function: WindowFunction) -> Result<Window, String> {
+    match safe_next(tokens)? {
+        Token::OVER => {}
+        res => {
+            return Err(format!(
+                "SYNTAX ERROR: {} must be followed by OVER (...), error at {}",
+                function, res
+            ));
+        }
+    }
+
+    let definition = match safe_next(tokens)? {
+        Token::Group(res) => res,
+        res => {
+            return Err(format!(
+                "SYNTAX ERROR: OVER must be followed by a window definition e.g. OVER (PARTITION BY a ORDER BY b DESC), error at {}",
+                res
+            ));
+        }
+    };
+
+    let mut definition = definition.into_iter().peekable();
+    let mut partition_by: Vec<String> = Vec::new();
+    let mut order: OrderBy = Vec::new();
+
+    while let Some(token) = definition.next() {
+        match token {
+            Token::PARTITION => {
+                if safe_next(&mut definition)? != Token::BY {
+                    return Err("SYNTAX ERROR: Incomplete compound keyword, PARTITION must be followed with BY. (PARTITION BY a, b)".to_string());
+                }
+
+                while let Some(Token::Attribute(_)) = definition.peek() {
+                    if let Some(Token::Attribute(attr)) = definition.next() {
+                        partition_by.push(attr)
+                    }
+                }
+
+                if partition_by.is_empty() {
+                    return Err("SYNTAX ERROR: Partition statement has no attributes, must be in the form 'PARTITION BY a, b'".to_string());
+                }
+            }
+            Token::ORDER => {
+                // the window can only be ordered by the attributes of each row
+                let mut window_aggregates: Vec<(Aggregation, String)> = Vec::new();
+
+                order = parse_order(&mut definition, &mut window_aggregates)?.unwrap_or_default();
+
+                if !window_aggregates.is_empty() {
+                    return Err(
+                        "SYNTAX ERROR: A window cannot be ordered by an aggregation".to_string()
+                    );
+                }
+
+                if orders_by_window(&order) {
+                    return Err(
+                        "SYNTAX ERROR: A window cannot be ordered by another window function"
+                            .to_string(),
+                    );
+                }
+            }
+            res => {
+                return Err(format!(
+                    "SYNTAX ERROR: Invalid token in window definition at {}",
+                    res
+                ));
+            }
+        }
+    }
+
+    if let WindowFunction::Aggregate(_, attr) = &function
+        && attr.contains(',')
+    {
+        return Err(format!(
+            "SYNTAX ERROR: Only one attribute can be aggregated at {}",
+            function
+        ));
+    }
+
+    Ok(Window {
+        function,
+        partition_by,
+        order,
+    })
+}
+
+/// Parse a searched (CASE WHEN a > 1 THEN x ... END) or simple (CASE a WHEN 1 THEN x ... END) CASE expression
+fn parse_case(
+    tokens: &mut TokenIter,
+    aggregates: &mut Vec<(Aggregation, String)>,
+) -> Result<Case, String> {
+    // the attribute a simple CASE compares against
+    let subject = match tokens.peek() {
+        Some(Token::Attribute(res)) => {
+            let subject = res.clone();
+            tokens.next();
+            Some(subject)
+        }
+        _ => None,
+    };
+
+    let mut branches: Vec<(Condition, Value)> = Vec::new();
+    let mut default = Value::Null;
+
+    loop {
+        match safe_next(tokens)? {
+            Token::WHEN => {
+                let mut condition_tokens: Vec<Token> = Vec::new();
+                // a CASE inside the condition has its own THEN
+                let mut depth = 0;
+
+                loop {
+                    match safe_next(tokens)? {
+                        Token::THEN if depth == 0 => break,
+                        res => {
+                            match res {
+                                Token::CASE => depth += 1,
+                                Token::END => depth -= 1,
+                                _ => {}
+                            }
+
+                            condition_tokens.push(res)
+                        }
+                    }
+                }
+
+                let condition = match &subject {
+                    Some(attr) => match condition_tokens.as_slice() {
+                        [Token::Value(res)] => Condition {
+                            lhs: Operand::Attribute(attr.clone()),
+                            operation: Operator::Equals,
+                            rhs: Operand::Value(res.clone()),
+                            next: None,
+                        },
+                        _ => {
+                            return Err(format!(
+                                "SYNTAX ERROR: WHEN in a CASE on {} must be followed by a single value",
+                                attr
+                            ));
+                        }
                     },
-                    _ => return Err("SYNTAX ERROR: Invalid tokens in order statement, must be in the form 'ORDER BY a, b, c ASC'".to_string())
+                    None => {
+                        match parse_where(&mut condition_tokens.into_iter().peekable(), aggregates)?
+                        {
+                            Some(res) => res,
+                            None => {
+                                return Err("SYNTAX ERROR: WHEN must be followed by a condition"
+                                    .to_string());
+                            }
+                        }
+                    }
+                };
 
+                match safe_next(tokens)? {
+                    Token::Value(res) => branches.push((condition, res)),
+                    res => {
+                        return Err(format!(
+                            "SYNTAX ERROR: THEN must be followed by a value, error at {}",
+                            res
+                        ));
+                    }
+                }
+            }
+            Token::ELSE => match safe_next(tokens)? {
+                Token::Value(res) => default = res,
+                res => {
+                    return Err(format!(
+                        "SYNTAX ERROR: ELSE must be followed by a value, error at {}",
+                        res
+                    ));
                 }
             },
-            None => {
-                return Err("SYNTAX ERROR: Order statement not completed, must be in the form 'ORDER BY a, b, c ASC'".to_string())
+            Token::END => break,
+            res => {
+                return Err(format!(
+                    "SYNTAX ERROR: Expected WHEN, ELSE or END in CASE, error at {}",
+                    res
+                ));
             }
         }
     }
 
-    Ok(Some((attributes, order)))
+    if branches.is_empty() {
+        return Err("SYNTAX ERROR: CASE must have at least one WHEN ... THEN ...".to_string());
+    }
+
+    Case::new(branches, default)
+}
+
+/// The playlist a statement changes, e.g. the PLAYLIST(name) in INSERT INTO PLAYLIST(name)
+fn target_playlist(token: Token) -> Result<String, String> {
+    match token {
+        Token::Source(DataSource::Playlist(res)) if !res.is_empty() => Ok(res),
+        res => Err(format!(
+            "SYNTAX ERROR: Only playlists can be changed, expected PLAYLIST(name) at {}",
+            res
+        )),
+    }
+}
+
+/// MATCH ID or MATCH NAME, how duplicate tracks are found
+fn parse_match(token: Token) -> Result<DuplicateMatch, String> {
+    match token {
+        Token::Attribute(res) if res.eq_ignore_ascii_case("id") => Ok(DuplicateMatch::Id),
+        Token::Attribute(res) if res.eq_ignore_ascii_case("name") => Ok(DuplicateMatch::Name),
+        res => Err(format!(
+            "SYNTAX ERROR: MATCH must be followed by ID or NAME, error at {}",
+            res
+        )),
+    }
+}
+
+/// INTO 'file' [APPEND], where the results of a SELECT are saved
+fn parse_into(tokens: &mut TokenIter) -> Result<OutputFile, String> {
+    let path = match safe_next(tokens)? {
+        Token::Value(Value::Str(res)) => res,
+        res => {
+            return Err(format!(
+                "SYNTAX ERROR: INTO must be followed by a file name in quotes, error at {}",
+                res
+            ));
+        }
+    };
+
+    let append = tokens.peek() == Some(&Token::APPEND);
+    if append {
+        tokens.next();
+    }
+
+    Ok(OutputFile { path, append })
 }
 
-pub fn parse(_tokens: Vec<Token>) -> Result<SelectStatement, String> {
+/// The query choosing the tracks to add to a playlist, which must come from a playlist, saved album, Liked Songs, top tracks or recent plays
+fn track_select(tokens: Vec<Token>) -> Result<SelectStatement, String> {
+    let select = parse_select(tokens)?;
+
+    if select.into.is_some() {
+        return Err("SYNTAX ERROR: INTO can only be used with SELECT".to_string());
+    }
+
+    match select.source {
+        DataSource::Playlist(_)
+        | DataSource::SavedAlbum(_)
+        | DataSource::Liked
+        | DataSource::TopTracks(_)
+        | DataSource::RecentlyPlayed => Ok(select),
+        _ => Err(format!(
+            "SYNTAX ERROR: Tracks can only be taken from a playlist, saved album, LIKED, TOP_TRACKS or RECENTLY_PLAYED, not {}",
+            select.source
+        )),
+    }
+}
+
+pub fn parse(_tokens: Vec<Token>) -> Result<Statement, String> {
+    let mut tokens = _tokens.into_iter();
+
+    match tokens.next() {
+        Some(Token::SELECT) => {
+            let mut select_tokens = vec![Token::SELECT];
+            select_tokens.extend(tokens);
+
+            Ok(Statement::Select(parse_select(select_tokens)?))
+        }
+        Some(Token::CREATE) => {
+            // CREATE PLAYLIST "name" AS SELECT ...
+            match safe_next(&mut tokens)? {
+                Token::Source(DataSource::Playlist(res)) if res.is_empty() => {}
+                res => {
+                    return Err(format!(
+                        "SYNTAX ERROR: Only playlists can be created, error at {}",
+                        res
+                    ));
+                }
+            }
+
+            let name = match safe_next(&mut tokens)? {
+                Token::Value(Value::Str(res)) => res,
+                res => {
+                    return Err(format!(
+                        "SYNTAX ERROR: CREATE PLAYLIST must be followed by a name in quotes, error at {}",
+                        res
+                    ));
+                }
+            };
+
+            if safe_next(&mut tokens)? != Token::AS {
+                return Err(
+                    "SYNTAX ERROR: Must be in the form 'CREATE PLAYLIST \"name\" AS SELECT ...'"
+                        .to_string(),
+                );
+            }
+
+            Ok(Statement::CreatePlaylist(
+                name,
+                track_select(tokens.collect())?,
+            ))
+        }
+        Some(Token::INSERT) => {
+            // INSERT INTO PLAYLIST(name) SELECT ...
+            if safe_next(&mut tokens)? != Token::INTO {
+                return Err(
+                    "SYNTAX ERROR: Incomplete compound keyword, INSERT must be followed with INTO. (INSERT INTO PLAYLIST(name) SELECT ...)"
+                        .to_string(),
+                );
+            }
+
+            let name = target_playlist(safe_next(&mut tokens)?)?;
+
+            Ok(Statement::Insert(name, track_select(tokens.collect())?))
+        }
+        Some(Token::DELETE) => {
+            // DELETE FROM PLAYLIST(name) WHERE ..., the tracks to remove are found with SELECT id FROM PLAYLIST(name) WHERE ...
+            if safe_next(&mut tokens)? != Token::FROM {
+                return Err(
+                    "SYNTAX ERROR: DELETE must be followed with FROM. (DELETE FROM PLAYLIST(name) WHERE ...)"
+                        .to_string(),
+                );
+            }
+
+            let name = target_playlist(safe_next(&mut tokens)?)?;
+
+            let mut select_tokens = vec![
+                Token::SELECT,
+                Token::Attribute("id".to_string()),
+                Token::FROM,
+                Token::Source(DataSource::Playlist(name.clone())),
+            ];
+
+            match tokens.next() {
+                Some(Token::WHERE) => {
+                    select_tokens.push(Token::WHERE);
+                    select_tokens.extend(tokens);
+                }
+                Some(res) => {
+                    return Err(format!(
+                        "SYNTAX ERROR: Only a WHERE clause can come after DELETE FROM, error at {}",
+                        res
+                    ));
+                }
+                None => {}
+            }
+
+            let select = parse_select(select_tokens)?;
+
+            if select.into.is_some() {
+                return Err("SYNTAX ERROR: INTO can only be used with SELECT".to_string());
+            }
+
+            Ok(Statement::Delete(name, select))
+        }
+        Some(Token::RESTORE) => {
+            // RESTORE PLAYLIST(name) TO <timestamp>
+            let name = target_playlist(safe_next(&mut tokens)?)?;
+
+            if safe_next(&mut tokens)? != Token::TO {
+                return Err(
+                    "SYNTAX ERROR: RESTORE must be in the form 'RESTORE PLAYLIST(name) TO <timestamp>'"
+                        .to_string(),
+                );
+            }
+
+            let timestamp = match safe_next(&mut tokens)? {
+                Token::Value(Value::Int(res)) if res >= 0 => res.cast_unsigned(),
+                res => {
+                    return Err(format!(
+                        "SYNTAX ERROR: A snapshot timestamp must be a positive whole number, error at {}",
+                        res
+                    ));
+                }
+            };
+
+            match tokens.next() {
+                Some(res) => Err(format!(
+                    "SYNTAX ERROR: Unexpected token after RESTORE, error at {}",
+                    res
+                )),
+                None => Ok(Statement::Restore(name, timestamp)),
+            }
+        }
+        Some(Token::SHOW) => match (tokens.next(), tokens.next()) {
+            (Some(Token::SNAPSHOTS), None) => Ok(Statement::ShowSnapshots),
+            _ => Err("SYNTAX ERROR: SHOW must be followed by SNAPSHOTS".to_string()),
+        },
+        Some(Token::DEDUPE) => {
+            // DEDUPE PLAYLIST(name) [MATCH ID|NAME] [KEEP FIRST|LAST|MOST_POPULAR]
+            let name = target_playlist(safe_next(&mut tokens)?)?;
+
+            let mut duplicate_match = DuplicateMatch::Id;
+            let mut keep = Keep::First;
+
+            while let Some(token) = tokens.next() {
+                match token {
+                    Token::MATCH => duplicate_match = parse_match(safe_next(&mut tokens)?)?,
+                    Token::KEEP => {
+                        keep = match safe_next(&mut tokens)? {
+                            Token::FIRST => Keep::First,
+                            Token::LAST => Keep::Last,
+                            Token::Attribute(res) if res.eq_ignore_ascii_case("most_popular") => {
+                                Keep::MostPopular
+                            }
+                            res => {
+                                return Err(format!(
+                                    "SYNTAX ERROR: KEEP must be followed by FIRST, LAST or MOST_POPULAR, error at {}",
+                                    res
+                                ));
+                            }
+                        }
+                    }
+                    res => {
+                        return Err(format!(
+                            "SYNTAX ERROR: Only MATCH and KEEP can come after DEDUPE, error at {}",
+                            res
+                        ));
+                    }
+                }
+            }
+
+            Ok(Statement::Dedupe(name, duplicate_match, keep))
+        }
+        Some(Token::REORDER) => {
+            // REORDER PLAYLIST(name) BY a ASC, b DESC
+            let name = target_playlist(safe_next(&mut tokens)?)?;
+
+            // the ORDER BY parser starts at BY
+            let mut order_tokens: TokenIter = tokens.collect::<Vec<Token>>().into_iter().peekable();
+            let mut aggregates: Vec<(Aggregation, String)> = Vec::new();
+
+            let order = parse_order(&mut order_tokens, &mut aggregates)?;
+
+            if !aggregates.is_empty() {
+                return Err(
+                    "SYNTAX ERROR: A playlist can't be reordered by an aggregation".to_string(),
+                );
+            }
+
+            if order.as_ref().is_some_and(orders_by_window) {
+                return Err(
+                    "SYNTAX ERROR: A playlist can't be reordered by a window function".to_string(),
+                );
+            }
+
+            match (order_tokens.next(), order) {
+                (Some(res), _) => Err(format!(
+                    "SYNTAX ERROR: Unexpected token after REORDER, error at {}",
+                    res
+                )),
+                (None, Some(res)) => Ok(Statement::Reorder(name, res)),
+                (None, None) => Err("SYNTAX ERROR: REORDER must be followed by BY".to_string()),
+            }
+        }
+        Some(Token::PREVIEW) => {
+            // PREVIEW INSERT INTO ..., shows the changes a statement would make without making them
+            match parse(tokens.collect())? {
+                Statement::Select(_) | Statement::ShowSnapshots | Statement::Preview(_) => Err(
+                    "SYNTAX ERROR: PREVIEW must be followed by a CREATE, INSERT, DELETE or RESTORE statement"
+                        .to_string(),
+                ),
+                res => Ok(Statement::Preview(Box::new(res))),
+            }
+        }
+        Some(res) => Err(format!("SYNTAX ERROR: Invalid token at {}", res)),
+        None => Err("SYNTAX ERROR: Incomplete statement".to_string()),
+    }
+}
+
+/// Whether genres is used anywhere in the tokens, including inside window definitions
+fn uses_genres(tokens: &[Token]) -> bool {
+    tokens.iter().any(|x| match x {
+        Token::Attribute(res)
+        | Token::COUNT(res)
+        | Token::AVERAGE(res)
+        | Token::SUM(res)
+        | Token::UNNEST(res) => res == "genres",
+        Token::Group(res) => uses_genres(res),
+        _ => false,
+    })
+}
+
+fn parse_select(_tokens: Vec<Token>) -> Result<SelectStatement, String> {
     // if the tokens contain a COUNT then it's a SelectCount, otherwise it's a Select
     if _tokens.len() < 4 {
         return Err(
             "Must have a minimum of 4 tokens. e.g. 'SELECT name FROM Playlist(\"pl1\")".to_string(),
         );
     }
+    let genres = uses_genres(&_tokens);
     let mut tokens = _tokens.into_iter().peekable();
 
     // the first 4 tokens can be unwrapped safely because of the above check
     let statement_type = tokens.next().unwrap();
     if statement_type == Token::SELECT {
         let mut aggregation = Aggregation::None;
-        let mut targets: Vec<String> = Vec::new();
+        let mut columns: Vec<Column> = Vec::new();
+        let mut aggregates: Vec<(Aggregation, String)> = Vec::new();
 
         let mut attribute_wild_card = false;
+        // only a token that adds a single column can be given an alias
+        let mut can_alias = false;
+
+        let mut into: Option<OutputFile> = None;
 
         let mut reached_from = false;
         loop {
@@ -224,40 +890,61 @@ pub fn parse(_tokens: Vec<Token>) -> Result<SelectStatement, String> {
                 None => break,
             };
 
-            match attr.clone() {
-                Token::COUNT(res) => {
-                    if targets.len() != 0 {
-                        return Err(format!(
-                            "SYNTAX ERROR: Cannot mix aggregated attributes and non-aggregated attributes at {}",
-                            attr
-                        ));
-                    }
+            if attribute_wild_card && attr != Token::FROM && attr != Token::INTO {
+                return Err(format!(
+                    "SYNTAX ERROR: Cannot mix wildcard with specific attributes at {}",
+                    attr
+                ));
+            }
 
-                    targets = split_aggregated_attributes(res);
-                    aggregation = Aggregation::Count;
+            match attr.clone() {
+                Token::COUNT(_) | Token::AVERAGE(_) | Token::SUM(_)
+                    if tokens.peek() != Some(&Token::OVER) =>
+                {
+                    // aggregate_token always matches these tokens so the unwrap is safe
+                    let (aggregation, res) = aggregate_token(&attr).unwrap();
 
-                    // cannot mix aggregation and normal attributes to this has to be the end of the attribute section
-                    break;
+                    // COUNT(a, b) adds a column for each attribute
+                    let expressions = register_aggregates(aggregation, res, &mut aggregates);
+                    can_alias = expressions.len() == 1;
+                    columns.extend(expressions.into_iter().map(Column::new));
+                }
+                Token::UNNEST(res) => {
+                    columns.push(Column::new(Expression::Unnest(res.replace(" ", ""))));
+                    can_alias = true;
+                }
+                token if starts_expression(&token) => {
+                    columns.push(Column::new(parse_expression(
+                        token,
+                        &mut tokens,
+                        &mut aggregates,
+                    )?));
+                    can_alias = true;
                 }
-                Token::AVERAGE(res) => {
-                    if targets.len() != 0 {
+                Token::AS => {
+                    let alias = match safe_next(&mut tokens)? {
+                        Token::Attribute(res) => res,
+                        res => {
+                            return Err(format!(
+                                "SYNTAX ERROR: AS must be followed by a name, error at {}",
+                                res
+                            ));
+                        }
+                    };
+
+                    if !can_alias {
                         return Err(format!(
-                            "SYNTAX ERROR: Cannot mix aggregated attributes and non-aggregated attributes at {}",
-                            attr
+                            "SYNTAX ERROR: AS {} must come after a single attribute or aggregation",
+                            alias
                         ));
                     }
 
-                    targets = split_aggregated_attributes(res);
-                    aggregation = Aggregation::Average;
-
-                    // cannot mix aggregation and normal attributes to this has to be the end of the attribute section
-                    break;
-                }
-                Token::Attribute(res) => {
-                    targets.push(res);
+                    // can_alias is only true after a column has been added so the unwrap is safe
+                    columns.last_mut().unwrap().alias = Some(alias);
+                    can_alias = false;
                 }
                 Token::AttributeWildcard => {
-                    if targets.len() != 0 {
+                    if !columns.is_empty() {
                         return Err(format!(
                             "SYNTAX ERROR: Cannot mix wildcard with specific attributes at {}",
                             attr
@@ -265,7 +952,11 @@ pub fn parse(_tokens: Vec<Token>) -> Result<SelectStatement, String> {
                     }
 
                     attribute_wild_card = true; // need to wait to find the datasource token to get the attributes list
-                    break;
+                }
+                Token::INTO => {
+                    // SELECT ... INTO 'file' FROM ..., the same as putting it after the other clauses
+                    into = Some(parse_into(&mut tokens)?);
+                    can_alias = false;
                 }
                 Token::FROM => {
                     reached_from = true;
@@ -275,10 +966,22 @@ pub fn parse(_tokens: Vec<Token>) -> Result<SelectStatement, String> {
             }
         }
 
-        if targets.len() == 0 && attribute_wild_card == false {
+        if columns.is_empty() && !attribute_wild_card {
             return Err("SYNTAX ERROR: No attributes defined after SELECT".to_string());
         }
 
+        // every unaliased CASE would be called CASE, so the ones after the first are numbered to keep their own values
+        let mut cases = 0;
+        for column in columns.iter_mut() {
+            if matches!(column.expression, Expression::Case(_)) && column.alias.is_none() {
+                cases += 1;
+
+                if cases > 1 {
+                    column.alias = Some(format!("CASE {}", cases));
+                }
+            }
+        }
+
         if !reached_from {
             let fr = safe_next(&mut tokens)?;
 
@@ -287,7 +990,7 @@ pub fn parse(_tokens: Vec<Token>) -> Result<SelectStatement, String> {
             }
         }
 
-        let source: DataSource;
+        let mut source: DataSource;
 
         let st = safe_next(&mut tokens)?;
 
@@ -302,42 +1005,163 @@ pub fn parse(_tokens: Vec<Token>) -> Result<SelectStatement, String> {
         }
 
         if attribute_wild_card {
-            targets = match source {
-                DataSource::Playlist(_) | DataSource::SavedAlbum(_) => TrackData::attributes(),
+            columns = match source {
+                DataSource::Playlist(_) | DataSource::SavedAlbum(_) | DataSource::Liked => {
+                    TrackData::attributes()
+                }
                 DataSource::Playlists => PlaylistData::attributes(),
                 DataSource::SavedAlbums => AlbumData::attributes(),
+                DataSource::Duplicates(..) => Duplicate::attributes(),
+                DataSource::FollowedArtists | DataSource::Artists => ArtistData::attributes(),
+                DataSource::TopTracks(_) => TopTrack::attributes(),
+                DataSource::TopArtists(_) => TopArtist::attributes(),
+                DataSource::RecentlyPlayed => Play::attributes(),
+                DataSource::StreamingHistory => HistoryRecord::attributes(),
             }
+            .into_iter()
+            // the genres of tracks and albums take extra requests so they have to be asked for by name
+            .filter(|x| x != "genres" || !source.needs_genres())
+            .map(|x| Column::new(Expression::Attribute(x)))
+            .collect();
         }
 
         let mut order: Option<OrderBy> = None;
         let mut conditions: Option<Condition> = None;
+        let mut group_by: Option<Vec<String>> = None;
+        let mut having: Option<Condition> = None;
+        let mut qualify: Option<Condition> = None;
 
-        loop {
-            match tokens.next() {
-                Some(w) => match w {
-                    Token::WHERE => conditions = parse_where(&mut tokens)?,
-                    Token::ORDER => order = parse_order(&mut tokens)?,
+        while let Some(w) = tokens.next() {
+            match w {
+                Token::WHERE => {
+                    // WHERE is applied before the rows are grouped
+                    let mut where_aggregates: Vec<(Aggregation, String)> = Vec::new();
+                    conditions = parse_where(&mut tokens, &mut where_aggregates)?;
+
+                    if !where_aggregates.is_empty() {
+                        return Err(
+                            "SYNTAX ERROR: WHERE can't use an aggregation, use HAVING after GROUP BY"
+                                .to_string(),
+                        );
+                    }
+                }
+                Token::ORDER => order = parse_order(&mut tokens, &mut aggregates)?,
+                Token::GROUP => group_by = parse_group(&mut tokens)?,
+                Token::HAVING => having = parse_where(&mut tokens, &mut aggregates)?,
+                Token::QUALIFY => qualify = parse_where(&mut tokens, &mut aggregates)?,
+                Token::INTO => into = Some(parse_into(&mut tokens)?),
+                Token::MATCH => match &mut source {
+                    DataSource::Duplicates(_, duplicate_match) => {
+                        *duplicate_match = parse_match(safe_next(&mut tokens)?)?
+                    }
                     _ => {
-                        return Ok(SelectStatement {
-                            aggregation,
-                            targets,
-                            source,
-                            order,
-                            conditions,
-                        });
+                        return Err(
+                            "SYNTAX ERROR: MATCH can only come after DUPLICATES".to_string()
+                        );
                     }
                 },
-                None => {
-                    return Ok(SelectStatement {
-                        aggregation,
-                        targets,
-                        source,
-                        order,
-                        conditions,
+                _ => break,
+            }
+        }
+
+        if having.is_some() && group_by.is_none() {
+            return Err("SYNTAX ERROR: HAVING can only be used alongside GROUP BY".to_string());
+        }
+
+        let has_window = columns
+            .iter()
+            .any(|x| matches!(x.expression, Expression::Window(_)))
+            || order.as_ref().is_some_and(orders_by_window);
+
+        if qualify.is_some() && !has_window {
+            return Err(
+                "SYNTAX ERROR: QUALIFY can only be used alongside a window function".to_string(),
+            );
+        }
+
+        let is_aggregate = |x: &Column| matches!(x.expression, Expression::Aggregate(..));
+        let mut targets: Vec<String> = columns.iter().map(|x| x.name()).collect();
+
+        if group_by.is_none() && columns.iter().any(is_aggregate) {
+            if !columns.iter().all(is_aggregate) {
+                return Err(
+                    "SYNTAX ERROR: Cannot mix aggregated attributes and non-aggregated attributes without GROUP BY"
+                        .to_string(),
+                );
+            }
+
+            let single_aggregation =
+                columns
+                    .iter()
+                    .all(|x| match (&x.expression, &columns[0].expression) {
+                        (Expression::Aggregate(agg1, _), Expression::Aggregate(agg2, _)) => {
+                            agg1 == agg2 && x.alias.is_none()
+                        }
+                        _ => false,
                     });
+
+            if single_aggregation && order.is_none() {
+                // a single aggregation over all the data is displayed the same way it always has been
+                if let Expression::Aggregate(agg, _) = &columns[0].expression {
+                    aggregation = agg.clone();
+                }
+                targets = aggregates.drain(..).map(|(_, attr)| attr).collect();
+            } else {
+                // anything else is computed as one group containing all the data
+                group_by = Some(Vec::new());
+            }
+        }
+
+        if group_by.is_none() && !aggregates.is_empty() {
+            return Err(
+                "SYNTAX ERROR: Can only use an aggregation outside the SELECT list alongside GROUP BY"
+                    .to_string(),
+            );
+        }
+
+        if let Some(group) = &group_by {
+            if attribute_wild_card {
+                return Err("SYNTAX ERROR: Cannot use a wildcard with GROUP BY".to_string());
+            }
+
+            for column in &columns {
+                if let Expression::Attribute(attr) = &column.expression
+                    && !group.contains(attr)
+                    && !group.contains(&column.name())
+                {
+                    return Err(format!(
+                        "SYNTAX ERROR: {} must be in the GROUP BY clause or used inside an aggregation",
+                        attr
+                    ));
+                }
+
+                // CASE and UNNEST are computed from the ungrouped data so the groups have to be made on them, unless the CASE uses an aggregation
+                if let Expression::Case(_) | Expression::Unnest(_) = &column.expression
+                    && !column.expression.has_aggregate()
+                    && !group.contains(&column.name())
+                {
+                    return Err(format!(
+                        "SYNTAX ERROR: {} must be in the GROUP BY clause, use AS to give it a name",
+                        column.name()
+                    ));
                 }
             }
         }
+
+        Ok(SelectStatement {
+            aggregation,
+            targets,
+            columns,
+            genres: genres && source.needs_genres(),
+            source,
+            order,
+            conditions,
+            group_by,
+            aggregates,
+            having,
+            qualify,
+            into,
+        })
     } else {
         Err(format!("SYNTAX ERROR: Invalid token at {}", statement_type))
     }
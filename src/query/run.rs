@@ -1,19 +1,19 @@
 use crate::app_context::AppContext;
-use crate::query::data::load_data_source;
 use crate::query::parse::parse;
-use crate::query::statements::SelectStatement;
+use crate::query::statements::Statement;
 use crate::query::tokenise::{Token, tokenise};
 use crate::utils::logger::{info, info_nnl, success};
-use std::io;
-use std::io::Write;
 
 #[derive(PartialEq, Clone)]
 pub enum TUIQueryStage {
     NotRunning,
     Queued(String),
     Tokenised(Vec<Token>),
-    Parsed(SelectStatement),
-    ParsedWithData(SelectStatement),
+    Parsed(Statement),
+    ParsedWithData(Statement),
+    /// The statement changes more tracks than the confirm threshold and is waiting for the user to enter y
+    AwaitingConfirmation(Statement),
+    Confirmed(Statement),
 }
 
 pub struct QueryTracker {
@@ -28,11 +28,16 @@ pub fn run_query(query: String, cx: &mut AppContext) -> Result<(), String> {
     let tokens: Vec<Token> = tokenise(query.clone())?;
     success!("Processed Tokens");
 
+    // debug output goes through the logger so it stays off stdout and is hidden in quiet mode
     if cx.user_config.debug && !cx.user_config.tui {
-        info!("Tokens");
-        for i in &tokens {
-            println!("{}   ", i.to_string())
-        }
+        info!(
+            "Tokens {}",
+            tokens
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<String>>()
+                .join("   ")
+        );
     }
 
     info_nnl!("Parsing Tokens");
@@ -40,13 +45,11 @@ pub fn run_query(query: String, cx: &mut AppContext) -> Result<(), String> {
     success!("Parsed Tokens");
 
     if cx.user_config.debug && !cx.user_config.tui {
-        info!("Parsed Statement");
-        println!("{:?}", statement)
+        info!("Parsed Statement {:?}", statement);
     }
 
     info_nnl!("Loading Data");
-    io::stdout().flush().unwrap();
-    load_data_source(cx, statement.source.clone())?;
+    statement.load_data(cx)?;
     success!("Loaded Data");
 
     let _ = statement.run(cx, None, query)?;
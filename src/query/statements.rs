@@ -1,22 +1,40 @@
+use crate::api::APIQuery;
 use crate::app_context::AppContext;
+use crate::cache::serialise_cache;
+use crate::history::{HistoryRecord, read_history};
+use crate::play_log::Play;
 use crate::query::condition::{Condition, compute_conditions};
-use crate::query::data::{AlbumData, KeyAccess, PlaylistData, TrackData};
+use crate::query::data::{
+    AlbumData, ArtistData, KeyAccess, PlaylistData, Row, TopArtist, TopTrack, TrackData,
+    library_artist_ids, load_data_source, load_genres,
+};
 use crate::query::display::data_display::{
-    aggregation_table, build_aggregation_table, build_table, table,
+    build_aggregation_table, build_table, print_aggregation, print_rows,
+};
+use crate::query::duplicates::{
+    Duplicate, DuplicateMatch, Keep, dedupe_positions, find_duplicates,
 };
+use crate::query::expression::{Column, Expression, Window};
 use crate::query::tokenise::{DataSource, Order};
 use crate::query::value::Value;
-use crate::ui::tui::TUI;
-use crate::utils::file::write_result;
-use crate::utils::logger::info;
-use crate::utils::sort::mergesort;
-use std::collections::HashMap;
+use crate::snapshot::{Snapshot, find_snapshot, load_snapshots, take_snapshot};
+use crate::ui::tui::{Log, Severity, TUI};
+use crate::utils::date::Date;
+use crate::utils::file::{
+    File, WriteMode, delete_file, file_format, write_aggregation_result, write_file, write_result,
+};
+use crate::utils::logger::{info, success, warning};
+use crate::utils::sort::{mergesort, moves};
+use crate::utils::utils::iso_str;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::io;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Aggregation {
     Count,
     Average,
+    Sum,
     None,
 }
 
@@ -31,271 +49,2671 @@ impl Aggregation {
         match self {
             Aggregation::Count => format!("COUNT({})", attribute),
             Aggregation::Average => format!("AVERAGE({})", attribute),
+            Aggregation::Sum => format!("SUM({})", attribute),
             Aggregation::None => "".to_string(),
         }
     }
+
+    /// Compute the aggregation of an attribute over a set of rows
+    pub fn compute<T>(&self, data: &[T], attribute: &String) -> Result<Value, String>
+    where
+        T: KeyAccess,
+    {
+        let mut running = RunningAggregate::default();
+
+        for i in data {
+            running.add(self, i, attribute)?;
+        }
+
+        running.value(self, attribute)
+    }
+}
+
+/// An aggregation that rows can be added to one at a time, so a running aggregation doesn't go over the earlier rows again
+#[derive(Default)]
+pub struct RunningAggregate {
+    count: usize,
+    int_total: i64,
+    float_total: f64,
+    /// A sum stays an int unless one of the values is a float
+    is_float: bool,
+}
+
+impl RunningAggregate {
+    pub fn add<T>(
+        &mut self,
+        aggregation: &Aggregation,
+        data: &T,
+        attribute: &String,
+    ) -> Result<(), String>
+    where
+        T: KeyAccess,
+    {
+        self.count += 1;
+
+        if *aggregation == Aggregation::Count {
+            return Ok(());
+        }
+
+        match data.access(attribute)? {
+            Value::Int(res) => self.int_total += res,
+            Value::Float(res) => {
+                self.float_total += res;
+                self.is_float = true;
+            }
+            _ => {
+                return Err(format!(
+                    "Cannot {} field {} as it is a non-numeric type.",
+                    if *aggregation == Aggregation::Average {
+                        "average"
+                    } else {
+                        "sum"
+                    },
+                    attribute
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The aggregation of every row added so far
+    pub fn value(&self, aggregation: &Aggregation, attribute: &String) -> Result<Value, String> {
+        let total = self.float_total + self.int_total as f64;
+
+        match aggregation {
+            Aggregation::Count => Ok(Value::Int(self.count as i64)),
+            Aggregation::Average | Aggregation::Sum if self.count == 0 => Ok(Value::Null),
+            Aggregation::Average => Ok(Value::Float(total / self.count as f64)),
+            Aggregation::Sum if self.is_float => Ok(Value::Float(total)),
+            Aggregation::Sum => Ok(Value::Int(self.int_total)),
+            Aggregation::None => Err(format!("No aggregation to apply to {}.", attribute)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct OrderKey {
+    pub expression: Expression,
+    pub order: Order,
+    pub nulls_first: bool,
 }
 
-pub type OrderBy = (Vec<String>, Order);
+pub type OrderBy = Vec<OrderKey>;
+
+/// Rows that share the same values for a set of attributes, alongside those values
+type Partition<T> = (Vec<Value>, Vec<T>);
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct SelectStatement {
     pub aggregation: Aggregation,
     pub targets: Vec<String>, // list of attribute names
+    /// The SELECT list, targets holds the names of these columns unless a single aggregation is applied to all the data
+    pub columns: Vec<Column>,
+    /// Whether genres of the tracks or albums are used, which means the artists have to be loaded
+    pub genres: bool,
     pub source: DataSource,
     pub order: Option<OrderBy>,
     pub conditions: Option<Condition>,
+    pub group_by: Option<Vec<String>>,
+    /// Aggregations computed for each group, grouped targets and HAVING conditions refer to them by their formatted name e.g. COUNT(name)
+    pub aggregates: Vec<(Aggregation, String)>,
+    pub having: Option<Condition>,
+    /// Conditions on the rows once window functions have been computed
+    pub qualify: Option<Condition>,
+    /// INTO 'file', saves the results to a file instead of the --file file
+    pub into: Option<OutputFile>,
 }
 
-impl SelectStatement {
-    pub fn run(
-        self,
-        cx: &AppContext,
-        window: Option<&mut TUI>,
-        query_string: String,
-    ) -> Result<(), String> {
-        // gather targets
-        match &self.source {
-            DataSource::Playlists => {
-                let mut valid = self.playlists(match &cx.data.playlist_data {
-                    Some(playlists) => playlists.clone(),
-                    None => return Err("Playlist data not fetched.".to_string()),
-                })?;
+/// A file the results of a SELECT are saved to, in the format for its extension
+#[derive(Debug, PartialEq, Clone)]
+pub struct OutputFile {
+    pub path: String,
+    /// Add the results to the end of the file instead of replacing it
+    pub append: bool,
+}
 
-                if cx.user_config.debug && !cx.user_config.tui {
-                    info!("Filtered playlists")
-                }
+#[derive(Debug, PartialEq, Clone)]
+pub enum Statement {
+    Select(SelectStatement),
+    /// CREATE PLAYLIST "name" AS SELECT ..., creates a playlist holding the tracks of the query
+    CreatePlaylist(String, SelectStatement),
+    /// INSERT INTO PLAYLIST(name) SELECT ..., adds the tracks of the query to the end of a playlist
+    Insert(String, SelectStatement),
+    /// DELETE FROM PLAYLIST(name) WHERE ..., stored as the query that selects the tracks to remove
+    Delete(String, SelectStatement),
+    /// RESTORE PLAYLIST(name) TO <timestamp>, puts back the latest snapshot taken at or before the timestamp
+    Restore(String, u64),
+    /// SHOW SNAPSHOTS, lists the saved snapshots
+    ShowSnapshots,
+    /// DEDUPE PLAYLIST(name) MATCH ... KEEP ..., removes all but one copy of each duplicated track
+    Dedupe(String, DuplicateMatch, Keep),
+    /// REORDER PLAYLIST(name) BY ..., sorts the playlist itself with an ORDER BY list
+    Reorder(String, OrderBy),
+    /// PREVIEW followed by a statement that changes a playlist, shows the changes without making them
+    Preview(Box<Statement>),
+}
 
-                if self.order.is_some() {
-                    Self::order(&mut valid, self.order.clone().unwrap())?;
-                }
+/// The tracks a statement adds to and removes from a playlist
+#[derive(Debug, Clone)]
+pub struct Changes {
+    pub playlist: String,
+    /// The playlist doesn't exist yet and is created before the tracks are added
+    pub create: bool,
+    pub additions: Vec<TrackData>,
+    /// Every occurrence of each removed track, unless positions is set
+    pub removals: Vec<TrackData>,
+    /// Where the removed tracks are in the playlist when only some copies of a track are removed
+    pub positions: Option<Vec<usize>>,
+    /// The full list of tracks the playlist is replaced with, in order, when restoring a snapshot
+    pub replace: Option<Vec<TrackData>>,
+    /// Every track in its new order alongside its current position, when reordering a playlist
+    pub reorder: Option<Vec<(usize, TrackData)>>,
+}
 
-                self.handle_aggregation_and_display(valid.clone(), cx, window)?;
+impl Changes {
+    /// The number of tracks that will be added or removed
+    pub fn len(&self) -> usize {
+        self.additions.len() + self.removals.len() + self.moved().len()
+    }
 
-                if cx.save_file.is_some() {
-                    write_result(cx, valid, query_string)?
-                }
-            }
-            DataSource::SavedAlbums => {
-                let mut valid = self.albums(match &cx.data.saved_album_data {
-                    Some(albums) => albums.clone(),
-                    None => return Err("Playlist data not fetched.".to_string()),
-                })?;
+    /// The tracks that change position alongside their current and new positions
+    fn moved(&self) -> Vec<(usize, usize, &TrackData)> {
+        match &self.reorder {
+            Some(tracks) => tracks
+                .iter()
+                .enumerate()
+                .filter(|(i, (from, _))| from != i)
+                .map(|(i, (from, track))| (*from, i, track))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
 
-                if cx.user_config.debug && !cx.user_config.tui {
-                    info!("Filtered playlists")
-                }
+    pub fn summary(&self) -> String {
+        if self.reorder.is_some() {
+            return format!("{}: {} tracks moved.", self.playlist, self.moved().len());
+        }
 
-                if self.order.is_some() {
-                    Self::order(&mut valid, self.order.clone().unwrap())?;
-                }
+        format!(
+            "{}{}: {} tracks added, {} tracks removed.",
+            if self.create { "New playlist " } else { "" },
+            self.playlist,
+            self.additions.len(),
+            self.removals.len()
+        )
+    }
 
-                self.handle_aggregation_and_display(valid.clone(), cx, window)?;
+    /// Show the changes as a table with a + or - for each track, or its old and new position when it's moved
+    pub fn display(&self, cx: &AppContext, window: Option<&mut TUI>) -> Result<(), String> {
+        let mut rows: Vec<Row> = Vec::new();
 
-                if cx.save_file.is_some() {
-                    write_result(cx, valid, query_string)?
-                }
+        for (change, tracks) in [("+", &self.additions), ("-", &self.removals)] {
+            for track in tracks {
+                let mut row = Row::from_data(track)?;
+                row.insert("change".to_string(), Value::Str(change.to_string()));
+                rows.push(row);
             }
-            DataSource::Playlist(res) => {
-                let mut data: Option<&Vec<TrackData>> = None;
+        }
 
-                match &cx.data.playlist_data {
-                    Some(playlists) => {
-                        for playlist in playlists {
-                            if playlist.name == *res {
-                                data = Some(&playlist.tracks);
-                                break;
-                            }
-                        }
-                    }
-                    None => return Err("Playlist data not fetched.".to_string()),
-                };
+        for (from, to, track) in self.moved() {
+            let mut row = Row::from_data(track)?;
+            row.insert(
+                "change".to_string(),
+                Value::Str(format!("{} -> {}", from, to)),
+            );
+            rows.push(row);
+        }
 
-                if data.is_none() {
-                    return Err(format!("No playlist with the name {}.", res));
-                }
+        let columns: Vec<String> = ["change", "name", "artists", "album_name", "id"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
 
-                let mut valid = self.tracks(data.unwrap().clone())?;
+        if cx.user_config.tui {
+            let window = window.unwrap(); // if cx.user_config.tui is true then .unwrap() is safe
+            window.send_table_data(build_table(rows, columns)?)?;
+            window.log(Log::new(self.summary(), Severity::Log))
+        } else {
+            print_rows(rows, columns, cx)?;
+            info!("{}", self.summary())
+        }
 
-                if cx.user_config.debug && !cx.user_config.tui {
-                    info!("Filtered playlist tracks")
-                }
+        Ok(())
+    }
 
-                if self.order.is_some() {
-                    Self::order(&mut valid, self.order.clone().unwrap())?;
-                }
+    /// Make the changes through the API then update the loaded data and cache to match, playlists that already exist are snapshotted first
+    pub fn apply(self, cx: &mut AppContext, window: Option<&mut TUI>) -> Result<(), String> {
+        let additions = added_now(self.additions)?;
 
-                self.handle_aggregation_and_display(valid.clone(), cx, window)?;
+        let mut removed_ids: Vec<String> = Vec::new();
 
-                if cx.save_file.is_some() {
-                    write_result(cx, valid, query_string)?
-                }
+        for id in track_ids(&self.removals) {
+            if !removed_ids.contains(&id) {
+                removed_ids.push(id);
             }
-            DataSource::SavedAlbum(res) => {
-                let mut data: Option<&Vec<TrackData>> = None;
+        }
 
-                match &cx.data.saved_album_data {
-                    Some(albums) => {
-                        for album in albums {
-                            if album.name == *res {
-                                data = Some(&album.tracks)
-                            }
-                        }
-                    }
-                    None => return Err("Playlist data not fetched.".to_string()),
-                };
+        let mut snapshot: Option<u64> = None;
+        // the version of the playlist the changes are made against
+        let mut snapshot_id: Option<String> = None;
 
-                if data.is_none() {
-                    return Err(format!("No saved album with the name {}.", res));
-                }
+        let playlist_id = if self.create {
+            let playlist = APIQuery::create_playlist(cx, &self.playlist)?;
+            let id = playlist.id.clone();
 
-                let mut valid = self.tracks(data.unwrap().clone())?;
+            cx.data
+                .playlist_data
+                .get_or_insert_with(Vec::new)
+                .push(playlist);
 
-                if cx.user_config.debug && !cx.user_config.tui {
-                    info!("Filtered playlists")
-                }
+            id
+        } else {
+            let playlist = find_playlist(cx, &self.playlist)?;
+            let (id, loaded) = (playlist.id.clone(), playlist.snapshot_id.clone());
 
-                if self.order.is_some() {
-                    Self::order(&mut valid, self.order.clone().unwrap())?;
-                }
+            let current = APIQuery::get_playlist_snapshot_id(cx, id.clone())?;
 
-                self.handle_aggregation_and_display(valid.clone(), cx, window)?;
+            // an empty snapshot id means the data came from an older cache and can't be checked
+            if !loaded.is_empty() && current != loaded {
+                // make the next query load the playlists again
+                cx.data.playlist_data_ct = 0;
+                delete_file(cx, File::Cache)?;
 
-                if cx.save_file.is_some() {
-                    write_result(cx, valid, query_string)?
-                }
+                return Err(format!(
+                    "{} has been changed since it was loaded, run the statement again to use the latest tracks.",
+                    self.playlist
+                ));
             }
+
+            let playlist = find_playlist_by_id(cx, &id)?.clone();
+            snapshot = Some(take_snapshot(cx, &playlist)?);
+            snapshot_id = Some(current);
+            id
         };
 
-        Ok(())
-    }
+        let reordered = self.reorder.is_some();
 
-    fn handle_aggregation_and_display<T>(
-        self,
-        data: Vec<T>,
-        cx: &AppContext,
-        window: Option<&mut TUI>,
-    ) -> Result<(), String>
-    where
-        T: KeyAccess,
-    {
-        match self.aggregation {
-            Aggregation::Count => {
-                let mut count_data: HashMap<String, AggregationResult> = HashMap::new();
-                let count = AggregationResult::Int(data.len() as i64);
+        let mut message = if let Some(tracks) = self.reorder {
+            let order: Vec<usize> = tracks.iter().map(|(from, _)| *from).collect();
+            let moves = moves(&order);
 
-                for i in self.targets {
-                    count_data.insert(i, count.clone());
-                }
+            // each move is made against the version of the playlist left by the one before it
+            let mut current = snapshot_id.unwrap(); // only playlists that already exist are reordered
 
-                if cx.user_config.tui {
-                    window
-                        .unwrap()
-                        .send_table_data(build_aggregation_table(self.aggregation, count_data))? // if cx.usee_config.tui is true then .unwrap() is safe
-                } else {
-                    aggregation_table(self.aggregation, count_data)
-                }
+            for (range_start, insert_before) in &moves {
+                current = APIQuery::move_playlist_track(
+                    cx,
+                    playlist_id.clone(),
+                    *range_start,
+                    *insert_before,
+                    current,
+                )?;
             }
-            Aggregation::Average => {
-                let mut average_data: HashMap<String, AggregationResult> = HashMap::new();
-                let count = data.len() as f64;
 
-                for i in self.targets {
-                    let mut total: f64 = 0f64;
+            snapshot_id = Some(current);
+            find_playlist_by_id(cx, &playlist_id)?.tracks =
+                tracks.into_iter().map(|(_, x)| x).collect();
 
-                    for j in &data {
-                        match j.access(&i)? {
-                            Value::Int(res) => total += res as f64,
-                            Value::Float(res) => total += res,
-                            _ => {
-                                return Err(format!(
-                                    "Cannot average field {} as it is a non-numeric type.",
-                                    i
-                                ));
-                            }
-                        };
-                    }
+            format!("Reordered {} with {} moves.", self.playlist, moves.len())
+        } else if let Some(tracks) = self.replace {
+            let tracks = added_now(tracks)?;
+            APIQuery::replace_playlist_tracks(cx, playlist_id.clone(), &track_ids(&tracks))?;
 
-                    average_data.insert(i, AggregationResult::Float(total / count));
+            let message = format!("Restored {} to {} tracks.", self.playlist, tracks.len());
+            find_playlist_by_id(cx, &playlist_id)?.tracks = tracks;
+
+            message
+        } else if let Some(positions) = self.positions {
+            let playlist = find_playlist_by_id(cx, &playlist_id)?;
+            let mut kept: Vec<TrackData> = playlist
+                .tracks
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !positions.contains(i))
+                .map(|(_, x)| x.clone())
+                .collect();
+
+            // the API removes every copy of a track, so the copies being kept are put back where they were
+            APIQuery::remove_playlist_tracks(cx, playlist_id.clone(), &removed_ids)?;
+
+            let mut batches: Vec<(usize, Vec<String>)> = Vec::new();
+
+            for (i, track) in kept.iter_mut().enumerate() {
+                if !removed_ids.contains(&track.id) {
+                    continue;
                 }
 
-                if cx.user_config.tui {
-                    window
-                        .unwrap()
-                        .send_table_data(build_aggregation_table(self.aggregation, average_data))? // if cx.usee_config.tui is true then .unwrap() is safe
-                } else {
-                    aggregation_table(self.aggregation, average_data)
+                track.added_at = Date::from_iso8601(iso_str())?;
+
+                match batches.last_mut() {
+                    Some((start, ids)) if *start + ids.len() == i => ids.push(track.id.clone()),
+                    _ => batches.push((i, vec![track.id.clone()])),
                 }
             }
-            Aggregation::None => {
-                if cx.user_config.tui {
-                    window
-                        .unwrap()
-                        .send_table_data(build_table(data, self.targets.clone())?)? // if cx.user_config.tui is true then .unwrap() is safe
-                } else {
-                    table(data, self.targets.clone())?
-                }
+
+            for (position, ids) in batches {
+                APIQuery::insert_playlist_tracks(cx, playlist_id.clone(), &ids, position)?;
+            }
+
+            find_playlist_by_id(cx, &playlist_id)?.tracks = kept;
+
+            format!(
+                "Removed {} duplicate tracks from {}.",
+                positions.len(),
+                self.playlist
+            )
+        } else {
+            if !additions.is_empty() {
+                APIQuery::add_playlist_tracks(cx, playlist_id.clone(), &track_ids(&additions))?;
+            }
+
+            if !removed_ids.is_empty() {
+                // the API removes every occurrence of a track
+                APIQuery::remove_playlist_tracks(cx, playlist_id.clone(), &removed_ids)?;
             }
+
+            let message = if self.create {
+                format!(
+                    "Created playlist {} with {} tracks.",
+                    self.playlist,
+                    additions.len()
+                )
+            } else if removed_ids.is_empty() {
+                format!("Added {} tracks to {}.", additions.len(), self.playlist)
+            } else {
+                format!(
+                    "Removed {} tracks from {}.",
+                    self.removals.len(),
+                    self.playlist
+                )
+            };
+
+            let playlist = find_playlist_by_id(cx, &playlist_id)?;
+            playlist.tracks.retain(|x| !removed_ids.contains(&x.id));
+            playlist.tracks.extend(additions);
+
+            message
+        };
+
+        // only the moves return the new version, anything else has to fetch it
+        let snapshot_id = match snapshot_id {
+            Some(res) if reordered => res,
+            _ => APIQuery::get_playlist_snapshot_id(cx, playlist_id.clone())?,
+        };
+
+        let playlist = find_playlist_by_id(cx, &playlist_id)?;
+        playlist.track_count = playlist.tracks.len() as u64;
+        playlist.snapshot_id = snapshot_id;
+
+        if let Some(res) = snapshot {
+            message = format!(
+                "{} The previous tracks were saved as snapshot {}.",
+                message, res
+            );
+        }
+
+        if cx.user_config.cache {
+            let sd = serialise_cache(cx)?;
+            write_file(cx, File::Cache, sd, WriteMode::Overwrite)?
+        }
+
+        if cx.user_config.tui {
+            window.unwrap().log(Log::new(message, Severity::Success)) // if cx.user_config.tui is true then .unwrap() is safe
+        } else {
+            success!("{}", message)
         }
 
         Ok(())
     }
+}
 
-    fn tracks(&self, data: Vec<TrackData>) -> Result<Vec<TrackData>, String> {
-        let mut valid: Vec<TrackData> = Vec::new();
-
-        for i in data {
-            if self.conditions.is_none()
-                || compute_conditions(&i, self.conditions.clone().unwrap())?
-            {
-                valid.push(i);
+impl Statement {
+    /// The data that has to be loaded before the statement can run
+    pub fn sources(&self) -> Vec<DataSource> {
+        match self {
+            Statement::Select(select)
+            | Statement::CreatePlaylist(_, select)
+            | Statement::Delete(_, select) => vec![select.source.clone()],
+            Statement::Insert(name, select) => {
+                vec![DataSource::Playlist(name.clone()), select.source.clone()]
             }
+            Statement::Restore(name, _)
+            | Statement::Dedupe(name, ..)
+            | Statement::Reorder(name, _) => {
+                vec![DataSource::Playlist(name.clone())]
+            }
+            Statement::ShowSnapshots => Vec::new(),
+            Statement::Preview(statement) => statement.sources(),
         }
+    }
 
-        Ok(valid)
+    /// The source whose tracks or albums need the genres of their artists
+    fn genres_source(&self) -> Option<&DataSource> {
+        match self {
+            Statement::Select(select)
+            | Statement::CreatePlaylist(_, select)
+            | Statement::Insert(_, select)
+            | Statement::Delete(_, select) => select.genres.then_some(&select.source),
+            Statement::Preview(statement) => statement.genres_source(),
+            _ => None,
+        }
     }
 
-    fn playlists(&self, data: Vec<PlaylistData>) -> Result<Vec<PlaylistData>, String> {
-        let mut valid: Vec<PlaylistData> = Vec::new();
+    /// Load the data the statement needs, genres come last as loading a source replaces the loaded data
+    pub fn load_data(&self, cx: &mut AppContext) -> Result<(), String> {
+        for source in self.sources() {
+            load_data_source(cx, source)?;
+        }
 
-        for i in data {
-            if self.conditions.is_none()
-                || compute_conditions(&i, self.conditions.clone().unwrap())?
-            {
-                valid.push(i);
-            }
+        if let Some(source) = self.genres_source() {
+            load_genres(cx, source)?;
         }
 
-        Ok(valid)
+        Ok(())
     }
 
-    fn albums(&self, data: Vec<AlbumData>) -> Result<Vec<AlbumData>, String> {
-        let mut valid: Vec<AlbumData> = Vec::new();
+    /// Work out the tracks the statement would add and remove without calling the API, None if it doesn't change a playlist
+    pub fn changes(&self, cx: &mut AppContext) -> Result<Option<Changes>, String> {
+        match self {
+            Statement::Select(_) | Statement::ShowSnapshots => Ok(None),
+            Statement::CreatePlaylist(name, select) => Ok(Some(Changes {
+                playlist: name.clone(),
+                create: true,
+                additions: select.result_tracks(cx)?,
+                removals: Vec::new(),
+                replace: None,
+                positions: None,
+                reorder: None,
+            })),
+            Statement::Insert(name, select) => {
+                // make sure the playlist exists before anything is computed
+                find_playlist(cx, name)?;
 
-        for i in data {
-            if self.conditions.is_none()
-                || compute_conditions(&i, self.conditions.clone().unwrap())?
-            {
-                valid.push(i);
+                Ok(Some(Changes {
+                    playlist: name.clone(),
+                    create: false,
+                    additions: select.result_tracks(cx)?,
+                    removals: Vec::new(),
+                    replace: None,
+                    positions: None,
+                    reorder: None,
+                }))
+            }
+            Statement::Delete(name, select) => {
+                let ids = track_ids(&select.result_tracks(cx)?);
+
+                Ok(Some(Changes {
+                    playlist: name.clone(),
+                    create: false,
+                    additions: Vec::new(),
+                    removals: find_playlist(cx, name)?
+                        .tracks
+                        .iter()
+                        .filter(|x| ids.contains(&x.id))
+                        .cloned()
+                        .collect(),
+                    replace: None,
+                    positions: None,
+                    reorder: None,
+                }))
+            }
+            Statement::Restore(name, timestamp) => {
+                let id = find_playlist(cx, name)?.id.clone();
+                let snapshot = find_snapshot(cx, &id, *timestamp)?;
+                let playlist = find_playlist(cx, name)?;
+
+                let current = track_ids(&playlist.tracks);
+                let restored = track_ids(&snapshot.playlist.tracks);
+
+                Ok(Some(Changes {
+                    playlist: name.clone(),
+                    create: false,
+                    additions: snapshot
+                        .playlist
+                        .tracks
+                        .iter()
+                        .filter(|x| !current.contains(&x.id))
+                        .cloned()
+                        .collect(),
+                    removals: playlist
+                        .tracks
+                        .iter()
+                        .filter(|x| !restored.contains(&x.id))
+                        .cloned()
+                        .collect(),
+                    replace: Some(snapshot.playlist.tracks),
+                    positions: None,
+                    reorder: None,
+                }))
+            }
+            Statement::Dedupe(name, duplicate_match, keep) => {
+                let playlist = find_playlist(cx, name)?;
+                let positions = dedupe_positions(playlist, duplicate_match, keep);
+
+                Ok(Some(Changes {
+                    playlist: name.clone(),
+                    create: false,
+                    additions: Vec::new(),
+                    removals: positions
+                        .iter()
+                        .map(|x| playlist.tracks[*x].clone())
+                        .collect(),
+                    replace: None,
+                    positions: Some(positions),
+                    reorder: None,
+                }))
+            }
+            Statement::Reorder(name, order) => {
+                let playlist = find_playlist(cx, name)?;
+
+                // the current position is kept with each track so the moves can be worked out once it's sorted
+                let mut rows: Vec<Row> = Vec::new();
+                for (i, track) in playlist.tracks.iter().enumerate() {
+                    let mut row = Row::from_data(track)?;
+                    row.insert("position".to_string(), Value::Int(i as i64));
+                    rows.push(row);
+                }
+
+                SelectStatement::order(&mut rows, order)?;
+
+                let mut reorder: Vec<(usize, TrackData)> = Vec::new();
+                for row in rows {
+                    let Ok(Value::Int(position)) = row.access("position") else {
+                        return Err("Could not find a track's position.".to_string());
+                    };
+                    let position = position as usize;
+                    reorder.push((position, playlist.tracks[position].clone()));
+                }
+
+                Ok(Some(Changes {
+                    playlist: name.clone(),
+                    create: false,
+                    additions: Vec::new(),
+                    removals: Vec::new(),
+                    replace: None,
+                    positions: None,
+                    reorder: Some(reorder),
+                }))
             }
+            Statement::Preview(statement) => statement.changes(cx),
         }
+    }
 
-        Ok(valid)
+    /// The changes the statement will make if they are big enough to need confirming first
+    pub fn needs_confirmation(&self, cx: &mut AppContext) -> Result<Option<Changes>, String> {
+        if cx.dry_run || cx.assume_yes || matches!(self, Statement::Preview(_)) {
+            return Ok(None);
+        }
+
+        match self.changes(cx)? {
+            Some(res) if res.len() > cx.user_config.confirm_threshold => Ok(Some(res)),
+            _ => Ok(None),
+        }
     }
 
-    fn order<T>(valid: &mut Vec<T>, (attributes, order): OrderBy) -> Result<(), String>
-    where
-        T: KeyAccess + Clone + Default + Debug,
-    {
-        //println!("{:?}", valid);
-        let mut temp = mergesort(valid, &attributes)?;
-        //println!("{:?}", temp);
-        if order == Order::Descending {
-            temp.reverse()
+    /// Run the statement, in the TUI any confirmation has to be done with needs_confirmation() beforehand
+    pub fn run(
+        self,
+        cx: &mut AppContext,
+        window: Option<&mut TUI>,
+        query_string: String,
+    ) -> Result<(), String> {
+        match self {
+            Statement::Select(select) => return select.run(cx, window, query_string),
+            Statement::ShowSnapshots => return show_snapshots(cx, window),
+            _ => {}
         }
 
-        *valid = temp;
+        // the branches above are the only statements without changes
+        let changes = self.changes(cx)?.unwrap();
 
-        Ok(())
+        if cx.dry_run || matches!(self, Statement::Preview(_)) {
+            return changes.display(cx, window);
+        }
+
+        if !cx.user_config.tui && !cx.assume_yes && changes.len() > cx.user_config.confirm_threshold
+        {
+            if !cx.interactive {
+                return Err(format!(
+                    "This will change {} tracks, more than the confirm threshold of {}. Use --yes to make the changes without asking.",
+                    changes.len(),
+                    cx.user_config.confirm_threshold
+                ));
+            }
+
+            changes.display(cx, None)?;
+
+            if !confirm(format!(
+                "This will change {} tracks, continue? (y/N)",
+                changes.len()
+            ))? {
+                info!("Cancelled");
+                return Ok(());
+            }
+        }
+
+        changes.apply(cx, window)
+    }
+}
+
+fn show_snapshots(cx: &AppContext, window: Option<&mut TUI>) -> Result<(), String> {
+    let snapshots = load_snapshots(cx)?;
+
+    if cx.user_config.tui {
+        window
+            .unwrap() // if cx.user_config.tui is true then .unwrap() is safe
+            .send_table_data(build_table(snapshots, Snapshot::attributes())?)
+    } else {
+        print_rows(snapshots, Snapshot::attributes(), cx)
+    }
+}
+
+/// Say where the results of a query were saved
+fn saved(cx: &AppContext, window: Option<&mut TUI>, file: &OutputFile) {
+    let message = format!(
+        "{} the results to {}",
+        if file.append { "Added" } else { "Saved" },
+        file.path
+    );
+
+    if cx.user_config.tui {
+        window.unwrap().log(Log::new(message, Severity::Success)) // if cx.user_config.tui is true then .unwrap() is safe
+    } else {
+        success!("{}", message)
+    }
+}
+
+/// Ask a yes or no question on the command line, anything other than y or yes is a no
+fn confirm(question: String) -> Result<bool, String> {
+    warning!("{}", question);
+
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(|x| x.to_string())?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+fn find_playlist<'a>(
+    cx: &'a mut AppContext,
+    name: &String,
+) -> Result<&'a mut PlaylistData, String> {
+    match &mut cx.data.playlist_data {
+        Some(playlists) => match playlists.iter_mut().find(|x| x.name == *name) {
+            Some(res) => Ok(res),
+            None => Err(format!("No playlist with the name {}.", name)),
+        },
+        None => Err("Playlist data not fetched.".to_string()),
+    }
+}
+
+/// The playlist with the id, used once a playlist has been chosen as another playlist can have the same name
+fn find_playlist_by_id<'a>(
+    cx: &'a mut AppContext,
+    id: &String,
+) -> Result<&'a mut PlaylistData, String> {
+    match &mut cx.data.playlist_data {
+        Some(playlists) => match playlists.iter_mut().find(|x| x.id == *id) {
+            Some(res) => Ok(res),
+            None => Err(format!("No playlist with the id {}.", id)),
+        },
+        None => Err("Playlist data not fetched.".to_string()),
+    }
+}
+
+fn track_ids(tracks: &[TrackData]) -> Vec<String> {
+    tracks.iter().map(|x| x.id.clone()).collect()
+}
+
+/// Tracks added to a playlist are given the current time as the time they were added
+fn added_now(mut tracks: Vec<TrackData>) -> Result<Vec<TrackData>, String> {
+    let now = Date::from_iso8601(iso_str())?;
+
+    for track in tracks.iter_mut() {
+        track.added_at = now.clone();
+    }
+
+    Ok(tracks)
+}
+
+impl SelectStatement {
+    pub fn run(
+        self,
+        cx: &AppContext,
+        window: Option<&mut TUI>,
+        query_string: String,
+    ) -> Result<(), String> {
+        let rows = self.source_rows(cx)?;
+
+        self.output(rows, cx, window, query_string)
+    }
+
+    /// The tracks of the playlist, saved album, Liked Songs, top tracks or recent plays being queried
+    fn source_tracks(&self, cx: &AppContext) -> Result<Vec<TrackData>, String> {
+        match &self.source {
+            DataSource::Playlist(res) => {
+                let mut data: Option<&Vec<TrackData>> = None;
+
+                match &cx.data.playlist_data {
+                    Some(playlists) => {
+                        for playlist in playlists {
+                            if playlist.name == *res {
+                                data = Some(&playlist.tracks);
+                                break;
+                            }
+                        }
+                    }
+                    None => return Err("Playlist data not fetched.".to_string()),
+                };
+
+                match data {
+                    Some(res) => Ok(res.clone()),
+                    None => Err(format!("No playlist with the name {}.", res)),
+                }
+            }
+            DataSource::SavedAlbum(res) => {
+                let mut data: Option<&Vec<TrackData>> = None;
+
+                match &cx.data.saved_album_data {
+                    Some(albums) => {
+                        for album in albums {
+                            if album.name == *res {
+                                data = Some(&album.tracks)
+                            }
+                        }
+                    }
+                    None => return Err("Playlist data not fetched.".to_string()),
+                };
+
+                match data {
+                    Some(res) => Ok(res.clone()),
+                    None => Err(format!("No saved album with the name {}.", res)),
+                }
+            }
+            DataSource::Liked => match &cx.data.liked_track_data {
+                Some(res) => Ok(res.clone()),
+                None => Err("Liked track data not fetched.".to_string()),
+            },
+            DataSource::TopTracks(range) => match cx.data.top_track_data.get(range) {
+                Some((_, res)) => Ok(res.iter().map(|x| x.track.clone()).collect()),
+                None => Err("Top track data not fetched.".to_string()),
+            },
+            DataSource::RecentlyPlayed => match &cx.data.recently_played_data {
+                Some(res) => Ok(res.iter().map(|x| x.track.clone()).collect()),
+                None => Err("Recently played data not fetched.".to_string()),
+            },
+            _ => Err(format!("{} does not contain tracks.", self.source)),
+        }
+    }
+
+    /// Gather and filter the data being queried
+    fn source_rows(&self, cx: &AppContext) -> Result<Vec<Row>, String> {
+        let mut rows: Vec<Row> = Vec::new();
+
+        match &self.source {
+            DataSource::Playlists => {
+                let valid = self.playlists(match &cx.data.playlist_data {
+                    Some(playlists) => playlists.clone(),
+                    None => return Err("Playlist data not fetched.".to_string()),
+                })?;
+
+                if cx.user_config.debug && !cx.user_config.tui {
+                    info!("Filtered playlists")
+                }
+
+                for i in &valid {
+                    rows.push(Row::from_data(i)?);
+                }
+            }
+            DataSource::SavedAlbums => {
+                let valid = self.albums(match &cx.data.saved_album_data {
+                    Some(albums) => albums.clone(),
+                    None => return Err("Playlist data not fetched.".to_string()),
+                })?;
+
+                if cx.user_config.debug && !cx.user_config.tui {
+                    info!("Filtered playlists")
+                }
+
+                for i in &valid {
+                    rows.push(Row::from_data(i)?);
+                }
+            }
+            DataSource::FollowedArtists => {
+                let valid = self.artists(match &cx.data.followed_artist_data {
+                    Some(artists) => artists.clone(),
+                    None => return Err("Followed artist data not fetched.".to_string()),
+                })?;
+
+                if cx.user_config.debug && !cx.user_config.tui {
+                    info!("Filtered followed artists")
+                }
+
+                for i in &valid {
+                    rows.push(Row::from_data(i)?);
+                }
+            }
+            DataSource::Artists => {
+                let library: HashSet<String> = library_artist_ids(&cx.data).into_iter().collect();
+
+                let valid = self.artists(match &cx.data.library_artist_data {
+                    Some(artists) => artists
+                        .iter()
+                        .filter(|x| library.contains(&x.id))
+                        .cloned()
+                        .collect(),
+                    None if cx.offline => {
+                        return Err(
+                            "ARTISTS needs the Spotify API, which isn't used when running with --offline."
+                                .to_string(),
+                        );
+                    }
+                    None => return Err("Library artist data not fetched.".to_string()),
+                })?;
+
+                if cx.user_config.debug && !cx.user_config.tui {
+                    info!("Filtered library artists")
+                }
+
+                for i in &valid {
+                    rows.push(Row::from_data(i)?);
+                }
+            }
+            DataSource::TopTracks(range) => {
+                let valid = self.top_tracks(match cx.data.top_track_data.get(range) {
+                    Some((_, tracks)) => tracks.clone(),
+                    None => return Err("Top track data not fetched.".to_string()),
+                })?;
+
+                if cx.user_config.debug && !cx.user_config.tui {
+                    info!("Filtered top tracks")
+                }
+
+                for i in &valid {
+                    rows.push(Row::from_data(i)?);
+                }
+            }
+            DataSource::TopArtists(range) => {
+                let valid = self.top_artists(match cx.data.top_artist_data.get(range) {
+                    Some((_, artists)) => artists.clone(),
+                    None => return Err("Top artist data not fetched.".to_string()),
+                })?;
+
+                if cx.user_config.debug && !cx.user_config.tui {
+                    info!("Filtered top artists")
+                }
+
+                for i in &valid {
+                    rows.push(Row::from_data(i)?);
+                }
+            }
+            DataSource::RecentlyPlayed => {
+                let valid = self.plays(match &cx.data.recently_played_data {
+                    Some(plays) => plays.clone(),
+                    None => return Err("Recently played data not fetched.".to_string()),
+                })?;
+
+                if cx.user_config.debug && !cx.user_config.tui {
+                    info!("Filtered recently played tracks")
+                }
+
+                for i in &valid {
+                    rows.push(Row::from_data(i)?);
+                }
+            }
+            DataSource::StreamingHistory => {
+                let conditions = self.source_conditions();
+                let used = self.used_attributes();
+                let attributes: Vec<String> = HistoryRecord::attributes()
+                    .into_iter()
+                    .filter(|x| used.contains(x))
+                    .collect();
+
+                // filtered as the history is read so only the matching plays are kept, with only the attributes the query uses
+                read_history(cx, |record| {
+                    if conditions.is_none()
+                        || compute_conditions(&record, conditions.as_ref().unwrap())?
+                    {
+                        rows.push(Row::from_attributes(&record, &attributes)?);
+                    }
+
+                    Ok(())
+                })?;
+
+                if cx.user_config.debug && !cx.user_config.tui {
+                    info!("Filtered streaming history")
+                }
+            }
+            DataSource::Playlist(_) | DataSource::SavedAlbum(_) | DataSource::Liked => {
+                let valid = self.tracks(self.source_tracks(cx)?)?;
+
+                if cx.user_config.debug && !cx.user_config.tui {
+                    info!("Filtered tracks")
+                }
+
+                for i in &valid {
+                    rows.push(Row::from_data(i)?);
+                }
+            }
+            DataSource::Duplicates(name, duplicate_match) => {
+                let playlists = match &cx.data.playlist_data {
+                    Some(playlists) if name.is_empty() => playlists.clone(),
+                    Some(playlists) => match playlists.iter().find(|x| x.name == *name) {
+                        Some(res) => vec![res.clone()],
+                        None => return Err(format!("No playlist with the name {}.", name)),
+                    },
+                    None => return Err("Playlist data not fetched.".to_string()),
+                };
+
+                let valid = self.duplicates(find_duplicates(&playlists, duplicate_match))?;
+
+                if cx.user_config.debug && !cx.user_config.tui {
+                    info!("Filtered duplicates")
+                }
+
+                for i in &valid {
+                    rows.push(Row::from_data(i)?);
+                }
+            }
+        };
+
+        Ok(rows)
+    }
+
+    /// The tracks in the result of the query, in order, used by statements that change playlists
+    pub fn result_tracks(&self, cx: &AppContext) -> Result<Vec<TrackData>, String> {
+        if self.aggregation != Aggregation::None {
+            return Err("Cannot use an aggregation to choose tracks.".to_string());
+        }
+
+        let tracks = self.source_tracks(cx)?;
+        let rows = self.evaluate(self.source_rows(cx)?)?;
+
+        let mut result: Vec<TrackData> = Vec::new();
+
+        for row in rows {
+            let id = match row.access("id") {
+                Ok(Value::Str(res)) => res,
+                _ => {
+                    return Err(
+                        "Every row of the query must have the id of a track, the query cannot be grouped."
+                            .to_string(),
+                    );
+                }
+            };
+
+            match tracks.iter().find(|x| x.id == id) {
+                Some(res) => result.push(res.clone()),
+                None => return Err(format!("No track with the id {}.", id)),
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Group, order and display the filtered data then save it if a save file is set
+    fn output(
+        self,
+        rows: Vec<Row>,
+        cx: &AppContext,
+        mut window: Option<&mut TUI>,
+        query_string: String,
+    ) -> Result<(), String> {
+        let output_file = match &self.into {
+            Some(res) => Some(res.clone()),
+            None => cx.save_file.clone().map(|path| OutputFile {
+                path,
+                append: false,
+            }),
+        };
+
+        // checked before anything is shown so a bad file doesn't leave only half the output
+        let playlist_output = (!cx.user_config.tui && cx.output_format.is_playlist())
+            || output_file
+                .as_ref()
+                .is_some_and(|x| file_format(cx, x).is_playlist());
+        if playlist_output && !self.source.has_tracks() {
+            return Err(format!(
+                "M3U8 and XSPF files can only be made from tracks, use a PLAYLIST, ALBUM, LIKED, TOP_TRACKS, RECENTLY_PLAYED or DUPLICATES source instead of {}.",
+                self.source
+            ));
+        }
+
+        if self.aggregation != Aggregation::None {
+            // a single aggregation over all the data
+            let aggregated = self.aggregate(&rows)?;
+
+            if cx.user_config.tui {
+                window
+                    .as_mut()
+                    .unwrap()
+                    .send_table_data(build_aggregation_table(
+                        self.aggregation.clone(),
+                        aggregated.clone(),
+                    ))? // if cx.user_config.tui is true then .unwrap() is safe
+            } else {
+                print_aggregation(
+                    self.aggregation.clone(),
+                    aggregated.clone(),
+                    self.targets.clone(),
+                    cx,
+                )?
+            }
+
+            if let Some(file) = output_file {
+                write_aggregation_result(
+                    cx,
+                    &file,
+                    self.aggregation,
+                    aggregated,
+                    self.targets,
+                    query_string,
+                )?;
+                saved(cx, window, &file)
+            }
+
+            return Ok(());
+        }
+
+        let rows = self.evaluate(rows)?;
+
+        if cx.user_config.tui {
+            window
+                .as_mut()
+                .unwrap()
+                .send_table_data(build_table(rows.clone(), self.targets.clone())?)? // if cx.user_config.tui is true then .unwrap() is safe
+        } else {
+            print_rows(rows.clone(), self.targets.clone(), cx)?
+        }
+
+        if let Some(file) = output_file {
+            write_result(cx, &file, rows, self.targets, query_string)?;
+            saved(cx, window, &file)
+        }
+
+        Ok(())
+    }
+
+    /// Compute the SELECT list, grouping, window functions and ordering for the filtered rows
+    fn evaluate(&self, mut rows: Vec<Row>) -> Result<Vec<Row>, String> {
+        if self.is_unnested() {
+            // the WHERE conditions are left until now so they can use the unnested columns
+            rows = self.unnest(rows)?;
+            rows = self.filter_rows(rows, &self.conditions)?;
+        }
+
+        rows = self.project(rows, false)?;
+
+        if let Some(group_by) = &self.group_by {
+            rows = self.having(self.project(self.group(rows, group_by)?, true)?)?;
+        }
+
+        if !self.window_columns().is_empty() {
+            rows = self.qualify(self.windows(rows)?)?;
+        }
+
+        if let Some(order) = &self.order {
+            Self::order(&mut rows, order)?;
+        }
+
+        Ok(rows)
+    }
+
+    /// Every attribute of the source the query reads, the rest can be left out of its rows
+    fn used_attributes(&self) -> Vec<String> {
+        let mut attributes: Vec<String> = Vec::new();
+
+        for column in &self.columns {
+            attributes.extend(column.expression.attributes());
+        }
+
+        for (_, attr) in &self.aggregates {
+            attributes.push(attr.clone());
+        }
+
+        for condition in [&self.conditions, &self.having, &self.qualify]
+            .into_iter()
+            .flatten()
+        {
+            attributes.extend(condition.attributes());
+        }
+
+        for key in self.order.iter().flatten() {
+            attributes.extend(key.expression.attributes());
+        }
+
+        attributes.extend(self.group_by.iter().flatten().cloned());
+
+        attributes
+    }
+
+    fn is_unnested(&self) -> bool {
+        self.columns
+            .iter()
+            .any(|x| matches!(x.expression, Expression::Unnest(_)))
+    }
+
+    /// The conditions to filter the source data with, unnested rows are filtered once they have been expanded
+    fn source_conditions(&self) -> Option<Condition> {
+        if self.is_unnested() {
+            None
+        } else {
+            self.conditions.clone()
+        }
+    }
+
+    /// Replace each row with one row for each element of the unnested attributes, rows with an empty list are dropped
+    fn unnest(&self, mut rows: Vec<Row>) -> Result<Vec<Row>, String> {
+        for column in &self.columns {
+            let attr = match &column.expression {
+                Expression::Unnest(res) => res,
+                _ => continue,
+            };
+
+            let mut expanded: Vec<Row> = Vec::new();
+
+            for row in rows {
+                let items = match row.access(attr)? {
+                    Value::List(res) => res,
+                    Value::Null => Vec::new(),
+                    res => vec![res],
+                };
+
+                for item in items {
+                    let mut new_row = row.clone();
+                    new_row.insert(column.name(), item);
+                    expanded.push(new_row);
+                }
+            }
+
+            rows = expanded;
+        }
+
+        Ok(rows)
+    }
+
+    /// Add the columns from the SELECT list to each row so they can be ordered on and displayed
+    /// aggregations can only be computed once the rows are grouped
+    fn project(&self, mut rows: Vec<Row>, grouped: bool) -> Result<Vec<Row>, String> {
+        for row in rows.iter_mut() {
+            for column in &self.columns {
+                match column.expression {
+                    _ if !grouped && column.expression.has_aggregate() => continue,
+                    Expression::Window(..) => continue, // computed across all the rows by windows()
+                    _ => {}
+                }
+
+                let name = column.name();
+
+                if !row.contains(&name) {
+                    let value = column.expression.evaluate(row)?;
+                    row.insert(name, value);
+                }
+            }
+        }
+
+        Ok(rows)
+    }
+
+    /// Split the data into partitions that share the same values for the given attributes, partitions are kept in the order they are first seen
+    fn partition<T>(data: Vec<T>, attributes: &Vec<String>) -> Result<Vec<Partition<T>>, String>
+    where
+        T: KeyAccess,
+    {
+        let mut partitions: Vec<Partition<T>> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+
+        if attributes.is_empty() {
+            // no attributes means everything is in one partition, even if there is no data
+            partitions.push((Vec::new(), data));
+        } else {
+            for i in data {
+                let mut key: Vec<Value> = Vec::new();
+
+                for attr in attributes {
+                    key.push(i.access(attr)?);
+                }
+
+                let key_str = format!("{:?}", key);
+
+                match index.get(&key_str) {
+                    Some(res) => partitions[*res].1.push(i),
+                    None => {
+                        index.insert(key_str, partitions.len());
+                        partitions.push((key, vec![i]));
+                    }
+                }
+            }
+        }
+
+        Ok(partitions)
+    }
+
+    /// Split the data into groups that share the same values for the group attributes, then compute the aggregates for each group
+    fn group<T>(&self, data: Vec<T>, group_by: &Vec<String>) -> Result<Vec<Row>, String>
+    where
+        T: KeyAccess,
+    {
+        let mut rows: Vec<Row> = Vec::new();
+
+        for (key, members) in Self::partition(data, group_by)? {
+            let mut row = Row::default();
+
+            for (attr, value) in group_by.iter().zip(key) {
+                row.insert(attr.clone(), value);
+            }
+
+            for (aggregation, attr) in &self.aggregates {
+                row.insert(
+                    aggregation.format(attr),
+                    aggregation.compute(&members, attr)?,
+                );
+            }
+
+            rows.push(row);
+        }
+
+        Ok(rows)
+    }
+
+    /// The window functions in the SELECT list and ORDER BY alongside the name of the column they are computed into
+    fn window_columns(&self) -> Vec<(String, &Window)> {
+        let mut windows: Vec<(String, &Window)> = Vec::new();
+
+        for column in &self.columns {
+            if let Expression::Window(res) = &column.expression {
+                windows.push((column.name(), res))
+            }
+        }
+
+        for key in self.order.iter().flatten() {
+            if let Expression::Window(res) = &key.expression
+                && !windows
+                    .iter()
+                    .any(|(name, _)| *name == key.expression.name())
+            {
+                windows.push((key.expression.name(), res))
+            }
+        }
+
+        windows
+    }
+
+    /// Compute each window function column, the rows come out grouped by partition and in window order
+    fn windows(&self, mut rows: Vec<Row>) -> Result<Vec<Row>, String> {
+        for (name, window) in self.window_columns() {
+            let mut computed: Vec<Row> = Vec::with_capacity(rows.len());
+
+            for (_, partition) in Self::partition(rows, &window.partition_by)? {
+                let mut partition = mergesort(&partition, &window.order)?;
+                let values = window.compute(&partition)?;
+
+                for (row, value) in partition.iter_mut().zip(values) {
+                    row.insert(name.clone(), value);
+                }
+
+                computed.extend(partition);
+            }
+
+            rows = computed;
+        }
+
+        Ok(rows)
+    }
+
+    /// Compute the aggregation for each target over all the rows
+    fn aggregate<T>(&self, data: &[T]) -> Result<HashMap<String, AggregationResult>, String>
+    where
+        T: KeyAccess,
+    {
+        let mut aggregated: HashMap<String, AggregationResult> = HashMap::new();
+
+        match self.aggregation {
+            Aggregation::Count => {
+                let count = AggregationResult::Int(data.len() as i64);
+
+                for i in &self.targets {
+                    aggregated.insert(i.clone(), count.clone());
+                }
+            }
+            Aggregation::Average => {
+                let count = data.len() as f64;
+
+                for i in &self.targets {
+                    let mut total: f64 = 0f64;
+
+                    for j in data {
+                        match j.access(i)? {
+                            Value::Int(res) => total += res as f64,
+                            Value::Float(res) => total += res,
+                            _ => {
+                                return Err(format!(
+                                    "Cannot average field {} as it is a non-numeric type.",
+                                    i
+                                ));
+                            }
+                        };
+                    }
+
+                    aggregated.insert(i.clone(), AggregationResult::Float(total / count));
+                }
+            }
+            Aggregation::Sum => {
+                for i in &self.targets {
+                    let sum = match self.aggregation.compute(data, i)? {
+                        Value::Float(res) => AggregationResult::Float(res),
+                        Value::Int(res) => AggregationResult::Int(res),
+                        _ => AggregationResult::Int(0), // the sum of no rows
+                    };
+
+                    aggregated.insert(i.clone(), sum);
+                }
+            }
+            Aggregation::None => {}
+        }
+
+        Ok(aggregated)
+    }
+
+    fn tracks(&self, data: Vec<TrackData>) -> Result<Vec<TrackData>, String> {
+        let mut valid: Vec<TrackData> = Vec::new();
+
+        let conditions = self.source_conditions();
+
+        for i in data {
+            if conditions.is_none() || compute_conditions(&i, conditions.as_ref().unwrap())? {
+                valid.push(i);
+            }
+        }
+
+        Ok(valid)
+    }
+
+    fn playlists(&self, data: Vec<PlaylistData>) -> Result<Vec<PlaylistData>, String> {
+        let mut valid: Vec<PlaylistData> = Vec::new();
+
+        let conditions = self.source_conditions();
+
+        for i in data {
+            if conditions.is_none() || compute_conditions(&i, conditions.as_ref().unwrap())? {
+                valid.push(i);
+            }
+        }
+
+        Ok(valid)
+    }
+
+    fn albums(&self, data: Vec<AlbumData>) -> Result<Vec<AlbumData>, String> {
+        let mut valid: Vec<AlbumData> = Vec::new();
+
+        let conditions = self.source_conditions();
+
+        for i in data {
+            if conditions.is_none() || compute_conditions(&i, conditions.as_ref().unwrap())? {
+                valid.push(i);
+            }
+        }
+
+        Ok(valid)
+    }
+
+    fn artists(&self, data: Vec<ArtistData>) -> Result<Vec<ArtistData>, String> {
+        let mut valid: Vec<ArtistData> = Vec::new();
+
+        let conditions = self.source_conditions();
+
+        for i in data {
+            if conditions.is_none() || compute_conditions(&i, conditions.as_ref().unwrap())? {
+                valid.push(i);
+            }
+        }
+
+        Ok(valid)
+    }
+
+    fn top_tracks(&self, data: Vec<TopTrack>) -> Result<Vec<TopTrack>, String> {
+        let mut valid: Vec<TopTrack> = Vec::new();
+
+        let conditions = self.source_conditions();
+
+        for i in data {
+            if conditions.is_none() || compute_conditions(&i, conditions.as_ref().unwrap())? {
+                valid.push(i);
+            }
+        }
+
+        Ok(valid)
+    }
+
+    fn top_artists(&self, data: Vec<TopArtist>) -> Result<Vec<TopArtist>, String> {
+        let mut valid: Vec<TopArtist> = Vec::new();
+
+        let conditions = self.source_conditions();
+
+        for i in data {
+            if conditions.is_none() || compute_conditions(&i, conditions.as_ref().unwrap())? {
+                valid.push(i);
+            }
+        }
+
+        Ok(valid)
+    }
+
+    fn plays(&self, data: Vec<Play>) -> Result<Vec<Play>, String> {
+        let mut valid: Vec<Play> = Vec::new();
+
+        let conditions = self.source_conditions();
+
+        for i in data {
+            if conditions.is_none() || compute_conditions(&i, conditions.as_ref().unwrap())? {
+                valid.push(i);
+            }
+        }
+
+        Ok(valid)
+    }
+
+    fn having(&self, data: Vec<Row>) -> Result<Vec<Row>, String> {
+        self.filter_rows(data, &self.having)
+    }
+
+    fn qualify(&self, data: Vec<Row>) -> Result<Vec<Row>, String> {
+        self.filter_rows(data, &self.qualify)
+    }
+
+    fn duplicates(&self, data: Vec<Duplicate>) -> Result<Vec<Duplicate>, String> {
+        let mut valid: Vec<Duplicate> = Vec::new();
+
+        let conditions = self.source_conditions();
+
+        for i in data {
+            if conditions.is_none() || compute_conditions(&i, conditions.as_ref().unwrap())? {
+                valid.push(i);
+            }
+        }
+
+        Ok(valid)
+    }
+
+    fn filter_rows(
+        &self,
+        data: Vec<Row>,
+        conditions: &Option<Condition>,
+    ) -> Result<Vec<Row>, String> {
+        let mut valid: Vec<Row> = Vec::new();
+
+        for i in data {
+            if conditions.is_none() || compute_conditions(&i, conditions.as_ref().unwrap())? {
+                valid.push(i);
+            }
+        }
+
+        Ok(valid)
+    }
+
+    fn order<T>(valid: &mut Vec<T>, order: &OrderBy) -> Result<(), String>
+    where
+        T: KeyAccess + Clone + Default + Debug,
+    {
+        *valid = mergesort(valid, order)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::args::OutputFormat;
+    use crate::history::history_csv;
+    use crate::query::data::{TimeRange, load_data_source, load_genres};
+    use crate::query::parse::parse;
+    use crate::query::tokenise::tokenise;
+    use crate::utils::date::DateSource;
+    use crate::utils::utils::secs_now;
+    use std::path::PathBuf;
+    use std::sync::mpsc::channel;
+    use std::sync::{Mutex, MutexGuard, OnceLock};
+    use std::thread;
+    use tokio::runtime::Runtime;
+    use warp::Filter;
+    use warp::http::Method;
+    use warp::hyper::body::Bytes;
+    use warp::path::FullPath;
+
+    /// Dates are as the API gives them, so release dates can be just a year or a month
+    fn track(
+        id: &str,
+        name: &str,
+        artists: &[&str],
+        popularity: u8,
+        album_name: &str,
+        release_date: &str,
+        added_at: &str,
+    ) -> TrackData {
+        TrackData {
+            id: id.to_string(),
+            name: name.to_string(),
+            duration: 200_000,
+            artists: artists.iter().map(|x| x.to_string()).collect(),
+            artist_ids: artists.iter().map(|x| x.to_lowercase()).collect(),
+            popularity,
+            album_name: album_name.to_string(),
+            release_date: Date::new(release_date.to_string(), DateSource::Spotify).unwrap(),
+            added_at: Date::new(added_at.to_string(), DateSource::Spotify).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    /// A playlist called All with two tracks from each of two artists and one from a pair of artists
+    /// Do I Wanna Know and Help! were added before they were released, Help! and Solo are title tracks
+    fn context() -> AppContext {
+        let tracks = vec![
+            track(
+                "1",
+                "Do I Wanna Know",
+                &["Arctic Monkeys"],
+                85,
+                "AM",
+                "2013-09-09",
+                "2013-09-01",
+            ),
+            track(
+                "2",
+                "R U Mine",
+                &["Arctic Monkeys"],
+                80,
+                "AM",
+                "2013-09-09",
+                "2020-01-01",
+            ),
+            track(
+                "3",
+                "Help!",
+                &["The Beatles"],
+                77,
+                "Help!",
+                "1965",
+                "1964-12-31",
+            ),
+            track(
+                "4",
+                "Yesterday",
+                &["The Beatles"],
+                60,
+                "Help!",
+                "1965-08",
+                "1965-08-01",
+            ),
+            track(
+                "5",
+                "Solo",
+                &["Someone", "Else"],
+                20,
+                "Solo",
+                "2021-05-01",
+                "2022-02-02",
+            ),
+        ];
+
+        let mut cx = AppContext::default();
+        cx.user_config.cache = false;
+        cx.user_config.tui = false;
+        cx.api_endpoint = mock_endpoint();
+        cx.config_dir = Some(config_dir());
+        cx.data.playlist_data = Some(vec![PlaylistData {
+            id: "pl1".to_string(),
+            name: "All".to_string(),
+            track_count: tracks.len() as u64,
+            tracks,
+            snapshot_id: "loaded".to_string(),
+            ..Default::default()
+        }]);
+
+        cx
+    }
+
+    fn statement(query: &str) -> Result<Statement, String> {
+        parse(tokenise(query.to_string())?)
+    }
+
+    /// The rows of a SELECT after grouping, window functions and ordering
+    fn select(cx: &AppContext, query: &str) -> Vec<Row> {
+        match statement(query).unwrap() {
+            Statement::Select(select) => select.evaluate(select.source_rows(cx).unwrap()).unwrap(),
+            res => panic!("{:?} is not a SELECT", res),
+        }
+    }
+
+    fn column(rows: &[Row], name: &str) -> Vec<Value> {
+        rows.iter().map(|x| x.access(name).unwrap()).collect()
+    }
+
+    fn strs(values: &[&str]) -> Vec<Value> {
+        values.iter().map(|x| Value::Str(x.to_string())).collect()
+    }
+
+    fn ints(values: &[i64]) -> Vec<Value> {
+        values.iter().map(|x| Value::Int(*x)).collect()
+    }
+
+    #[test]
+    fn group_by_aggregates_each_group() {
+        let rows = select(
+            &context(),
+            "SELECT artists, COUNT(name), AVERAGE(popularity) FROM PLAYLIST(All) GROUP BY artists;",
+        );
+
+        assert_eq!(
+            column(&rows, "artists"),
+            vec![
+                Value::List(strs(&["Arctic Monkeys"])),
+                Value::List(strs(&["The Beatles"])),
+                Value::List(strs(&["Someone", "Else"])),
+            ]
+        );
+        assert_eq!(column(&rows, "COUNT(name)"), ints(&[2, 2, 1]));
+        assert_eq!(
+            column(&rows, "AVERAGE(popularity)"),
+            vec![Value::Float(82.5), Value::Float(68.5), Value::Float(20.0)]
+        );
+    }
+
+    #[test]
+    fn having_filters_groups() {
+        let rows = select(
+            &context(),
+            "SELECT artists, COUNT(name) FROM PLAYLIST(All) GROUP BY artists HAVING AVERAGE(popularity) > 70;",
+        );
+
+        assert_eq!(
+            column(&rows, "artists"),
+            vec![Value::List(strs(&["Arctic Monkeys"]))]
+        );
+    }
+
+    #[test]
+    fn grouping_errors() {
+        for query in [
+            "SELECT name FROM PLAYLIST(All) HAVING COUNT(name) > 1;",
+            "SELECT name, COUNT(id) FROM PLAYLIST(All) GROUP BY artists;",
+            "SELECT * FROM PLAYLIST(All) GROUP BY artists;",
+            "SELECT artists FROM PLAYLIST(All) GROUP artists;",
+        ] {
+            assert!(statement(query).is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn order_by_case() {
+        let rows = select(
+            &context(),
+            "SELECT name FROM PLAYLIST(All) ORDER BY CASE WHEN popularity > 70 THEN 1 ELSE 0 END DESC, name;",
+        );
+
+        assert_eq!(
+            column(&rows, "name"),
+            strs(&["Do I Wanna Know", "Help!", "R U Mine", "Solo", "Yesterday"])
+        );
+    }
+
+    #[test]
+    fn order_by_window() {
+        let rows = select(
+            &context(),
+            "SELECT name FROM PLAYLIST(All) ORDER BY ROW_NUMBER() OVER (ORDER BY popularity) DESC;",
+        );
+
+        assert_eq!(
+            column(&rows, "name"),
+            strs(&["Do I Wanna Know", "R U Mine", "Help!", "Yesterday", "Solo"])
+        );
+    }
+
+    #[test]
+    fn order_by_errors() {
+        for query in [
+            "SELECT name FROM PLAYLIST(All) ORDER BY COUNT(name);",
+            "SELECT name, ROW_NUMBER() OVER (ORDER BY RANK() OVER (ORDER BY name)) FROM PLAYLIST(All);",
+            "REORDER PLAYLIST(All) BY ROW_NUMBER() OVER (ORDER BY name);",
+        ] {
+            assert!(statement(query).is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn qualify_filters_window_results() {
+        let rows = select(
+            &context(),
+            "SELECT name, ROW_NUMBER() OVER (PARTITION BY artists ORDER BY popularity DESC) AS rn FROM PLAYLIST(All) QUALIFY rn <= 1;",
+        );
+
+        assert_eq!(
+            column(&rows, "name"),
+            strs(&["Do I Wanna Know", "Help!", "Solo"])
+        );
+        assert_eq!(column(&rows, "rn"), ints(&[1, 1, 1]));
+    }
+
+    #[test]
+    fn ranks_share_ties() {
+        let rows = select(
+            &context(),
+            "SELECT name, RANK() OVER (ORDER BY artists) AS r, DENSE_RANK() OVER (ORDER BY artists) AS d FROM PLAYLIST(All) ORDER BY r, name;",
+        );
+
+        assert_eq!(column(&rows, "r"), ints(&[1, 1, 3, 4, 4]));
+        assert_eq!(column(&rows, "d"), ints(&[1, 1, 2, 3, 3]));
+    }
+
+    #[test]
+    fn running_sum_over_window() {
+        let rows = select(
+            &context(),
+            "SELECT name, SUM(popularity) OVER (ORDER BY popularity DESC) AS total FROM PLAYLIST(All);",
+        );
+
+        assert_eq!(column(&rows, "total"), ints(&[85, 165, 242, 302, 322]));
+    }
+
+    #[test]
+    fn running_sum_shares_ties() {
+        let rows = select(
+            &context(),
+            "SELECT name, SUM(popularity) OVER (ORDER BY artists) AS total FROM PLAYLIST(All) ORDER BY total, name;",
+        );
+
+        assert_eq!(
+            column(&rows, "name"),
+            strs(&["Do I Wanna Know", "R U Mine", "Solo", "Help!", "Yesterday"])
+        );
+        assert_eq!(column(&rows, "total"), ints(&[165, 165, 185, 322, 322]));
+    }
+
+    #[test]
+    fn window_errors() {
+        for query in [
+            "SELECT name FROM PLAYLIST(All) QUALIFY name == 'Help!';",
+            "SELECT name FROM PLAYLIST(All) QUALIFY popularity > 70;",
+            "SELECT name, RANK() FROM PLAYLIST(All);",
+            "SELECT name, ROW_NUMBER() OVER (ORDER BY COUNT(name)) FROM PLAYLIST(All);",
+        ] {
+            assert!(statement(query).is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn playlist_files_need_tracks() {
+        let mut cx = context();
+        cx.output_format = OutputFormat::M3u8;
+        cx.data.saved_album_data = Some(Vec::new());
+
+        for query in ["SELECT name FROM PLAYLISTS;", "SELECT name FROM ALBUMS;"] {
+            let res = statement(query)
+                .unwrap()
+                .run(&mut cx, None, query.to_string());
+
+            assert!(
+                res.unwrap_err()
+                    .starts_with("M3U8 and XSPF files can only be made from tracks"),
+                "{}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn into_can_append() {
+        for (query, append) in [
+            ("SELECT name FROM PLAYLIST(All) INTO \"out.csv\";", false),
+            (
+                "SELECT name FROM PLAYLIST(All) INTO \"out.csv\" APPEND;",
+                true,
+            ),
+            (
+                "SELECT name FROM PLAYLIST(All) INTO \"out.csv\" APPEND ORDER BY name;",
+                true,
+            ),
+        ] {
+            match statement(query).unwrap() {
+                Statement::Select(select) => assert_eq!(
+                    select.into,
+                    Some(OutputFile {
+                        path: "out.csv".to_string(),
+                        append
+                    })
+                ),
+                res => panic!("{:?} is not a SELECT", res),
+            }
+        }
+
+        assert!(statement("SELECT name FROM PLAYLIST(All) INTO out.csv;").is_err());
+    }
+
+    #[test]
+    fn case_groups_by_its_alias() {
+        let rows = select(
+            &context(),
+            "SELECT CASE WHEN popularity > 70 THEN 'hit' WHEN popularity > 40 THEN 'known' ELSE 'deep cut' END AS tier, COUNT(name) FROM PLAYLIST(All) GROUP BY tier ORDER BY tier;",
+        );
+
+        assert_eq!(column(&rows, "tier"), strs(&["deep cut", "hit", "known"]));
+        assert_eq!(column(&rows, "COUNT(name)"), ints(&[1, 3, 1]));
+    }
+
+    #[test]
+    fn simple_case_without_else_is_null() {
+        let rows = select(
+            &context(),
+            "SELECT CASE name WHEN 'Help!' THEN 1 WHEN 'Solo' THEN 2.5 END AS n FROM PLAYLIST(All) ORDER BY name;",
+        );
+
+        assert_eq!(
+            column(&rows, "n"),
+            vec![
+                Value::Null,
+                Value::Float(1.0),
+                Value::Null,
+                Value::Float(2.5),
+                Value::Null,
+            ]
+        );
+    }
+
+    #[test]
+    fn unaliased_cases_keep_their_own_values() {
+        let rows = select(
+            &context(),
+            "SELECT name, CASE WHEN popularity > 70 THEN 1 ELSE 0 END, CASE WHEN popularity > 50 THEN 1 ELSE 0 END FROM PLAYLIST(All) ORDER BY name;",
+        );
+
+        assert_eq!(column(&rows, "CASE"), ints(&[1, 1, 1, 0, 0]));
+        assert_eq!(column(&rows, "CASE 2"), ints(&[1, 1, 1, 0, 1]));
+    }
+
+    #[test]
+    fn case_errors() {
+        for query in [
+            "SELECT CASE WHEN popularity > 70 THEN 'hit' ELSE 0 END FROM PLAYLIST(All);",
+            "SELECT CASE WHEN popularity > 70 THEN 'hit' FROM PLAYLIST(All);",
+            "SELECT CASE ELSE 'hit' END FROM PLAYLIST(All);",
+            "SELECT CASE WHEN popularity > 70 THEN 'hit' END, COUNT(name) FROM PLAYLIST(All) GROUP BY artists;",
+        ] {
+            assert!(statement(query).is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn unnest_groups_by_each_element() {
+        let rows = select(
+            &context(),
+            "SELECT UNNEST(artists) AS artist, COUNT(name) FROM PLAYLIST(All) GROUP BY artist ORDER BY COUNT(name) DESC;",
+        );
+
+        assert_eq!(
+            column(&rows, "artist"),
+            strs(&["Arctic Monkeys", "The Beatles", "Someone", "Else"])
+        );
+        assert_eq!(column(&rows, "COUNT(name)"), ints(&[2, 2, 1, 1]));
+    }
+
+    #[test]
+    fn unnest_filters_each_element() {
+        let rows = select(
+            &context(),
+            "SELECT name, UNNEST(artists) AS artist FROM PLAYLIST(All) WHERE artist == \"Else\";",
+        );
+
+        assert_eq!(column(&rows, "name"), strs(&["Solo"]));
+    }
+
+    #[test]
+    fn list_conditions() {
+        let cx = context();
+
+        for (query, expected) in [
+            (
+                "SELECT name FROM PLAYLIST(All) WHERE artists CONTAINS ANY [\"Else\", \"The Beatles\"];",
+                strs(&["Help!", "Yesterday", "Solo"]),
+            ),
+            (
+                "SELECT name FROM PLAYLIST(All) WHERE artists CONTAINS ALL [\"Else\", \"Someone\"];",
+                strs(&["Solo"]),
+            ),
+            (
+                "SELECT name FROM PLAYLIST(All) WHERE artists CONTAINS ALL [\"Else\", \"The Beatles\"];",
+                strs(&[]),
+            ),
+            (
+                "SELECT name FROM PLAYLIST(All) WHERE artists LIKE 'monkeys';",
+                strs(&["Do I Wanna Know", "R U Mine"]),
+            ),
+        ] {
+            assert_eq!(column(&select(&cx, query), "name"), expected, "{}", query);
+        }
+    }
+
+    #[test]
+    fn attributes_on_both_sides() {
+        let cx = context();
+
+        let rows = select(
+            &cx,
+            "SELECT name FROM PLAYLIST(All) WHERE added_at < release_date;",
+        );
+        assert_eq!(column(&rows, "name"), strs(&["Do I Wanna Know", "Help!"]));
+
+        let rows = select(
+            &cx,
+            "SELECT name FROM PLAYLIST(All) WHERE album_name == name;",
+        );
+        assert_eq!(column(&rows, "name"), strs(&["Help!", "Solo"]));
+
+        let rows = select(
+            &cx,
+            "SELECT name FROM PLAYLIST(All) WHERE name != album_name;",
+        );
+        assert_eq!(
+            column(&rows, "name"),
+            strs(&["Do I Wanna Know", "R U Mine", "Yesterday"])
+        );
+    }
+
+    #[test]
+    fn dates_with_different_scopes() {
+        let cx = context();
+
+        // Yesterday was added on the day its month long release date starts, which orders the same
+        // but isn't equal as the dates aren't as precise as each other
+        let rows = select(
+            &cx,
+            "SELECT name FROM PLAYLIST(All) WHERE added_at <= release_date;",
+        );
+        assert_eq!(
+            column(&rows, "name"),
+            strs(&["Do I Wanna Know", "Help!", "Yesterday"])
+        );
+
+        let rows = select(
+            &cx,
+            "SELECT name FROM PLAYLIST(All) WHERE added_at == release_date;",
+        );
+        assert!(rows.is_empty());
+
+        // the day before a release that is only a year is still before it
+        let rows = select(
+            &cx,
+            "SELECT name FROM PLAYLIST(All) WHERE release_date > added_at AND release_date < 1966;",
+        );
+        assert_eq!(column(&rows, "name"), strs(&["Help!"]));
+    }
+
+    #[test]
+    fn case_operand_in_where() {
+        let rows = select(
+            &context(),
+            "SELECT name FROM PLAYLIST(All) WHERE CASE WHEN popularity > 70 THEN 'hit' ELSE 'other' END == 'other';",
+        );
+
+        assert_eq!(column(&rows, "name"), strs(&["Yesterday", "Solo"]));
+    }
+
+    #[test]
+    fn case_inside_a_case_condition() {
+        let rows = select(
+            &context(),
+            "SELECT name, CASE WHEN CASE WHEN popularity > 70 THEN 1 ELSE 0 END == 1 THEN 'hit' ELSE 'other' END AS tier FROM PLAYLIST(All) WHERE artists CONTAINS ANY [\"The Beatles\"];",
+        );
+
+        assert_eq!(column(&rows, "tier"), strs(&["hit", "other"]));
+    }
+
+    #[test]
+    fn aggregate_operands_after_grouping() {
+        let cx = context();
+
+        let rows = select(
+            &cx,
+            "SELECT artists, SUM(popularity) FROM PLAYLIST(All) GROUP BY artists HAVING SUM(popularity) > AVERAGE(popularity);",
+        );
+        assert_eq!(column(&rows, "SUM(popularity)"), ints(&[165, 137]));
+
+        let rows = select(
+            &cx,
+            "SELECT artists, CASE WHEN COUNT(name) > 1 THEN 'several' ELSE 'one' END AS size FROM PLAYLIST(All) GROUP BY artists HAVING CASE WHEN AVERAGE(popularity) > 30 THEN 1 ELSE 0 END == 1;",
+        );
+        assert_eq!(column(&rows, "size"), strs(&["several", "several"]));
+    }
+
+    #[test]
+    fn operand_errors() {
+        for query in [
+            "SELECT name FROM PLAYLIST(All) WHERE COUNT(name) > 1;",
+            "SELECT name FROM PLAYLIST(All) WHERE ROW_NUMBER() OVER (ORDER BY name) == 1;",
+            "SELECT name FROM PLAYLIST(All) QUALIFY COUNT(name) > 1;",
+            "SELECT artists, COUNT(name) FROM PLAYLIST(All) GROUP BY artists HAVING COUNT(name, id) > 1;",
+        ] {
+            assert!(statement(query).is_err(), "{}", query);
+        }
+    }
+
+    /// What the mock API has been sent, and the version of every playlist
+    struct MockApi {
+        snapshot_id: String,
+        /// Each request as its method, path and body
+        requests: Vec<(String, String, String)>,
+        /// The playlists with a snapshot saved when each change was sent
+        snapshotted: Vec<Vec<String>>,
+    }
+
+    static MOCK_API: Mutex<MockApi> = Mutex::new(MockApi {
+        snapshot_id: String::new(),
+        requests: Vec::new(),
+        snapshotted: Vec::new(),
+    });
+
+    fn mock_artist(id: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "name": id,
+            "genres": [format!("{} genre", id)],
+            "followers": { "total": 0 },
+            "popularity": 0,
+        })
+    }
+
+    fn mock_track(id: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "name": id,
+            "duration_ms": 200000,
+            "popularity": 50,
+            "album": { "id": "al1", "name": "Album", "release_date": "2013-09-09" },
+            "artists": [{ "id": "ar1", "name": "Arctic Monkeys" }],
+        })
+    }
+
+    /// Reply to a request the way the Spotify API would, changing the tracks of a playlist gives it a new version
+    fn mock_response(method: Method, path: FullPath, query: String, body: Bytes) -> String {
+        let mut api = MOCK_API.lock().unwrap();
+        let path = path.as_str().to_string();
+        let query: Vec<(String, String)> = serde_urlencoded::from_str(&query).unwrap();
+
+        api.requests.push((
+            method.to_string(),
+            path.clone(),
+            match query.iter().find(|(k, _)| k == "ids") {
+                Some((_, ids)) => ids.clone(),
+                None => String::from_utf8_lossy(&body).to_string(),
+            },
+        ));
+
+        if method != Method::GET {
+            let cx = AppContext {
+                config_dir: Some(config_dir()),
+                ..Default::default()
+            };
+            let snapshotted = load_snapshots(&cx)
+                .unwrap()
+                .into_iter()
+                .map(|x| x.playlist.id)
+                .collect();
+            api.snapshotted.push(snapshotted);
+        }
+
+        if path.ends_with("/tracks") {
+            api.snapshot_id = "changed".to_string();
+        }
+
+        match path.as_str() {
+            "/me" => r#"{"id": "user"}"#.to_string(),
+            "/users/user/playlists" => r#"{"id": "new"}"#.to_string(),
+            // every artist has a genre named after them
+            "/artists" => {
+                let artists: Vec<serde_json::Value> = query
+                    .iter()
+                    .filter(|(k, _)| k == "ids")
+                    .flat_map(|(_, ids)| ids.split(","))
+                    .map(mock_artist)
+                    .collect();
+
+                serde_json::json!({ "artists": artists }).to_string()
+            }
+            // two pages of followed artists, the cursor is null on the last one
+            "/me/following" => {
+                let (id, after) = match query.iter().find(|(k, _)| k == "after") {
+                    Some(_) => ("followed2", serde_json::Value::Null),
+                    None => ("followed1", serde_json::json!("followed1")),
+                };
+
+                serde_json::json!({
+                    "artists": {
+                        "items": [mock_artist(id)],
+                        "cursors": { "after": after },
+                    }
+                })
+                .to_string()
+            }
+            // the top tracks and artists are named after their position
+            "/me/top/tracks" => {
+                let tracks: Vec<serde_json::Value> = ["top1", "top2", "top3"]
+                    .into_iter()
+                    .map(mock_track)
+                    .collect();
+
+                serde_json::json!({ "items": tracks }).to_string()
+            }
+            "/me/top/artists" => {
+                let artists: Vec<serde_json::Value> =
+                    ["top1", "top2"].into_iter().map(mock_artist).collect();
+
+                serde_json::json!({ "items": artists }).to_string()
+            }
+            _ => format!(r#"{{"snapshot_id": "{}"}}"#, api.snapshot_id),
+        }
+    }
+
+    /// The mock API's address, it is started by the first test to use it
+    fn mock_endpoint() -> String {
+        static ENDPOINT: OnceLock<String> = OnceLock::new();
+
+        ENDPOINT
+            .get_or_init(|| {
+                let (tx, rx) = channel();
+
+                thread::spawn(move || {
+                    let rt = Runtime::new().expect("Could not init tokio runtime");
+                    rt.block_on(async move {
+                        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                        tx.send(listener.local_addr().unwrap()).unwrap();
+
+                        let routes = warp::method()
+                            .and(warp::path::full())
+                            .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+                            .and(warp::body::bytes())
+                            .map(mock_response);
+
+                        warp::serve(routes).incoming(listener).run().await;
+                    });
+                });
+
+                format!("http://{}", rx.recv().unwrap())
+            })
+            .clone()
+    }
+
+    /// Files are kept in a temp folder rather than the user's config folder
+    fn config_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("spotifyQL-test-{}", std::process::id()))
+    }
+
+    /// Reset the mock API, tests that use it hold the guard so they run one at a time
+    fn mock_api(snapshot_id: &str) -> MutexGuard<'static, ()> {
+        static API_TESTS: Mutex<()> = Mutex::new(());
+
+        let guard = API_TESTS.lock().unwrap_or_else(|x| x.into_inner());
+
+        let mut api = MOCK_API.lock().unwrap();
+        api.snapshot_id = snapshot_id.to_string();
+        api.requests.clear();
+        api.snapshotted.clear();
+
+        guard
+    }
+
+    fn requests() -> Vec<(String, String, String)> {
+        MOCK_API.lock().unwrap().requests.clone()
+    }
+
+    fn apply(cx: &mut AppContext, query: &str) -> Result<(), String> {
+        statement(query)?.changes(cx)?.unwrap().apply(cx, None)
+    }
+
+    fn playlist<'a>(cx: &'a AppContext, id: &str) -> &'a PlaylistData {
+        cx.data
+            .playlist_data
+            .as_ref()
+            .and_then(|x| x.iter().find(|x| x.id == id))
+            .unwrap()
+    }
+
+    #[test]
+    fn create_playlist_with_the_name_of_another() {
+        let _guard = mock_api("loaded");
+        let mut cx = context();
+
+        apply(
+            &mut cx,
+            "CREATE PLAYLIST \"All\" AS SELECT * FROM PLAYLIST(All) WHERE popularity > 78;",
+        )
+        .unwrap();
+
+        let requests = requests();
+        assert_eq!(requests[1].0, "POST");
+        assert_eq!(requests[1].1, "/users/user/playlists");
+        assert_eq!(requests[2].1, "/playlists/new/tracks");
+        assert!(
+            requests[2]
+                .2
+                .contains(r#""spotify:track:1","spotify:track:2""#)
+        );
+
+        // the tracks go in the new playlist, not the one it shares a name with
+        let created = playlist(&cx, "new");
+        assert_eq!(created.name, "All");
+        assert_eq!(created.track_count, 2);
+        assert_eq!(created.snapshot_id, "changed");
+        assert_eq!(playlist(&cx, "pl1").tracks.len(), 5);
+    }
+
+    #[test]
+    fn insert_adds_to_the_end() {
+        let _guard = mock_api("loaded");
+        let mut cx = context();
+
+        apply(
+            &mut cx,
+            "INSERT INTO PLAYLIST(All) SELECT * FROM PLAYLIST(All) WHERE popularity < 30;",
+        )
+        .unwrap();
+
+        let requests = requests();
+        assert_eq!(requests[0].1, "/playlists/pl1");
+        assert_eq!(requests[1].0, "POST");
+        assert_eq!(requests[1].1, "/playlists/pl1/tracks");
+        assert!(requests[1].2.contains(r#"["spotify:track:5"]"#));
+
+        let changed = playlist(&cx, "pl1");
+        assert_eq!(changed.tracks.last().unwrap().id, "5");
+        assert_eq!(changed.track_count, 6);
+        assert_eq!(changed.snapshot_id, "changed");
+    }
+
+    #[test]
+    fn changed_playlist_is_not_written_to() {
+        let _guard = mock_api("edited elsewhere");
+        let mut cx = context();
+        cx.data.playlist_data_ct = 1;
+
+        let err = apply(
+            &mut cx,
+            "INSERT INTO PLAYLIST(All) SELECT * FROM PLAYLIST(All) WHERE popularity < 30;",
+        )
+        .unwrap_err();
+
+        assert!(
+            err.contains("has been changed since it was loaded"),
+            "{}",
+            err
+        );
+        assert_eq!(requests().len(), 1);
+        assert_eq!(playlist(&cx, "pl1").tracks.len(), 5);
+        assert_eq!(cx.data.playlist_data_ct, 0);
+    }
+
+    #[test]
+    fn delete_removes_every_matching_track() {
+        let _guard = mock_api("loaded");
+        let mut cx = context();
+
+        apply(
+            &mut cx,
+            "DELETE FROM PLAYLIST(All) WHERE \"The Beatles\" IN artists;",
+        )
+        .unwrap();
+
+        let sent = requests();
+        assert_eq!(sent[1].0, "DELETE");
+        assert_eq!(sent[1].1, "/playlists/pl1/tracks");
+        assert!(sent[1].2.contains("spotify:track:3"));
+        assert!(sent[1].2.contains("spotify:track:4"));
+
+        let changed = playlist(&cx, "pl1");
+        assert_eq!(track_ids(&changed.tracks), vec!["1", "2", "5"]);
+        assert_eq!(changed.track_count, 3);
+    }
+
+    #[test]
+    fn dedupe_puts_back_the_kept_copy() {
+        let _guard = mock_api("loaded");
+        let mut cx = context();
+
+        let all = &mut cx.data.playlist_data.as_mut().unwrap()[0];
+        all.tracks.push(track(
+            "1",
+            "Do I Wanna Know",
+            &["Arctic Monkeys"],
+            85,
+            "AM",
+            "2013-09-09",
+            "2013-09-01",
+        ));
+        all.track_count = 6;
+
+        apply(&mut cx, "DEDUPE PLAYLIST(All) MATCH ID KEEP FIRST;").unwrap();
+
+        // the API removes every copy, so the first is inserted back where it was
+        let sent = requests();
+        assert_eq!(sent[1].0, "DELETE");
+        assert_eq!(sent[2].0, "POST");
+        assert!(sent[2].2.contains(r#""position":0"#));
+
+        let changed = playlist(&cx, "pl1");
+        assert_eq!(track_ids(&changed.tracks), vec!["1", "2", "3", "4", "5"]);
+        assert_eq!(changed.track_count, 5);
+    }
+
+    #[test]
+    fn reorder_moves_each_track_against_the_latest_version() {
+        let _guard = mock_api("loaded");
+        let mut cx = context();
+
+        apply(&mut cx, "REORDER PLAYLIST(All) BY popularity;").unwrap();
+
+        let moves: Vec<(String, String, String)> =
+            requests().into_iter().filter(|x| x.0 == "PUT").collect();
+        assert_eq!(moves.len(), 4);
+        assert!(moves[0].2.contains(r#""snapshot_id":"loaded""#));
+        assert!(moves[1].2.contains(r#""snapshot_id":"changed""#));
+
+        let changed = playlist(&cx, "pl1");
+        assert_eq!(track_ids(&changed.tracks), vec!["5", "4", "3", "2", "1"]);
+        assert_eq!(changed.snapshot_id, "changed");
+    }
+
+    /// Requests other than GETs, the ones that would change something
+    fn writes() -> Vec<(String, String, String)> {
+        requests().into_iter().filter(|x| x.0 != "GET").collect()
+    }
+
+    fn run(cx: &mut AppContext, query: &str) -> Result<(), String> {
+        statement(query)?.run(cx, None, query.to_string())
+    }
+
+    #[test]
+    fn preview_makes_no_changes() {
+        let _guard = mock_api("loaded");
+        let mut cx = context();
+
+        let query = "PREVIEW DELETE FROM PLAYLIST(All) WHERE popularity > 70;";
+        assert!(
+            statement(query)
+                .unwrap()
+                .needs_confirmation(&mut cx)
+                .unwrap()
+                .is_none()
+        );
+        run(&mut cx, query).unwrap();
+
+        assert!(writes().is_empty());
+        assert_eq!(playlist(&cx, "pl1").tracks.len(), 5);
+    }
+
+    #[test]
+    fn dry_run_makes_no_changes() {
+        let _guard = mock_api("loaded");
+        let mut cx = context();
+        cx.dry_run = true;
+
+        run(&mut cx, "DELETE FROM PLAYLIST(All) WHERE popularity > 70;").unwrap();
+        run(&mut cx, "REORDER PLAYLIST(All) BY name;").unwrap();
+
+        assert!(writes().is_empty());
+        assert_eq!(
+            track_ids(&playlist(&cx, "pl1").tracks),
+            vec!["1", "2", "3", "4", "5"]
+        );
+    }
+
+    #[test]
+    fn big_changes_need_yes_without_a_terminal() {
+        let _guard = mock_api("loaded");
+        let mut cx = context();
+        cx.user_config.confirm_threshold = 2;
+        cx.interactive = false;
+
+        let query = "DELETE FROM PLAYLIST(All) WHERE popularity > 70;";
+        let changes = statement(query)
+            .unwrap()
+            .needs_confirmation(&mut cx)
+            .unwrap();
+        assert_eq!(changes.map(|x| x.len()), Some(3));
+
+        let err = run(&mut cx, query).unwrap_err();
+        assert!(
+            err.contains("more than the confirm threshold of 2"),
+            "{}",
+            err
+        );
+        assert!(writes().is_empty());
+
+        // at or under the threshold doesn't ask
+        run(&mut cx, "DELETE FROM PLAYLIST(All) WHERE popularity < 30;").unwrap();
+        assert_eq!(writes().len(), 1);
+
+        cx.assume_yes = true;
+        assert!(
+            statement(query)
+                .unwrap()
+                .needs_confirmation(&mut cx)
+                .unwrap()
+                .is_none()
+        );
+        run(&mut cx, query).unwrap();
+        assert_eq!(track_ids(&playlist(&cx, "pl1").tracks), vec!["4"]);
+    }
+
+    /// The test playlist under its own id, so the snapshots of other tests taken in the same second aren't used
+    fn snapshot_context(id: &str) -> AppContext {
+        let mut cx = context();
+        cx.data.playlist_data.as_mut().unwrap()[0].id = id.to_string();
+
+        cx
+    }
+
+    #[test]
+    fn changes_are_snapshotted_first() {
+        let _guard = mock_api("loaded");
+        let mut cx = snapshot_context("snapshotted");
+
+        apply(&mut cx, "DELETE FROM PLAYLIST(All) WHERE popularity > 70;").unwrap();
+
+        let snapshotted = MOCK_API.lock().unwrap().snapshotted.clone();
+        assert_eq!(snapshotted.len(), 1);
+        assert!(snapshotted[0].contains(&"snapshotted".to_string()));
+
+        let snapshot = find_snapshot(&cx, &"snapshotted".to_string(), secs_now()).unwrap();
+        assert_eq!(
+            track_ids(&snapshot.playlist.tracks),
+            vec!["1", "2", "3", "4", "5"]
+        );
+        assert_eq!(
+            track_ids(&playlist(&cx, "snapshotted").tracks),
+            vec!["4", "5"]
+        );
+    }
+
+    #[test]
+    fn restore_puts_back_the_snapshot_in_order() {
+        let _guard = mock_api("loaded");
+        let mut cx = snapshot_context("restored");
+
+        let mut saved = playlist(&cx, "restored").clone();
+        saved.tracks = vec![
+            saved.tracks[4].clone(),
+            saved.tracks[2].clone(),
+            saved.tracks[0].clone(),
+        ];
+        let timestamp = take_snapshot(&cx, &saved).unwrap();
+
+        assert!(
+            statement(&format!("RESTORE PLAYLIST(All) TO {};", timestamp - 1))
+                .unwrap()
+                .changes(&mut cx)
+                .is_err()
+        );
+
+        apply(&mut cx, &format!("RESTORE PLAYLIST(All) TO {};", timestamp)).unwrap();
+
+        let sent = writes();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, "PUT");
+        assert_eq!(sent[0].1, "/playlists/restored/tracks");
+        assert!(
+            sent[0]
+                .2
+                .contains(r#"["spotify:track:5","spotify:track:3","spotify:track:1"]"#)
+        );
+        assert_eq!(
+            track_ids(&playlist(&cx, "restored").tracks),
+            vec!["5", "3", "1"]
+        );
+
+        let snapshots = load_snapshots(&cx).unwrap();
+        assert!(
+            snapshots
+                .windows(2)
+                .all(|x| x[0].timestamp <= x[1].timestamp)
+        );
+        assert!(snapshots.iter().any(|x| x.playlist.id == "restored"));
+        run(&mut cx, "SHOW SNAPSHOTS;").unwrap();
+    }
+
+    #[test]
+    fn streaming_history_keeps_only_used_attributes() {
+        let _guard = mock_api("");
+        let cx = context();
+
+        let plays: Vec<String> = [
+            ("Arctic Monkeys", 200_000, false),
+            ("The Beatles", 100_000, false),
+            ("Arctic Monkeys", 50_000, true),
+            ("Arctic Monkeys", 120_000, false),
+        ]
+        .iter()
+        .map(|(artist, ms_played, skipped)| {
+            history_csv(&HistoryRecord {
+                played_at: Date::from_timestamp("2024-01-01T10:00:00Z".to_string()).unwrap(),
+                ms_played: *ms_played,
+                artist: artist.to_string(),
+                skipped: *skipped,
+                ..Default::default()
+            })
+        })
+        .collect();
+        write_file(
+            &cx,
+            File::StreamingHistory,
+            plays.join("\n"),
+            WriteMode::Overwrite,
+        )
+        .unwrap();
+
+        let query = "SELECT artist, SUM(ms_played) FROM STREAMING_HISTORY WHERE skipped == false GROUP BY artist;";
+
+        let rows = match statement(query).unwrap() {
+            Statement::Select(select) => select.source_rows(&cx).unwrap(),
+            res => panic!("{:?} is not a SELECT", res),
+        };
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            rows[0]
+                .values
+                .iter()
+                .map(|(k, _)| k.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["ms_played", "artist", "skipped"]
+        );
+
+        let rows = select(&cx, query);
+        assert_eq!(column(&rows, "SUM(ms_played)"), ints(&[320_000, 100_000]));
+    }
+
+    #[test]
+    fn followed_artists_stop_at_the_last_cursor() {
+        let _guard = mock_api("");
+        let mut cx = context();
+
+        load_data_source(&mut cx, DataSource::FollowedArtists).unwrap();
+
+        let sent = requests();
+        assert_eq!(sent.len(), 2);
+        assert!(sent.iter().all(|x| x.1 == "/me/following"));
+
+        let rows = select(&cx, "SELECT id, genres FROM FOLLOWED_ARTISTS;");
+        assert_eq!(column(&rows, "id"), strs(&["followed1", "followed2"]));
+        assert_eq!(
+            column(&rows, "genres")[0],
+            Value::List(strs(&["followed1 genre"]))
+        );
+    }
+
+    #[test]
+    fn top_tracks_and_artists_are_ranked_from_one() {
+        let _guard = mock_api("");
+        let mut cx = context();
+
+        load_data_source(&mut cx, DataSource::TopTracks(TimeRange::Short)).unwrap();
+        load_data_source(&mut cx, DataSource::TopArtists(TimeRange::Long)).unwrap();
+
+        let rows = select(&cx, "SELECT rank, id FROM TOP_TRACKS(short);");
+        assert_eq!(column(&rows, "rank"), ints(&[1, 2, 3]));
+        assert_eq!(column(&rows, "id"), strs(&["top1", "top2", "top3"]));
+
+        let rows = select(
+            &cx,
+            "SELECT rank, name FROM TOP_ARTISTS(long) WHERE rank > 1;",
+        );
+        assert_eq!(column(&rows, "rank"), ints(&[2]));
+        assert_eq!(column(&rows, "name"), strs(&["top2"]));
+    }
+
+    #[test]
+    fn genres_only_fetch_the_artists_of_the_source() {
+        let _guard = mock_api("");
+        let mut cx = context();
+        cx.data.playlist_data.as_mut().unwrap().push(PlaylistData {
+            id: "pl2".to_string(),
+            name: "Other".to_string(),
+            tracks: vec![track(
+                "6",
+                "Bohemian Rhapsody",
+                &["Queen"],
+                90,
+                "A Night at the Opera",
+                "1975-10-31",
+                "2019-06-01",
+            )],
+            track_count: 1,
+            ..Default::default()
+        });
+
+        let query = "SELECT name, genres FROM PLAYLIST(All) WHERE \"the beatles genre\" IN genres;";
+        let select = match statement(query).unwrap() {
+            Statement::Select(select) => select,
+            res => panic!("{:?} is not a SELECT", res),
+        };
+
+        // only the source is loaded, not the whole library
+        assert_eq!(
+            Statement::Select(select.clone()).sources(),
+            vec![DataSource::Playlist("All".to_string())]
+        );
+
+        load_genres(&mut cx, &select.source).unwrap();
+
+        let sent = requests();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].1, "/artists");
+        assert_eq!(sent[0].2, "arctic monkeys,the beatles,someone,else");
+
+        let rows = select.evaluate(select.source_rows(&cx).unwrap()).unwrap();
+        assert_eq!(column(&rows, "name"), strs(&["Help!", "Yesterday"]));
+
+        // the artists already fetched aren't asked for again
+        load_genres(&mut cx, &select.source).unwrap();
+        assert_eq!(requests().len(), 1);
     }
 }
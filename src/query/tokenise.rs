@@ -1,3 +1,6 @@
+use crate::query::data::TimeRange;
+use crate::query::duplicates::DuplicateMatch;
+use crate::query::expression::WindowFunction;
 use crate::query::value::Value;
 use crate::utils::date::{Date, DateSource};
 use regex::Regex;
@@ -13,6 +16,35 @@ pub enum DataSource {
     Playlists, // all playlists
     SavedAlbum(String),
     SavedAlbums, // all saved albums
+    /// Every copy of the tracks that appear more than once in a playlist, or across all playlists if the name is empty
+    Duplicates(String, DuplicateMatch),
+    FollowedArtists, // all artists the user follows
+    Liked,           // the user's Liked Songs
+    TopTracks(TimeRange),
+    TopArtists(TimeRange),
+    RecentlyPlayed,   // the user's recent plays merged into the local play log
+    StreamingHistory, // plays imported from the extended streaming history export
+    Artists,          // every artist of the playlist tracks, saved albums and liked tracks
+}
+
+impl DataSource {
+    /// Whether the rows of the source are tracks rather than playlists or albums
+    pub fn has_tracks(&self) -> bool {
+        matches!(
+            self,
+            DataSource::Playlist(_)
+                | DataSource::SavedAlbum(_)
+                | DataSource::Liked
+                | DataSource::TopTracks(_)
+                | DataSource::RecentlyPlayed
+                | DataSource::Duplicates(..)
+        )
+    }
+
+    /// Whether genres of the source's rows come from loading the artists, rather than being part of the data
+    pub fn needs_genres(&self) -> bool {
+        self.has_tracks() || *self == DataSource::SavedAlbums
+    }
 }
 
 impl Display for DataSource {
@@ -25,6 +57,15 @@ impl Display for DataSource {
                 DataSource::Playlists => "Playlists".to_string(),
                 DataSource::SavedAlbum(res) => format!("SavedAlbum({})", res),
                 DataSource::SavedAlbums => "SavedAlbums".to_string(),
+                DataSource::Duplicates(res, duplicate_match) =>
+                    format!("Duplicates({}, {})", res, duplicate_match),
+                DataSource::FollowedArtists => "FollowedArtists".to_string(),
+                DataSource::Liked => "Liked".to_string(),
+                DataSource::TopTracks(res) => format!("TopTracks({})", res),
+                DataSource::TopArtists(res) => format!("TopArtists({})", res),
+                DataSource::RecentlyPlayed => "RecentlyPlayed".to_string(),
+                DataSource::StreamingHistory => "StreamingHistory".to_string(),
+                DataSource::Artists => "Artists".to_string(),
             }
         )
     }
@@ -56,6 +97,10 @@ pub enum Operator {
     NotEquals,
     In,
     NotIn,
+    /// True if a list contains any of the given values
+    ContainsAny,
+    /// True if a list contains every one of the given values
+    ContainsAll,
     Less,
     LessEqual,
     Greater,
@@ -77,6 +122,8 @@ impl Display for Operator {
                 Operator::Greater => "GreaterThan",
                 Operator::GreaterEqual => "GreaterThanOrEqual",
                 Operator::NotIn => "NotIn",
+                Operator::ContainsAny => "ContainsAny",
+                Operator::ContainsAll => "ContainsAll",
             }
         )
     }
@@ -110,18 +157,55 @@ impl Display for Logical {
     }
 }
 
+/// Keywords are named the way they are written in queries
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, PartialEq)]
 pub enum Token {
     SELECT,
+    CREATE,
+    INSERT,
+    INTO,
+    DELETE,
+    PREVIEW,
+    RESTORE,
+    TO,
+    SHOW,
+    SNAPSHOTS,
+    DEDUPE,
+    KEEP,
+    MATCH,
+    REORDER,
+    APPEND,
     AttributeWildcard,
     COUNT(String),
     AVERAGE(String),
+    SUM(String),
+    UNNEST(String),
+    WindowFunction(WindowFunction),
+    OVER,
+    PARTITION,
+    /// A parenthesised list of tokens, e.g. the window definition after OVER
+    Group(Vec<Token>),
     FROM,
     ORDER,
-    /// Only ever part of the ORDER BY compound keyword
+    GROUP,
+    /// Only ever part of the ORDER BY and GROUP BY compound keywords
     BY,
     OrderDirection(Order),
+    NULLS,
+    FIRST,
+    LAST,
+    AS,
     WHERE,
+    HAVING,
+    QUALIFY,
+    ANY,
+    ALL,
+    CASE,
+    WHEN,
+    THEN,
+    ELSE,
+    END,
     Attribute(String),
     Operator(Operator),
     Logical(Logical),
@@ -136,14 +220,54 @@ impl Display for Token {
             "{}",
             match self {
                 Token::SELECT => "SELECT".to_string(),
+                Token::CREATE => "CREATE".to_string(),
+                Token::INSERT => "INSERT".to_string(),
+                Token::INTO => "INTO".to_string(),
+                Token::DELETE => "DELETE".to_string(),
+                Token::PREVIEW => "PREVIEW".to_string(),
+                Token::RESTORE => "RESTORE".to_string(),
+                Token::TO => "TO".to_string(),
+                Token::SHOW => "SHOW".to_string(),
+                Token::SNAPSHOTS => "SNAPSHOTS".to_string(),
+                Token::DEDUPE => "DEDUPE".to_string(),
+                Token::KEEP => "KEEP".to_string(),
+                Token::MATCH => "MATCH".to_string(),
+                Token::REORDER => "REORDER".to_string(),
+                Token::APPEND => "APPEND".to_string(),
                 Token::AttributeWildcard => "AllAttributes".to_string(),
                 Token::COUNT(res) => format!("COUNT({})", res),
                 Token::AVERAGE(res) => format!("AVERAGE({})", res),
+                Token::SUM(res) => format!("SUM({})", res),
+                Token::UNNEST(res) => format!("UNNEST({})", res),
+                Token::WindowFunction(res) => format!("WindowFunction({})", res),
+                Token::OVER => "OVER".to_string(),
+                Token::PARTITION => "PARTITION".to_string(),
+                Token::Group(res) => format!(
+                    "Group({})",
+                    res.iter()
+                        .map(|x| x.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ),
                 Token::FROM => "FROM".to_string(),
                 Token::ORDER => "ORDER".to_string(),
+                Token::GROUP => "GROUP".to_string(),
                 Token::BY => "BY".to_string(),
                 Token::OrderDirection(res) => format!("{}", res),
+                Token::NULLS => "NULLS".to_string(),
+                Token::FIRST => "FIRST".to_string(),
+                Token::LAST => "LAST".to_string(),
+                Token::AS => "AS".to_string(),
                 Token::WHERE => "WHERE".to_string(),
+                Token::HAVING => "HAVING".to_string(),
+                Token::QUALIFY => "QUALIFY".to_string(),
+                Token::ANY => "ANY".to_string(),
+                Token::ALL => "ALL".to_string(),
+                Token::CASE => "CASE".to_string(),
+                Token::WHEN => "WHEN".to_string(),
+                Token::THEN => "THEN".to_string(),
+                Token::ELSE => "ELSE".to_string(),
+                Token::END => "END".to_string(),
                 Token::Attribute(res) => format!("Attribute({})", res),
                 Token::Operator(res) => format!("Operator({})", res),
                 Token::Logical(res) => format!("Logical({})", res),
@@ -172,6 +296,20 @@ impl RawToken {
     fn build_token(self) -> Result<Token, String> {
         match self.identifier.as_str().to_uppercase().as_str() {
             "SELECT" => return Ok(Token::SELECT),
+            "CREATE" => return Ok(Token::CREATE),
+            "INSERT" => return Ok(Token::INSERT),
+            "INTO" => return Ok(Token::INTO),
+            "DELETE" => return Ok(Token::DELETE),
+            "PREVIEW" => return Ok(Token::PREVIEW),
+            "RESTORE" => return Ok(Token::RESTORE),
+            "TO" => return Ok(Token::TO),
+            "SHOW" => return Ok(Token::SHOW),
+            "SNAPSHOTS" => return Ok(Token::SNAPSHOTS),
+            "DEDUPE" => return Ok(Token::DEDUPE),
+            "KEEP" => return Ok(Token::KEEP),
+            "MATCH" => return Ok(Token::MATCH),
+            "REORDER" => return Ok(Token::REORDER),
+            "APPEND" => return Ok(Token::APPEND),
             "*" => return Ok(Token::AttributeWildcard),
             "COUNT" => {
                 let attr = self.content.unwrap_or("".to_string());
@@ -183,6 +321,28 @@ impl RawToken {
 
                 return Ok(Token::AVERAGE(attr));
             }
+            "SUM" => {
+                let attr = self.content.unwrap_or("".to_string());
+
+                return Ok(Token::SUM(attr));
+            }
+            "UNNEST" => {
+                let attr = self.content.unwrap_or("".to_string());
+
+                return Ok(Token::UNNEST(attr));
+            }
+            // window functions need their parentheses to not be confused with attributes
+            "ROW_NUMBER" if self.content.is_some() => {
+                return Ok(Token::WindowFunction(WindowFunction::RowNumber));
+            }
+            "RANK" if self.content.is_some() => {
+                return Ok(Token::WindowFunction(WindowFunction::Rank));
+            }
+            "DENSE_RANK" if self.content.is_some() => {
+                return Ok(Token::WindowFunction(WindowFunction::DenseRank));
+            }
+            "OVER" => return Ok(Token::OVER),
+            "PARTITION" => return Ok(Token::PARTITION),
             "FROM" => return Ok(Token::FROM),
             "WHERE" => return Ok(Token::WHERE),
             "==" => return Ok(Token::Operator(Operator::Equals)),
@@ -194,10 +354,25 @@ impl RawToken {
             "LIKE" => return Ok(Token::Operator(Operator::Like)),
             "IN" => return Ok(Token::Operator(Operator::In)),
             "NOT" => return Ok(Token::Operator(Operator::NotIn)), // not can only be used before IN so it must be a NotIn operation
+            "CONTAINS" => return Ok(Token::Operator(Operator::ContainsAny)), // the ANY or ALL after CONTAINS decides the operation
+            "ANY" => return Ok(Token::ANY),
+            "ALL" => return Ok(Token::ALL),
             "ORDER" => return Ok(Token::ORDER),
+            "GROUP" => return Ok(Token::GROUP),
+            "HAVING" => return Ok(Token::HAVING),
+            "QUALIFY" => return Ok(Token::QUALIFY),
+            "CASE" => return Ok(Token::CASE),
+            "WHEN" => return Ok(Token::WHEN),
+            "THEN" => return Ok(Token::THEN),
+            "ELSE" => return Ok(Token::ELSE),
+            "END" => return Ok(Token::END),
             "BY" => return Ok(Token::BY),
             "ASC" => return Ok(Token::OrderDirection(Order::Ascending)),
             "DESC" => return Ok(Token::OrderDirection(Order::Descending)),
+            "NULLS" => return Ok(Token::NULLS),
+            "FIRST" => return Ok(Token::FIRST),
+            "LAST" => return Ok(Token::LAST),
+            "AS" => return Ok(Token::AS),
             "AND" => return Ok(Token::Logical(Logical::And)),
             "OR" => return Ok(Token::Logical(Logical::Or)),
             "PLAYLIST" => {
@@ -216,6 +391,34 @@ impl RawToken {
             "ALBUMS" => {
                 return Ok(Token::Source(DataSource::SavedAlbums));
             }
+            "TOP_TRACKS" => {
+                return Ok(Token::Source(DataSource::TopTracks(TimeRange::parse(
+                    &self.content.unwrap_or_default(),
+                )?)));
+            }
+            "TOP_ARTISTS" => {
+                return Ok(Token::Source(DataSource::TopArtists(TimeRange::parse(
+                    &self.content.unwrap_or_default(),
+                )?)));
+            }
+            "LIKED" => {
+                return Ok(Token::Source(DataSource::Liked));
+            }
+            "RECENTLY_PLAYED" => {
+                return Ok(Token::Source(DataSource::RecentlyPlayed));
+            }
+            "STREAMING_HISTORY" => {
+                return Ok(Token::Source(DataSource::StreamingHistory));
+            }
+            "FOLLOWED_ARTISTS" => {
+                return Ok(Token::Source(DataSource::FollowedArtists));
+            }
+            "DUPLICATES" => {
+                return Ok(Token::Source(DataSource::Duplicates(
+                    self.content.unwrap_or("".to_string()),
+                    DuplicateMatch::Id,
+                )));
+            }
             _ => {
                 let int_regex = Regex::new(r"^-?\d+$").map_err(|x| x.to_string())?;
                 let float_regex = Regex::new(r"^-?\d+.\d+$").map_err(|x| x.to_string())?;
@@ -223,8 +426,9 @@ impl RawToken {
                 let date_regex = Regex::new(r"^(\d?\d([-/]))?(\d?\d([-/]))?(\d{2}|\d{4})$")
                     .map_err(|x| x.to_string())?;
                 let str_regex = Regex::new(r"^[\w\s]+$").map_err(|x| x.to_string())?;
+                let quoted_str_regex = Regex::new(r#"^[^"']+$"#).map_err(|x| x.to_string())?;
                 let str_list_regex =
-                    Regex::new(r#"^("\w+",  ?)*("\w+")$"#).map_err(|x| x.to_string())?;
+                    Regex::new(r#"^("[\w\s]+", *)*("[\w\s]+")$"#).map_err(|x| x.to_string())?;
                 let int_list_regex =
                     Regex::new(r#"^(\d+,  ?)*(\d+)$"#).map_err(|x| x.to_string())?;
                 let float_list_regex =
@@ -332,7 +536,8 @@ impl RawToken {
                         return Ok(Token::Value(Value::List(items)));
                     }
 
-                    if str_regex.is_match(cont.as_str()) {
+                    // anything else in quotes is a string, e.g. a file name with dots and slashes
+                    if quoted_str_regex.is_match(cont.as_str()) {
                         return Ok(Token::Value(Value::Str(cont))); // remove the quotes from the string
                     }
                 }
@@ -351,7 +556,7 @@ impl RawToken {
 }
 
 fn split_token(s: &String) -> RawToken {
-    let split_on = HashMap::from([('(', ')'), ('"', '"'), ('[', ']')]);
+    let split_on = HashMap::from([('(', ')'), ('"', '"'), ('\'', '\''), ('[', ']')]);
     let mut end_on: char = '.';
 
     let mut split = false;
@@ -390,7 +595,7 @@ pub fn tokenise(input: String) -> Result<Vec<Token>, String> {
     let mut terminated: bool = false;
 
     let mut end_on = '.';
-    let split_on = HashMap::from([('(', ')'), ('"', '"'), ('[', ']')]);
+    let split_on = HashMap::from([('(', ')'), ('"', '"'), ('\'', '\''), ('[', ']')]);
 
     while let Some(letter) = letters.next() {
         if letter == ';' {
@@ -437,6 +642,29 @@ pub fn tokenise(input: String) -> Result<Vec<Token>, String> {
 
     while let Some(elem) = split_iter.next() {
         let temp = split_token(elem);
+
+        // the parentheses after OVER hold a window definition which is tokenised separately
+        if tokens.last() == Some(&Token::OVER)
+            && temp.identifier.is_empty()
+            && let Some(content) = temp.content
+        {
+            if content.trim().is_empty() {
+                tokens.push(Token::Group(Vec::new()));
+            } else {
+                tokens.push(Token::Group(tokenise(format!("{};", content))?));
+            }
+            continue;
+        }
+
+        // ARTISTS is only the source straight after FROM, anywhere else it is the artists attribute
+        if tokens.last() == Some(&Token::FROM)
+            && temp.content.is_none()
+            && temp.identifier.eq_ignore_ascii_case("ARTISTS")
+        {
+            tokens.push(Token::Source(DataSource::Artists));
+            continue;
+        }
+
         let token: Token = temp.build_token()?;
 
         tokens.push(token);
@@ -12,6 +12,8 @@ pub enum Value {
     Bool(bool),
     Date(Date),
     List(Vec<Value>),
+    /// The absence of a value, e.g. the average of no rows
+    Null,
 }
 
 impl Display for Value {
@@ -30,6 +32,7 @@ impl Display for Value {
                     .map(|x| x.to_string())
                     .collect::<Vec<String>>()
                     .join(","),
+                Value::Null => "NULL".to_string(),
             }
         )
     }
@@ -37,6 +40,18 @@ impl Display for Value {
 
 impl Value {
     pub fn compare(&self, value: Value, operator: Operator) -> Result<bool, String> {
+        if *self == Value::Null || value == Value::Null {
+            // nothing is comparable to a missing value
+            return Ok(false);
+        }
+
+        if let Value::Int(res) = self
+            && let Value::Date(_) = value
+        {
+            // a standalone year on the left hand side e.g. 2020 < release_date
+            return Value::Date(Date::year(*res as u32)?).compare(value, operator);
+        }
+
         if let Value::Date(attr) = self {
             // standalone years get tokenised as ints so need toc convert to date if the attr is a date
             let target: &Date = match &value {
@@ -66,14 +81,34 @@ impl Value {
             Operator::Greater => self.greater_than(value),
             Operator::GreaterEqual => self.greater_than_or_equal(value),
             Operator::NotIn => Ok(!self.in_list(value)?),
+            Operator::ContainsAny => self.contains(value, false),
+            Operator::ContainsAll => self.contains(value, true),
         }
     }
 
     fn equals(&self, value: Value) -> Result<bool, String> {
+        // ints and floats are compared by value as aggregations like AVERAGE always produce floats
+        if let Ok(lhs) = self.extract_numerics()
+            && let Ok(rhs) = value.extract_numerics()
+        {
+            return Ok(lhs == rhs);
+        }
+
         if self == &value { Ok(true) } else { Ok(false) }
     }
 
     fn like(&self, value: Value) -> Result<bool, String> {
+        if let Value::List(res) = self {
+            // a list matches if any of its elements do
+            for item in res {
+                if item.like(value.clone())? {
+                    return Ok(true);
+                }
+            }
+
+            return Ok(false);
+        }
+
         if let Value::Str(first) = self
             && let Value::Str(second) = &value
         {
@@ -114,6 +149,28 @@ impl Value {
         }
     }
 
+    fn contains(&self, value: Value, all: bool) -> Result<bool, String> {
+        let list = match self {
+            Value::List(res) => res,
+            _ => {
+                return Err(
+                    "CONTAINS can only be used on a list attribute, e.g. artists".to_string(),
+                );
+            }
+        };
+
+        let targets = match value {
+            Value::List(res) => res,
+            res => vec![res],
+        };
+
+        if all {
+            Ok(targets.iter().all(|x| list.contains(x)))
+        } else {
+            Ok(targets.iter().any(|x| list.contains(x)))
+        }
+    }
+
     fn extract_numerics(&self) -> Result<f64, ()> {
         match self {
             Value::Int(res) => Ok(*res as f64),
@@ -141,11 +198,7 @@ impl Value {
             "Left and right hand sides of <= operation must be numeric.".to_string()
         })?;
 
-        if lhs < rhs || self.equals(value)? {
-            Ok(true)
-        } else {
-            Ok(false)
-        }
+        if lhs <= rhs { Ok(true) } else { Ok(false) }
     }
 
     fn greater_than(&self, value: Value) -> Result<bool, String> {
@@ -167,10 +220,19 @@ impl Value {
             "Left and right hand sides of >= operation must be numeric.".to_string()
         })?;
 
-        if lhs > rhs || self.equals(value)? {
-            Ok(true)
-        } else {
-            Ok(false)
+        if lhs >= rhs { Ok(true) } else { Ok(false) }
+    }
+
+    /// Convert to JSON keeping the type, dates become ISO 8601 strings and lists become arrays
+    pub fn json(&self) -> serde_json::Value {
+        match self {
+            Value::Str(res) => serde_json::Value::from(res.clone()),
+            Value::Int(res) => serde_json::Value::from(*res),
+            Value::Float(res) => serde_json::Value::from(*res), // NaN and infinity become null
+            Value::Bool(res) => serde_json::Value::from(*res),
+            Value::Date(res) => serde_json::Value::from(res.format()),
+            Value::List(res) => serde_json::Value::Array(res.iter().map(|x| x.json()).collect()),
+            Value::Null => serde_json::Value::Null,
         }
     }
 }
@@ -189,7 +251,7 @@ where
 }
 
 impl PartialOrd for Value {
-    /// If None is returned it is because the provided attribute is not orderable, e.g. list or bool
+    /// If None is returned it is because the provided attribute is not orderable, e.g. bool
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         if discriminant(self) != discriminant(other) {
             return None;
@@ -220,6 +282,21 @@ impl PartialOrd for Value {
                 }
                 None
             }
+            Value::List(res1) => {
+                // lists are compared item by item, e.g. when ordering by artists
+                if let Value::List(res2) = other {
+                    for (item1, item2) in res1.iter().zip(res2) {
+                        match item1.partial_cmp(item2) {
+                            Some(Ordering::Equal) => {}
+                            res => return res,
+                        }
+                    }
+
+                    return Some(res1.len().cmp(&res2.len()));
+                }
+                None
+            }
+            Value::Null => Some(Ordering::Equal),
             _ => None,
         }
     }
@@ -0,0 +1,133 @@
+use crate::app_context::AppContext;
+use crate::cache::{ToCSV, deserialise_cache};
+use crate::query::data::{KeyAccess, PlaylistData};
+use crate::query::value::Value;
+use crate::utils::file::{File, WriteMode, read_file, write_file};
+use crate::utils::utils::secs_now;
+use chrono::DateTime;
+use std::fmt::Display;
+use std::fs::read_dir;
+
+/// A copy of a playlist's tracks, in order, from before it was changed
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    /// Unix time in seconds, used to pick the snapshot to restore
+    pub timestamp: u64,
+    pub playlist: PlaylistData,
+}
+
+impl KeyAccess for Snapshot {
+    fn access<T>(&self, key: T) -> Result<Value, String>
+    where
+        T: AsRef<str> + Display,
+    {
+        match key.as_ref() {
+            "playlist" => Ok(Value::Str(self.playlist.name.clone())),
+            "timestamp" => Ok(Value::Int(self.timestamp.cast_signed())),
+            "taken_at" => Ok(Value::Str(
+                match DateTime::from_timestamp(self.timestamp.cast_signed(), 0) {
+                    Some(res) => res.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    None => String::new(),
+                },
+            )),
+            "track_count" => Ok(Value::Int(self.playlist.tracks.len() as i64)),
+            _ => Err(format!("Unknown attribute {}", key)),
+        }
+    }
+
+    fn attributes() -> Vec<String> {
+        ["playlist", "timestamp", "taken_at", "track_count"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>()
+    }
+}
+
+/// Snapshot file format, the same as a playlist block in the cache
+/// PLAYLIST
+/// <playlist data as csv>
+/// <track 1 data as csv>
+/// ...
+///
+/// Files are named <playlist id>_<timestamp>.txt
+pub fn take_snapshot(cx: &AppContext, playlist: &PlaylistData) -> Result<u64, String> {
+    let timestamp = secs_now();
+    let file = File::Snapshot(format!("{}_{}.txt", playlist.id, timestamp));
+
+    // keep the earliest state if the playlist is changed twice in the same second
+    if file.path(cx)?.exists() {
+        return Ok(timestamp);
+    }
+
+    let mut buf: Vec<String> = vec!["PLAYLIST".to_string(), playlist.csv()];
+    for i in &playlist.tracks {
+        buf.push(i.csv())
+    }
+
+    write_file(cx, file, buf.join("\n"), WriteMode::Overwrite)?;
+
+    Ok(timestamp)
+}
+
+/// Every saved snapshot, oldest first
+pub fn load_snapshots(cx: &AppContext) -> Result<Vec<Snapshot>, String> {
+    let folder = File::snapshot_folder(cx)?;
+
+    if !folder.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut snapshots: Vec<Snapshot> = Vec::new();
+
+    for entry in read_dir(folder).map_err(|x| x.to_string())? {
+        let file_name = entry
+            .map_err(|x| x.to_string())?
+            .file_name()
+            .to_string_lossy()
+            .to_string();
+
+        let Some((_, timestamp)) = file_name
+            .strip_suffix(".txt")
+            .and_then(|x| x.rsplit_once("_"))
+        else {
+            continue;
+        };
+
+        let Ok(timestamp) = timestamp.parse::<u64>() else {
+            continue;
+        };
+
+        let content = read_file(cx, File::Snapshot(file_name.clone()))?;
+        let mut cache = deserialise_cache(content.lines().map(|x| x.to_string()))?;
+
+        match cache.playlists.pop() {
+            Some(playlist) => snapshots.push(Snapshot {
+                timestamp,
+                playlist,
+            }),
+            None => return Err(format!("Snapshot {} has no playlist data.", file_name)),
+        }
+    }
+
+    snapshots.sort_by_key(|x| x.timestamp);
+
+    Ok(snapshots)
+}
+
+/// The latest snapshot of the playlist taken at or before the timestamp
+pub fn find_snapshot(
+    cx: &AppContext,
+    playlist_id: &String,
+    timestamp: u64,
+) -> Result<Snapshot, String> {
+    match load_snapshots(cx)?
+        .into_iter()
+        .rfind(|x| x.playlist.id == *playlist_id && x.timestamp <= timestamp)
+    {
+        Some(res) => Ok(res),
+        None => Err(format!(
+            "No snapshot of the playlist from before {}, use SHOW SNAPSHOTS to list them.",
+            timestamp
+        )),
+    }
+}
@@ -1,5 +1,4 @@
 use crate::app_context::AppContext;
-use crate::query::data::load_data_source;
 use crate::query::parse::parse;
 use crate::query::run::{QueryTracker, TUIQueryStage};
 use crate::query::tokenise::tokenise;
@@ -26,7 +25,7 @@ use std::cmp::PartialEq;
 use std::fmt::{Display, Formatter};
 use std::io;
 use std::io::Write;
-use std::mem::{discriminant, swap};
+use std::mem::swap;
 use std::time::Duration;
 
 #[derive(Clone, PartialEq)]
@@ -284,20 +283,44 @@ impl TUI {
                         query_tracker.stage = TUIQueryStage::NotRunning
                     }
                 },
-                TUIQueryStage::Parsed(statement) => {
-                    match load_data_source(cx, statement.clone().source) {
-                        Ok(_) => {
-                            query_tracker.stage = TUIQueryStage::ParsedWithData(statement);
-                            log_buffer.push(Log::new("Loaded Data", Severity::Success));
-                            log_buffer.push(Log::new("Running Statement", Severity::Log));
-                        }
+                TUIQueryStage::Parsed(statement) => match statement.load_data(cx) {
+                    Ok(_) => {
+                        query_tracker.stage = TUIQueryStage::ParsedWithData(statement);
+                        log_buffer.push(Log::new("Loaded Data", Severity::Success));
+                        log_buffer.push(Log::new("Running Statement", Severity::Log));
+                    }
+                    Err(err) => {
+                        log_buffer.push(Log::new(err, Severity::Error));
+                        query_tracker.stage = TUIQueryStage::NotRunning
+                    }
+                },
+                TUIQueryStage::ParsedWithData(statement) => {
+                    match statement.needs_confirmation(cx) {
+                        Ok(Some(changes)) => match changes.display(cx, Some(self)) {
+                            Ok(_) => {
+                                query_tracker.stage =
+                                    TUIQueryStage::AwaitingConfirmation(statement);
+                                log_buffer.push(Log::new(
+                                    format!(
+                                        "This will change {} tracks, enter y to continue",
+                                        changes.len()
+                                    ),
+                                    Severity::Log,
+                                ));
+                            }
+                            Err(err) => {
+                                log_buffer.push(Log::new(err, Severity::Error));
+                                query_tracker.stage = TUIQueryStage::NotRunning
+                            }
+                        },
+                        Ok(None) => query_tracker.stage = TUIQueryStage::Confirmed(statement),
                         Err(err) => {
                             log_buffer.push(Log::new(err, Severity::Error));
                             query_tracker.stage = TUIQueryStage::NotRunning
                         }
                     }
                 }
-                TUIQueryStage::ParsedWithData(statement) => {
+                TUIQueryStage::Confirmed(statement) => {
                     match statement
                         .clone()
                         .run(cx, Some(self), query_tracker.query_string.clone())
@@ -423,12 +446,24 @@ impl TUI {
                     }
                 }
 
-                if let Some(q) = query
-                    && discriminant(&query_tracker.stage)
-                        == discriminant(&TUIQueryStage::NotRunning)
-                {
-                    query_tracker.stage = TUIQueryStage::Queued(q);
-                    query_tracker.start_time = micro_secs_now();
+                if let Some(q) = query {
+                    match query_tracker.stage.clone() {
+                        TUIQueryStage::NotRunning => {
+                            query_tracker.stage = TUIQueryStage::Queued(q);
+                            query_tracker.start_time = micro_secs_now();
+                        }
+                        // the next query entered is the answer to the confirmation
+                        TUIQueryStage::AwaitingConfirmation(statement) => {
+                            if matches!(q.trim().to_lowercase().as_str(), "y" | "yes") {
+                                query_tracker.stage = TUIQueryStage::Confirmed(statement);
+                                lb.push(Log::new("Running Statement", Severity::Log));
+                            } else {
+                                query_tracker.stage = TUIQueryStage::NotRunning;
+                                lb.push(Log::new("Cancelled", Severity::Log));
+                            }
+                        }
+                        _ => {}
+                    }
                 }
             }
             Event::Mouse(res) => {
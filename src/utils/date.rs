@@ -1,4 +1,4 @@
-use chrono::{Datelike, NaiveDate, TimeZone, Utc};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
 use std::cmp::{Ordering, PartialEq};
 use std::str::FromStr;
 
@@ -6,13 +6,14 @@ fn unix_time(date: &Date) -> i64 {
     let year = date.year as i32;
     let month = date.month.unwrap_or(1) as u32;
     let day = date.day.unwrap_or(1) as u32;
+    let time = date.time.unwrap_or(0) as i64;
 
     let date = NaiveDate::from_ymd_opt(year, month, day).expect("invalid date");
 
     let datetime = date.and_hms_opt(0, 0, 0).unwrap();
     let unix = Utc.from_utc_datetime(&datetime).timestamp();
 
-    unix
+    unix + time
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -20,6 +21,7 @@ pub enum DateScope {
     Day,   // dd/mm/yyyy
     Month, // mm/yyyy
     Year,  // yyyy
+    Time,  // a full timestamp, e.g. when a track was played
 }
 
 impl Default for DateScope {
@@ -34,6 +36,8 @@ pub struct Date {
     year: u32,
     month: Option<u8>,
     day: Option<u8>,
+    /// Seconds after midnight UTC, only set for timestamps
+    time: Option<u32>,
 }
 
 pub enum DateSource {
@@ -159,6 +163,7 @@ impl Date {
             year,
             month,
             day,
+            time: None,
         }
         .validate()?)
     }
@@ -173,12 +178,30 @@ impl Date {
         )
     }
 
+    /// Keep the time as well as the date, from an API timestamp like 2016-12-13T20:44:04.589Z or a formatted one
+    pub fn from_timestamp(timestamp: String) -> Result<Date, String> {
+        let datetime = match DateTime::parse_from_rfc3339(&timestamp) {
+            Ok(res) => res.naive_utc(),
+            Err(_) => NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%dT%H:%M:%S%.f")
+                .map_err(|x| format!("Invalid timestamp {} ({})", timestamp, x))?,
+        };
+
+        Ok(Date {
+            scope: DateScope::Time,
+            year: datetime.year() as u32,
+            month: Some(datetime.month() as u8),
+            day: Some(datetime.day() as u8),
+            time: Some(datetime.num_seconds_from_midnight()),
+        })
+    }
+
     pub fn year(year: u32) -> Result<Date, String> {
         Date {
             scope: DateScope::Year,
             year,
             month: None,
             day: None,
+            time: None,
         }
         .validate()
     }
@@ -243,7 +266,10 @@ impl Date {
         buf += self.year.to_string().as_str();
 
         let date = NaiveDate::parse_from_str(&*buf, "%d/%m/%Y").unwrap();
-        let datetime = date.and_hms_milli_opt(0, 0, 0, 0).unwrap();
+        let time = self.time.unwrap_or(0);
+        let datetime = date
+            .and_hms_milli_opt(time / 3600, time / 60 % 60, time % 60, 0)
+            .unwrap();
         datetime.format("%Y-%m-%dT%H:%M:%S%.3f").to_string()
     }
 }
@@ -269,6 +295,10 @@ impl PartialEq<Self> for Date {
                 }
             }
 
+            if self.time != other.time {
+                eq = false;
+            }
+
             eq
         } else {
             false
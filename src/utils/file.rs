@@ -1,8 +1,12 @@
 use crate::app_context::AppContext;
+use crate::config::args::OutputFormat;
 use crate::query::data::KeyAccess;
+use crate::query::display::data_display::{format_aggregation, format_rows};
+use crate::query::statements::{Aggregation, AggregationResult, OutputFile};
 use crate::utils::utils::iso_str;
+use std::collections::HashMap;
 use std::env::home_dir;
-use std::fs::{OpenOptions, create_dir_all, remove_file};
+use std::fs::{OpenOptions, create_dir_all, metadata, remove_file, rename};
 use std::io::{Read, Write};
 use std::path::PathBuf;
 
@@ -10,11 +14,26 @@ pub enum File {
     Auth,
     Config,
     Cache,
+    /// Every track played, built up from the recently played API
+    PlayLog,
+    /// Plays imported from Spotify's extended streaming history export
+    StreamingHistory,
+    /// The streaming history being imported, replaces StreamingHistory once every file has been read
+    StreamingHistoryImport,
+    /// Playlists, saved albums, followed artists and liked tracks from Spotify's account data export, used offline
+    Library,
+    /// A saved copy of a playlist, stored by file name in the snapshots folder
+    Snapshot(String),
     Other(String),
 }
 
 impl File {
-    fn folder_path() -> Result<PathBuf, String> {
+    /// The folder set on the app context, or the OS's config folder
+    fn folder_path(cx: &AppContext) -> Result<PathBuf, String> {
+        if let Some(res) = &cx.config_dir {
+            return Ok(res.clone());
+        }
+
         let mut home = match home_dir() {
             Some(res) => res,
             None => {
@@ -37,21 +56,40 @@ impl File {
         Ok(home)
     }
 
-    pub fn path(&self) -> Result<PathBuf, String> {
-        let mut folder = File::folder_path()?;
+    pub fn path(&self, cx: &AppContext) -> Result<PathBuf, String> {
+        let mut folder = File::folder_path(cx)?;
 
         match self {
             File::Auth => folder.push("auth.json"),
             File::Config => folder.push("config.json"),
             File::Cache => folder.push("cache.txt"),
+            File::PlayLog => folder.push("play_log.txt"),
+            File::StreamingHistory => folder.push("streaming_history.txt"),
+            File::StreamingHistoryImport => folder.push("streaming_history.txt.tmp"),
+            File::Library => folder.push("library.txt"),
+            File::Snapshot(res) => {
+                folder.push("snapshots");
+                folder.push(res)
+            }
             File::Other(res) => return Ok(PathBuf::from(res)),
         }
 
         Ok(folder)
     }
 
-    fn create_parent() -> Result<(), String> {
-        create_dir_all(File::folder_path()?).map_err(|e| e.to_string())
+    pub fn snapshot_folder(cx: &AppContext) -> Result<PathBuf, String> {
+        let mut folder = File::folder_path(cx)?;
+        folder.push("snapshots");
+
+        Ok(folder)
+    }
+
+    fn create_parent(&self, cx: &AppContext) -> Result<(), String> {
+        if let File::Snapshot(_) = self {
+            return create_dir_all(File::snapshot_folder(cx)?).map_err(|e| e.to_string());
+        }
+
+        create_dir_all(File::folder_path(cx)?).map_err(|e| e.to_string())
     }
 }
 
@@ -61,10 +99,15 @@ pub enum WriteMode {
     Append,
 }
 
-pub fn write_file(file: File, content: String, write_mode: WriteMode) -> Result<(), String> {
-    let path = file.path().map_err(|e| e)?;
+pub fn write_file(
+    cx: &AppContext,
+    file: File,
+    content: String,
+    write_mode: WriteMode,
+) -> Result<(), String> {
+    let path = file.path(cx).map_err(|e| e)?;
 
-    File::create_parent()?; // make sure the parent folders exist
+    file.create_parent(cx)?; // make sure the parent folders exist
 
     let mut file = OpenOptions::new()
         .create(true)
@@ -74,10 +117,9 @@ pub fn write_file(file: File, content: String, write_mode: WriteMode) -> Result<
         } else {
             false
         })
-        .open(path)
-        .map_err(|_| {
-            return "Could not open file in write mode (write, create, truncate).";
-        })?;
+        .append(write_mode == WriteMode::Append)
+        .open(&path)
+        .map_err(|e| format!("Could not open {} to write to it. ({})", path.display(), e))?;
 
     file.write_all(content.as_bytes())
         .map_err(|e| e.to_string())?;
@@ -85,8 +127,8 @@ pub fn write_file(file: File, content: String, write_mode: WriteMode) -> Result<
     Ok(())
 }
 
-pub fn read_file(file: File) -> Result<String, String> {
-    let path = file.path().map_err(|e| e)?;
+pub fn read_file(cx: &AppContext, file: File) -> Result<String, String> {
+    let path = file.path(cx).map_err(|e| e)?;
 
     let mut file = OpenOptions::new().read(true).open(path).map_err(|_| {
         return "Could not open file in read mode (read).";
@@ -98,8 +140,8 @@ pub fn read_file(file: File) -> Result<String, String> {
     Ok(content)
 }
 
-pub fn delete_file(file: File) -> Result<(), String> {
-    let path = file.path().map_err(|e| e)?;
+pub fn delete_file(cx: &AppContext, file: File) -> Result<(), String> {
+    let path = file.path(cx).map_err(|e| e)?;
 
     if path.exists() {
         remove_file(path).map_err(|x| x.to_string())?
@@ -108,44 +150,290 @@ pub fn delete_file(file: File) -> Result<(), String> {
     Ok(())
 }
 
-fn csv<T>(data: T) -> String
+pub fn rename_file(cx: &AppContext, from: File, to: File) -> Result<(), String> {
+    let from_path = from.path(cx)?;
+    let to_path = to.path(cx)?;
+
+    rename(&from_path, &to_path).map_err(|x| {
+        format!(
+            "Could not move {} to {}. ({})",
+            from_path.display(),
+            to_path.display(),
+            x
+        )
+    })
+}
+
+/// Saved files use the format for their extension, or the --format format when the extension isn't known, with tables saved as CSV
+pub fn file_format(cx: &AppContext, file: &OutputFile) -> OutputFormat {
+    if let Some(res) = OutputFormat::from_file_name(&file.path) {
+        return res;
+    }
+
+    match cx.output_format {
+        OutputFormat::Table => OutputFormat::Csv,
+        _ => cx.output_format.clone(),
+    }
+}
+
+/// Whether the results are added after what's already in the file, a JSON array, HTML page or XSPF playlist can't be added to
+fn appending(file: &OutputFile, format: &OutputFormat) -> Result<bool, String> {
+    if !file.append {
+        return Ok(false);
+    }
+
+    let has_content = match metadata(&file.path) {
+        Ok(res) => res.len() > 0,
+        Err(_) => false,
+    };
+
+    if has_content
+        && matches!(
+            format,
+            OutputFormat::Json | OutputFormat::Html | OutputFormat::Xspf
+        )
+    {
+        return Err(format!(
+            "Cannot APPEND to {}, only CSV, TSV, NDJSON, Markdown and M3U8 files can be added to.",
+            file.path
+        ));
+    }
+
+    Ok(has_content)
+}
+
+/// Write the selected attributes of each row to the file
+pub fn write_result<T>(
+    cx: &AppContext,
+    file: &OutputFile,
+    valid: Vec<T>,
+    attributes: Vec<String>,
+    query: String,
+) -> Result<(), String>
 where
     T: KeyAccess,
 {
-    let mut res: Vec<String> = Vec::new();
-    for attr in T::attributes() {
-        res.push(data.access(attr).unwrap().to_string()) // attr is guaranteed to be an attribute because of where it comes from so this unwrap is safe
-    }
+    let format = file_format(cx, file);
+    let appending = appending(file, &format)?;
+    // the header is already at the top of the file when adding to it
+    let lines = format_rows(valid, attributes, &format, cx.output_header && !appending)?;
+
+    write_lines(cx, file, lines, &format, appending, query)
+}
+
+/// Write a single aggregation over all the rows to the file
+pub fn write_aggregation_result(
+    cx: &AppContext,
+    file: &OutputFile,
+    aggregation: Aggregation,
+    data: HashMap<String, AggregationResult>,
+    attributes: Vec<String>,
+    query: String,
+) -> Result<(), String> {
+    let format = file_format(cx, file);
+    let appending = appending(file, &format)?;
+    let lines = format_aggregation(
+        aggregation,
+        data,
+        attributes,
+        &format,
+        cx.output_header && !appending,
+    )?;
 
-    res.join(",")
+    write_lines(cx, file, lines, &format, appending, query)
 }
 
 /// Output format
 ///
-/// query \n
-/// iso time \n
-/// line1 \n
-/// line2 \n
+/// # query \r\n (only with --metadata)
+/// # iso time \r\n
+/// header \r\n
+/// line1 \r\n
+/// line2 \r\n
 /// etc
-pub fn write_result<T>(cx: &AppContext, valid: Vec<T>, query: String) -> Result<(), String>
-where
-    T: KeyAccess,
-{
-    if cx.save_file.is_none() {
-        return Err("No save file specified".to_string());
-    }
+///
+/// CSV lines end with \r\n as in RFC 4180, other formats with \n, and only CSV and TSV can have the metadata lines
+fn write_lines(
+    cx: &AppContext,
+    file: &OutputFile,
+    lines: Vec<String>,
+    format: &OutputFormat,
+    appending: bool,
+    query: String,
+) -> Result<(), String> {
     let mut output: Vec<String> = Vec::new();
 
-    output.push(query);
-    output.push(iso_str());
+    if appending && *format == OutputFormat::Markdown {
+        output.push(String::new()); // a blank line between the tables
+    }
+
+    if cx.output_metadata && matches!(format, OutputFormat::Csv | OutputFormat::Tsv) {
+        output.push(format!("# {}", query.replace(['\r', '\n'], " ")));
+        output.push(format!("# {}", iso_str()));
+    }
+
+    output.extend(lines);
 
-    for i in valid {
-        output.push(csv(i));
+    let line_ending = if *format == OutputFormat::Csv {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    let mut text = output.join(line_ending);
+    if !text.is_empty() {
+        text += line_ending;
     }
 
     write_file(
-        File::Other(cx.save_file.clone().unwrap()),
-        output.join("\n"),
-        WriteMode::Overwrite,
+        cx,
+        File::Other(file.path.clone()),
+        text,
+        if file.append {
+            WriteMode::Append
+        } else {
+            WriteMode::Overwrite
+        },
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::data::TrackData;
+    use std::fs::read_to_string;
+
+    /// Nothing is written to the user's config folder
+    fn context() -> AppContext {
+        AppContext {
+            config_dir: Some(std::env::temp_dir()),
+            ..Default::default()
+        }
+    }
+
+    /// A file in the temp folder that doesn't exist yet
+    fn temp_file(name: &str) -> OutputFile {
+        let path = std::env::temp_dir().join(format!("spotifyQL-{}-{}", std::process::id(), name));
+        let _ = remove_file(&path);
+
+        OutputFile {
+            path: path.to_string_lossy().to_string(),
+            append: false,
+        }
+    }
+
+    fn tracks() -> Vec<TrackData> {
+        vec![TrackData {
+            id: "1".to_string(),
+            name: "Help!".to_string(),
+            ..Default::default()
+        }]
+    }
+
+    fn write(cx: &AppContext, file: &OutputFile) -> String {
+        write_result(
+            cx,
+            file,
+            tracks(),
+            vec!["name".to_string()],
+            "SELECT name\nFROM LIKED;".to_string(),
+        )
+        .unwrap();
+
+        let content = read_to_string(&file.path).unwrap();
+        let _ = remove_file(&file.path);
+
+        content
+    }
+
+    #[test]
+    fn format_comes_from_the_extension() {
+        let mut cx = context();
+        let file = |path: &str| OutputFile {
+            path: path.to_string(),
+            append: false,
+        };
+
+        assert_eq!(file_format(&cx, &file("out.TSV")), OutputFormat::Tsv);
+        assert_eq!(file_format(&cx, &file("out.jsonl")), OutputFormat::Ndjson);
+        assert_eq!(
+            file_format(&cx, &file("dir.v2/out.xspf")),
+            OutputFormat::Xspf
+        );
+        // tables are saved as CSV
+        assert_eq!(file_format(&cx, &file("out.txt")), OutputFormat::Csv);
+
+        cx.output_format = OutputFormat::Markdown;
+        assert_eq!(file_format(&cx, &file("out")), OutputFormat::Markdown);
+        assert_eq!(file_format(&cx, &file("out.html")), OutputFormat::Html);
+    }
+
+    #[test]
+    fn append_needs_a_format_that_can_be_added_to() {
+        for (name, format) in [
+            ("append.json", OutputFormat::Json),
+            ("append.html", OutputFormat::Html),
+            ("append.xspf", OutputFormat::Xspf),
+        ] {
+            let mut file = temp_file(name);
+            file.append = true;
+
+            // an empty or missing file is written as usual
+            assert_eq!(appending(&file, &format), Ok(false));
+
+            write_file(
+                &context(),
+                File::Other(file.path.clone()),
+                "x".to_string(),
+                WriteMode::Overwrite,
+            )
+            .unwrap();
+            assert!(appending(&file, &format).is_err(), "{}", name);
+            let _ = remove_file(&file.path);
+        }
+    }
+
+    #[test]
+    fn append_leaves_out_the_header() {
+        let mut file = temp_file("append.csv");
+        file.append = true;
+
+        let cx = context();
+        for _ in 0..2 {
+            write_result(
+                &cx,
+                &file,
+                tracks(),
+                vec!["name".to_string()],
+                String::new(),
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            read_to_string(&file.path).unwrap(),
+            "name\r\nHelp!\r\nHelp!\r\n"
+        );
+        let _ = remove_file(&file.path);
+    }
+
+    #[test]
+    fn metadata_is_opt_in() {
+        let file = temp_file("plain.csv");
+        assert_eq!(write(&context(), &file), "name\r\nHelp!\r\n");
+
+        let cx = AppContext {
+            output_metadata: true,
+            ..context()
+        };
+        let file = temp_file("metadata.csv");
+        let lines: Vec<String> = write(&cx, &file)
+            .split("\r\n")
+            .map(|x| x.to_string())
+            .collect();
+
+        assert_eq!(lines[0], "# SELECT name FROM LIKED;");
+        assert!(lines[1].starts_with("# "));
+        assert_eq!(lines[2..], ["name", "Help!", ""]);
+    }
+}
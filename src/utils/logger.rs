@@ -1,9 +1,44 @@
+use std::io::{IsTerminal, Write, stderr};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set when running a one-shot query, hides info and success messages so only results, warnings and errors are printed
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed)
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Logs go to stderr so they don't mix with query results on stdout.
+/// Colours and overwriting the previous line are only used when stderr is a terminal,
+/// otherwise every message is written on its own line
+pub fn write_log(colour: &str, tag: &str, message: String, new_line: bool) {
+    let mut err = stderr();
+
+    let _ = if err.is_terminal() {
+        write!(err, "\x1b[2K\r{}{}\x1b[0m {}", colour, tag, message)
+    } else {
+        write!(err, "{} {}", tag, message)
+    };
+
+    if new_line || !err.is_terminal() {
+        let _ = writeln!(err);
+    }
+
+    let _ = err.flush();
+}
+
 macro_rules! success {
     () => {
         $crate::print!("\n")
     };
     ($($arg:tt)*) => {{
-        println!("\x1b[2K\r\x1b[32m[SUCCESS]\x1b[0m {}", format!($($arg)*));
+        if !$crate::utils::logger::is_quiet() {
+            $crate::utils::logger::write_log("\x1b[32m", "[SUCCESS]", format!($($arg)*), true);
+        }
     }};
 }
 
@@ -12,8 +47,10 @@ macro_rules! info {
         $crate::print!("\n")
     };
     ($($arg:tt)*) => {{
-        const INFO_NAME: &str = "spotifyQL";
-        println!("\x1b[2K\r\x1b[34m[{}]\x1b[0m {}", INFO_NAME, format!($($arg)*));
+        const INFO_NAME: &str = "[spotifyQL]";
+        if !$crate::utils::logger::is_quiet() {
+            $crate::utils::logger::write_log("\x1b[34m", INFO_NAME, format!($($arg)*), true);
+        }
     }};
 }
 
@@ -22,7 +59,7 @@ macro_rules! warning {
         $crate::print!("\n")
     };
     ($($arg:tt)*) => {{
-        println!("\x1b[2K\r\x1b[33m[WARNING]\x1b[0m {}", format!($($arg)*));
+        $crate::utils::logger::write_log("\x1b[33m", "[WARNING]", format!($($arg)*), true);
     }};
 }
 
@@ -31,7 +68,7 @@ macro_rules! error {
         $crate::print!("\n")
     };
     ($($arg:tt)*) => {{
-        println!("\x1b[2K\r\x1b[31m\x1b[1m[ERROR]\x1b[0m {}", format!($($arg)*));
+        $crate::utils::logger::write_log("\x1b[31m\x1b[1m", "[ERROR]", format!($($arg)*), true);
     }};
 }
 
@@ -40,7 +77,7 @@ macro_rules! fatal {
         $crate::print!("\n")
     };
     ($($arg:tt)*) => {{
-        println!("\x1b[2K\r\x1b[31m\x1b[1m[FATAL] {}\x1b[0m ", format!($($arg)*));
+        $crate::utils::logger::write_log("\x1b[31m\x1b[1m", "[FATAL]", format!($($arg)*), true);
     }};
 }
 
@@ -58,8 +95,10 @@ macro_rules! info_nnl {
         $crate::print!("\n")
     };
     ($($arg:tt)*) => {{
-        const INFO_NAME: &str = "spotifyQL";
-        print!("\x1b[34m[{}]\x1b[0m {}", INFO_NAME, format!($($arg)*));
+        const INFO_NAME: &str = "[spotifyQL]";
+        if !$crate::utils::logger::is_quiet() {
+            $crate::utils::logger::write_log("\x1b[34m", INFO_NAME, format!($($arg)*), false);
+        }
     }};
 }
 
@@ -1,22 +1,56 @@
 use crate::query::data::KeyAccess;
+use crate::query::statements::OrderKey;
+use crate::query::tokenise::Order;
+use crate::query::value::Value;
+use std::cmp::Ordering;
 
-fn bigger<T>(v1: &T, v2: &T, attributes: &Vec<String>) -> Result<bool, String>
+/// Compare two items key by key, the first key that doesn't consider them equal decides the order
+pub fn compare<T>(v1: &T, v2: &T, keys: &Vec<OrderKey>) -> Result<Ordering, String>
 where
-    T: KeyAccess + Clone,
+    T: KeyAccess,
 {
-    for i in attributes {
-        if v1.access(i)? > v2.access(i)? {
-            return Ok(true);
-        } else if v1.access(i)? < v2.access(i)? {
-            return Ok(false);
+    for key in keys {
+        let val1 = key.expression.evaluate(v1)?;
+        let val2 = key.expression.evaluate(v2)?;
+
+        // nulls are placed by NULLS FIRST/LAST regardless of the direction
+        let ordering = match (val1 == Value::Null, val2 == Value::Null) {
+            (true, true) => Ordering::Equal,
+            (true, false) => {
+                if key.nulls_first {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                }
+            }
+            (false, true) => {
+                if key.nulls_first {
+                    Ordering::Greater
+                } else {
+                    Ordering::Less
+                }
+            }
+            (false, false) => {
+                let ordering = val1.partial_cmp(&val2).unwrap_or(Ordering::Equal);
+
+                if key.order == Order::Descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            }
+        };
+
+        if ordering != Ordering::Equal {
+            return Ok(ordering);
         }
     }
 
-    Ok(false)
+    Ok(Ordering::Equal)
 }
 
-/// Mergesort for an array of any struct implementing KeyAccess
-pub fn mergesort<T>(valid: &Vec<T>, attributes: &Vec<String>) -> Result<Vec<T>, String>
+/// Stable mergesort for an array of any struct implementing KeyAccess
+pub fn mergesort<T>(valid: &Vec<T>, keys: &Vec<OrderKey>) -> Result<Vec<T>, String>
 where
     T: KeyAccess + Clone + Default,
 {
@@ -31,20 +65,21 @@ where
 
     let split = valid.len() / 2;
 
-    let rhs = mergesort(&data.split_off(split), attributes)?;
-    let lhs = mergesort(&data, attributes)?;
+    let rhs = mergesort(&data.split_off(split), keys)?;
+    let lhs = mergesort(&data, keys)?;
 
     let mut i: usize = 0;
     let mut j: usize = 0;
     let mut k: usize = 0;
 
     while i < rhs.len() && j < lhs.len() {
-        if bigger(&rhs[i], &lhs[j], attributes)? {
-            res[k] = lhs[j].clone();
-            j += 1;
-        } else {
+        // only take from the right half when it is strictly smaller so equal items keep their order
+        if compare(&rhs[i], &lhs[j], keys)? == Ordering::Less {
             res[k] = rhs[i].clone();
             i += 1;
+        } else {
+            res[k] = lhs[j].clone();
+            j += 1;
         }
         k += 1
     }
@@ -63,3 +98,71 @@ where
 
     Ok(res)
 }
+
+/// Which items are part of the longest run of items already in increasing order, indexed by item
+fn longest_increasing(order: &[usize]) -> Vec<bool> {
+    // tails[n] is the index of the smallest item that ends an increasing run of length n + 1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; order.len()];
+
+    for (i, item) in order.iter().enumerate() {
+        let length = tails.partition_point(|x| order[*x] < *item);
+
+        if length > 0 {
+            previous[i] = Some(tails[length - 1]);
+        }
+
+        if length == tails.len() {
+            tails.push(i)
+        } else {
+            tails[length] = i
+        }
+    }
+
+    let mut in_order = vec![false; order.len()];
+    let mut next = tails.last().copied();
+
+    while let Some(i) = next {
+        in_order[order[i]] = true;
+        next = previous[i];
+    }
+
+    in_order
+}
+
+/// The fewest single item moves that rearrange a list into a new order, given as the current positions in their new order
+///
+/// Each move is (from, insert_before), both positions in the list as it is just before that move
+pub fn moves(order: &[usize]) -> Vec<(usize, usize)> {
+    let in_order = longest_increasing(order);
+
+    let mut current: Vec<usize> = (0..order.len()).collect();
+    let mut res: Vec<(usize, usize)> = Vec::new();
+
+    // every other item is moved to just after the item that comes before it in the new order
+    for (i, item) in order.iter().enumerate() {
+        if in_order[*item] {
+            continue;
+        }
+
+        let from = current.iter().position(|x| x == item).unwrap(); // order holds every position so the item is in current
+        let insert_before = match i {
+            0 => 0,
+            _ => current.iter().position(|x| *x == order[i - 1]).unwrap() + 1,
+        };
+
+        current.remove(from);
+        current.insert(
+            if insert_before > from {
+                insert_before - 1
+            } else {
+                insert_before
+            },
+            *item,
+        );
+
+        res.push((from, insert_before));
+    }
+
+    res
+}